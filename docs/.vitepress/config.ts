@@ -38,11 +38,19 @@ export default defineConfig({
             { text: 'remove', link: '/commands/remove' },
             { text: 'sync', link: '/commands/sync' },
             { text: 'pull', link: '/commands/pull' },
+            { text: 'resolve', link: '/commands/resolve' },
+            { text: 'share', link: '/commands/share' },
             { text: 'restore', link: '/commands/restore' },
+            { text: 'snapshot', link: '/commands/snapshot' },
             { text: 'status', link: '/commands/status' },
             { text: 'category', link: '/commands/category' },
+            { text: 'key', link: '/commands/key' },
+            { text: 'host', link: '/commands/host' },
+            { text: 'rekey', link: '/commands/rekey' },
             { text: 'info', link: '/commands/info' },
-            { text: 'diff', link: '/commands/diff' }
+            { text: 'diff', link: '/commands/diff' },
+            { text: 'log', link: '/commands/log' },
+            { text: 'which', link: '/commands/which' }
           ]
         }
       ],
@@ -1,6 +1,8 @@
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 
+use crate::core::HostStrategy;
+
 #[derive(Parser)]
 #[command(name = "confect")]
 #[command(author, version, about = "Manage system configuration files with Git")]
@@ -37,6 +39,28 @@ pub enum Commands {
         /// Hostname for this machine (default: auto-detect)
         #[arg(long)]
         host: Option<String>,
+
+        /// Keep hosts apart with a branch or a directory each
+        /// (branch|directory, default: hosts.strategy in the global config)
+        #[arg(long, value_name = "STRATEGY")]
+        strategy: Option<HostStrategy>,
+
+        /// Start this host from another host's files when cloning the remote
+        #[arg(
+            long,
+            value_name = "NAME",
+            requires = "remote",
+            conflicts_with = "from"
+        )]
+        from_host: Option<String>,
+
+        /// Start this host from a branch when cloning the remote (default: main)
+        #[arg(long, value_name = "BRANCH", requires = "remote")]
+        from: Option<String>,
+
+        /// List the files a restore would write after cloning the remote
+        #[arg(long, requires = "remote")]
+        dry_run_restore: bool,
     },
 
     /// Add a file or directory to be tracked
@@ -99,7 +123,7 @@ pub enum Commands {
         category: Option<String>,
 
         /// Specific file to restore
-        #[arg(short, long)]
+        #[arg(long)]
         file: Option<PathBuf>,
 
         /// Show what would be done without making changes
@@ -113,12 +137,39 @@ pub enum Commands {
         /// Create backup of existing files before restoring
         #[arg(short, long)]
         backup: bool,
+
+        /// Restore files as they were at a revision, tag or date
+        /// (e.g. HEAD~3, a snapshot tag, 2026-10-01 or "2026-10-01 14:30")
+        #[arg(long, value_name = "REV|DATE")]
+        at: Option<String>,
     },
 
     /// Manage categories
     #[command(subcommand)]
     Category(CategoryCommands),
 
+    /// Manage the age encryption key
+    #[command(subcommand)]
+    Key(KeyCommands),
+
+    /// Manage hosts and their branches
+    #[command(subcommand)]
+    Host(HostCommands),
+
+    /// Take, compare and restore named snapshots of this host
+    #[command(subcommand)]
+    Snapshot(SnapshotCommands),
+
+    /// Re-encrypt stored files to the current recipients
+    Rekey {
+        /// Category to rekey (default: all)
+        category: Option<String>,
+
+        /// Commit message
+        #[arg(short, long)]
+        message: Option<String>,
+    },
+
     /// Show repository information
     Info,
 
@@ -138,6 +189,28 @@ pub enum Commands {
         /// Also restore files after pulling
         #[arg(short, long)]
         restore: bool,
+
+        /// Merge the updated main branch into this host's branch
+        #[arg(long)]
+        shared: bool,
+
+        /// Rebase local commits onto the remote instead of merging
+        /// (default: global.pull in the config)
+        #[arg(long)]
+        rebase: bool,
+    },
+
+    /// Resolve the conflicts a pull left behind, one file at a time
+    Resolve,
+
+    /// Move this host's version of a file into main, shared by all hosts
+    Share {
+        /// Path to the file or directory
+        path: PathBuf,
+
+        /// Don't push to remote
+        #[arg(long)]
+        no_push: bool,
     },
 
     /// Show diff between system files and repository
@@ -148,6 +221,38 @@ pub enum Commands {
         /// Specific file to diff
         #[arg(short, long)]
         file: Option<PathBuf>,
+
+        /// Compare this system with another host's branch
+        #[arg(long, conflicts_with = "between")]
+        host: Option<String>,
+
+        /// Compare the branches of two hosts
+        #[arg(long, num_args = 2, value_names = ["HOST_A", "HOST_B"])]
+        between: Option<Vec<String>>,
+    },
+
+    /// Show the history of tracked files
+    Log {
+        /// Tracked file or directory to show the history of
+        path: Option<PathBuf>,
+
+        /// Only show commits changing this category
+        #[arg(short, long, conflicts_with = "path")]
+        category: Option<String>,
+
+        /// Show the changes of each commit as a patch
+        #[arg(short, long)]
+        patch: bool,
+
+        /// Show at most this many commits
+        #[arg(short = 'n', long)]
+        max_count: Option<usize>,
+    },
+
+    /// Show which layer supplies the effective version of a file
+    Which {
+        /// Path to the file
+        path: PathBuf,
     },
 
     /// Update confect to the latest version
@@ -224,3 +329,138 @@ pub enum CategoryCommands {
         path: String,
     },
 }
+
+#[derive(Subcommand)]
+pub enum HostCommands {
+    /// List hosts with the last commit on their branch
+    List,
+
+    /// Show details of a host
+    Show {
+        /// Host name (default: current host)
+        name: Option<String>,
+    },
+
+    /// Check out another host's branch and use it on this machine
+    Switch {
+        /// Host name
+        name: String,
+    },
+
+    /// Rename a host and its branch
+    Rename {
+        /// Current host name
+        old: String,
+
+        /// New host name
+        new: String,
+    },
+
+    /// Remove a host and delete its local branch
+    Remove {
+        /// Host name
+        name: String,
+
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SnapshotCommands {
+    /// Sync, then tag the host's branch as snapshot/<host>/<name>
+    Create {
+        /// Snapshot name
+        name: String,
+
+        /// Describe the snapshot in the tag message
+        #[arg(short, long)]
+        message: Option<String>,
+
+        /// Don't push the branch and tag to the remote
+        #[arg(long)]
+        no_push: bool,
+    },
+
+    /// List this host's snapshots with their date and file count
+    List,
+
+    /// Show differences between a snapshot and the system
+    Diff {
+        /// Snapshot name
+        name: String,
+
+        /// Category to compare (default: all)
+        #[arg(short, long)]
+        category: Option<String>,
+
+        /// Specific file to compare
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+    },
+
+    /// Restore files as a snapshot stored them
+    Restore {
+        /// Snapshot name
+        name: String,
+
+        /// Category to restore (default: all)
+        #[arg(short, long)]
+        category: Option<String>,
+
+        /// Specific file to restore
+        #[arg(long)]
+        file: Option<PathBuf>,
+
+        /// Show what would be done without making changes
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        force: bool,
+
+        /// Create backup of existing files before restoring
+        #[arg(short, long)]
+        backup: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum KeyCommands {
+    /// Generate a new age identity and enable encryption
+    Generate {
+        /// Replace an existing identity
+        #[arg(short, long)]
+        force: bool,
+
+        /// Protect the stored identity with a passphrase
+        #[arg(short, long)]
+        passphrase: bool,
+    },
+
+    /// Show the public key of the current identity
+    Show,
+
+    /// Import an identity from a key file or passphrase-protected backup
+    Import {
+        /// Path to the key file or backup
+        path: PathBuf,
+
+        /// Replace an existing identity
+        #[arg(short, long)]
+        force: bool,
+
+        /// Protect the stored identity with a passphrase
+        #[arg(short, long)]
+        passphrase: bool,
+    },
+
+    /// Export a passphrase-protected backup of the identity
+    Export {
+        /// Write the backup to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
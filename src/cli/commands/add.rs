@@ -1,9 +1,10 @@
-use console::style;
+use console::{style, Term};
+use dialoguer::Select;
 use std::path::PathBuf;
 
 use crate::core::{CategoryManager, Repository};
 use crate::error::{ConfectError, Result};
-use crate::fs::FileTracker;
+use crate::fs::{FileTracker, SecretFinding};
 
 pub fn run_add(
     path: PathBuf,
@@ -51,15 +52,23 @@ pub fn run_add(
         }
     };
 
+    // Check unencrypted files for secrets before they enter the repository
+    let tracker = FileTracker::new(&repo)?;
+    let flagged = tracker.scan(&path, &category_name, encrypt)?;
+    if !flagged.is_empty() {
+        report_secrets(&flagged);
+        resolve_secrets(&mut categories, &category_name, &flagged)?;
+    }
+
     // Track the file(s)
-    let tracker = FileTracker::new(&repo);
     let added_files = tracker.add(&path, &category_name, encrypt)?;
 
-    // Update categories if needed
-    if !categories.contains_path(&category_name, &path) {
+    // Update categories if needed (also records --encrypt for already covered paths)
+    if encrypt || !categories.contains_path(&category_name, &path) {
         categories.add_path(&category_name, path.to_string_lossy().to_string(), encrypt)?;
     }
     categories.save()?;
+    let category = categories.get(&category_name)?;
 
     // Report results
     println!();
@@ -70,7 +79,11 @@ pub fn run_add(
         style(&category_name).cyan()
     );
     for file in &added_files {
-        let encrypted_mark = if encrypt { " (encrypted)" } else { "" };
+        let encrypted_mark = if category.should_encrypt(file) {
+            " (encrypted)"
+        } else {
+            ""
+        };
         println!(
             "  {} {}{}",
             style("+").green(),
@@ -106,3 +119,65 @@ fn validate_path(path: &PathBuf) -> Result<()> {
 
     Ok(())
 }
+
+fn report_secrets(flagged: &[(PathBuf, Vec<SecretFinding>)]) {
+    eprintln!(
+        "{} Possible secrets in files that would be stored unencrypted:",
+        style("!").yellow().bold()
+    );
+    for (file, findings) in flagged {
+        for finding in findings {
+            eprintln!(
+                "  {} {}:{} ({})",
+                style("✗").red(),
+                file.display(),
+                finding.line,
+                finding.rule
+            );
+        }
+    }
+    eprintln!();
+}
+
+/// Encrypt or allow flagged files, as chosen by the user
+///
+/// Non-interactive runs refuse to add the files.
+fn resolve_secrets(
+    categories: &mut CategoryManager,
+    category_name: &str,
+    flagged: &[(PathBuf, Vec<SecretFinding>)],
+) -> Result<()> {
+    if !Term::stderr().is_term() {
+        return Err(ConfectError::SecretsDetected(flagged.len()));
+    }
+
+    let choice = Select::new()
+        .with_prompt("How should these files be stored?")
+        .items(&[
+            "Encrypt them",
+            "Store them unencrypted (add to allow_secrets)",
+            "Abort",
+        ])
+        .default(0)
+        .interact()?;
+
+    let cat = match choice {
+        0 | 1 => categories.get_mut(category_name)?,
+        _ => return Err(ConfectError::SecretsDetected(flagged.len())),
+    };
+
+    for (file, _) in flagged {
+        let pattern = file.to_string_lossy().to_string();
+        let list = if choice == 0 {
+            &mut cat.encrypt
+        } else {
+            &mut cat.allow_secrets
+        };
+        if !list.contains(&pattern) {
+            list.push(pattern);
+        }
+    }
+
+    // The tracker reads the categories from disk
+    categories.save()
+}
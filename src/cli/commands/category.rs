@@ -1,5 +1,6 @@
 use console::style;
 use dialoguer::Confirm;
+use std::path::PathBuf;
 
 use crate::cli::CategoryCommands;
 use crate::core::{Category, CategoryManager, Repository};
@@ -105,6 +106,25 @@ fn show_category(categories: &CategoryManager, name: &str) -> Result<()> {
         }
     }
 
+    if cat.passphrase {
+        println!();
+        println!("{} passphrase", style("Encryption:").bold());
+    } else if !cat.recipients.is_empty() {
+        println!();
+        println!("{}", style("Recipients:").bold());
+        for recipient in &cat.recipients {
+            println!("  {}", recipient);
+        }
+    }
+
+    if cat.hide_paths {
+        println!();
+        println!(
+            "{} encrypted files are stored under opaque IDs",
+            style("Hidden paths:").bold()
+        );
+    }
+
     if !cat.exclude.is_empty() {
         println!();
         println!("{}", style("Excluded patterns:").bold());
@@ -131,6 +151,13 @@ fn create_category(
         paths,
         encrypt,
         exclude: Vec::new(),
+        recipients: Vec::new(),
+        passphrase: false,
+        hide_paths: false,
+        allow_secrets: Vec::new(),
+        placeholders: Vec::new(),
+        policy: categories.policy().clone(),
+        root: PathBuf::new(),
     };
 
     categories.add(cat)?;
@@ -1,18 +1,46 @@
 use console::style;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use crate::core::{CategoryManager, Repository};
 use crate::error::Result;
 use crate::fs::FileTracker;
 
-pub fn run_diff(category: Option<String>, file: Option<PathBuf>) -> Result<()> {
+pub fn run_diff(
+    category: Option<String>,
+    file: Option<PathBuf>,
+    host: Option<String>,
+    between: Option<Vec<String>>,
+) -> Result<()> {
     let repo = Repository::open_default()?;
+    let tracker = FileTracker::new(&repo)?;
+    let file = file.map(|path| path.canonicalize().unwrap_or(path));
+
+    // Compare host branches instead of this system with its own copies
+    let host_diffs = match (&host, &between) {
+        (Some(host), _) => Some(tracker.diff_host(host, category.as_deref(), file.as_deref())?),
+        (None, Some(hosts)) => Some(tracker.diff_between(
+            &hosts[0],
+            &hosts[1],
+            category.as_deref(),
+            file.as_deref(),
+        )?),
+        (None, None) => None,
+    };
+    if let Some(diffs) = host_diffs {
+        for (path, diff) in &diffs {
+            print_diff(path, diff);
+        }
+        if diffs.is_empty() {
+            println!("No differences found.");
+        }
+        return Ok(());
+    }
+
     let categories = CategoryManager::load(&repo)?;
-    let tracker = FileTracker::new(&repo);
 
     // Determine what to diff
     let files_to_diff = if let Some(path) = file {
-        vec![path.canonicalize().unwrap_or(path)]
+        vec![path]
     } else if let Some(cat_name) = &category {
         let cat = categories.get(cat_name)?;
         tracker.list_files_in_category(&cat.name)?
@@ -31,21 +59,7 @@ pub fn run_diff(category: Option<String>, file: Option<PathBuf>) -> Result<()> {
         if let Ok(diff) = tracker.diff_file(path) {
             if !diff.is_empty() {
                 has_diff = true;
-                println!();
-                println!("{} {}", style("diff").bold(), style(path.display()).cyan());
-                println!("{}", style("─".repeat(60)).dim());
-
-                for line in diff.lines() {
-                    if line.starts_with('+') && !line.starts_with("+++") {
-                        println!("{}", style(line).green());
-                    } else if line.starts_with('-') && !line.starts_with("---") {
-                        println!("{}", style(line).red());
-                    } else if line.starts_with("@@") {
-                        println!("{}", style(line).cyan());
-                    } else {
-                        println!("{}", line);
-                    }
-                }
+                print_diff(path, &diff);
             }
         }
     }
@@ -56,3 +70,21 @@ pub fn run_diff(category: Option<String>, file: Option<PathBuf>) -> Result<()> {
 
     Ok(())
 }
+
+pub(super) fn print_diff(path: &Path, diff: &str) {
+    println!();
+    println!("{} {}", style("diff").bold(), style(path.display()).cyan());
+    println!("{}", style("─".repeat(60)).dim());
+
+    for line in diff.lines() {
+        if line.starts_with('+') && !line.starts_with("+++") {
+            println!("{}", style(line).green());
+        } else if line.starts_with('-') && !line.starts_with("---") {
+            println!("{}", style(line).red());
+        } else if line.starts_with("@@") {
+            println!("{}", style(line).cyan());
+        } else {
+            println!("{}", line);
+        }
+    }
+}
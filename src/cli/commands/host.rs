@@ -0,0 +1,206 @@
+use chrono::Local;
+use console::style;
+use dialoguer::Confirm;
+
+use crate::cli::HostCommands;
+use crate::core::{Config, HostInfo, HostStrategy, Repository};
+use crate::error::Result;
+
+pub fn run_host(cmd: HostCommands) -> Result<()> {
+    let mut repo = Repository::open_default()?;
+
+    match cmd {
+        HostCommands::List => {
+            list_hosts(&repo)?;
+        }
+        HostCommands::Show { name } => {
+            show_host(&repo, name)?;
+        }
+        HostCommands::Switch { name } => {
+            switch_host(&mut repo, &name)?;
+        }
+        HostCommands::Rename { old, new } => {
+            rename_host(&mut repo, &old, &new)?;
+        }
+        HostCommands::Remove { name, force } => {
+            remove_host(&repo, &name, force)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn list_hosts(repo: &Repository) -> Result<()> {
+    let hosts = repo.list_hosts()?;
+    let current = repo.current_host()?;
+
+    if hosts.is_empty() {
+        println!("No hosts in the repository config.");
+        return Ok(());
+    }
+
+    let width = hosts.iter().map(|h| h.name.len()).max().unwrap_or(0);
+
+    println!();
+    println!("{}", style("Hosts:").bold());
+    println!();
+
+    for host in &hosts {
+        let marker = if host.name == current { "*" } else { " " };
+        println!(
+            "  {} {:width$}  {}  {}",
+            style(marker).green().bold(),
+            style(&host.name).cyan().bold(),
+            style(location(repo, host).1).dim(),
+            last_commit(host),
+            width = width
+        );
+    }
+    println!();
+
+    Ok(())
+}
+
+fn show_host(repo: &Repository, name: Option<String>) -> Result<()> {
+    let name = match name {
+        Some(name) => name,
+        None => repo.current_host()?.to_string(),
+    };
+    let host = repo.host(&name)?;
+
+    println!();
+    print!(
+        "{} {}",
+        style("Host:").bold(),
+        style(&host.name).cyan().bold()
+    );
+    if host.name == repo.current_host()? {
+        print!(" {}", style("(current)").green());
+    }
+    println!();
+    let (kind, place) = location(repo, &host);
+    println!("{} {}", style(format!("{}:", kind)).bold(), place);
+    if !host.groups.is_empty() {
+        println!("{} {}", style("Groups:").bold(), host.groups.join(", "));
+    }
+    println!("{} {}", style("Last commit:").bold(), last_commit(&host));
+
+    if !host.remotes.is_empty() {
+        println!();
+        println!("{}", style("Remotes:").bold());
+        for remote in &host.remotes {
+            println!("  {}/{}", remote, host.branch);
+        }
+    }
+    println!();
+
+    Ok(())
+}
+
+fn switch_host(repo: &mut Repository, name: &str) -> Result<()> {
+    repo.switch_host(name)?;
+
+    let mut config = Config::load_global()?;
+    config.hosts.current = Some(name.to_string());
+    config.save_global()?;
+
+    let (kind, place) = location(repo, &repo.host(name)?);
+    println!(
+        "{} Switched to host '{}' on {} {}",
+        style("✓").green().bold(),
+        style(name).cyan(),
+        kind.to_lowercase(),
+        style(place).green()
+    );
+    println!(
+        "  Run {} to apply its files to this machine",
+        style("confect restore").cyan()
+    );
+
+    Ok(())
+}
+
+fn rename_host(repo: &mut Repository, old: &str, new: &str) -> Result<()> {
+    let was_current = repo.current_host()? == old;
+    repo.rename_host(old, new)?;
+
+    if was_current {
+        let mut config = Config::load_global()?;
+        config.hosts.current = Some(new.to_string());
+        config.save_global()?;
+    }
+
+    let (kind, place) = location(repo, &repo.host(new)?);
+    println!(
+        "{} Renamed host '{}' to '{}' on {} {}",
+        style("✓").green().bold(),
+        style(old).cyan(),
+        style(new).cyan(),
+        kind.to_lowercase(),
+        style(place).green()
+    );
+    if repo.strategy() == HostStrategy::Branch {
+        println!(
+            "  Branches on remotes keep the old name; {} pushes the new one",
+            style("confect sync").cyan()
+        );
+    }
+
+    Ok(())
+}
+
+fn remove_host(repo: &Repository, name: &str, force: bool) -> Result<()> {
+    let host = repo.host(name)?;
+    let (kind, place) = location(repo, &host);
+
+    if !force {
+        let proceed = Confirm::new()
+            .with_prompt(format!(
+                "Remove host '{}' and delete its local {} {}?",
+                name,
+                kind.to_lowercase(),
+                place
+            ))
+            .default(false)
+            .interact()?;
+
+        if !proceed {
+            println!("Aborted.");
+            return Ok(());
+        }
+    }
+
+    repo.remove_host(name)?;
+
+    println!(
+        "{} Removed host '{}'",
+        style("✓").green().bold(),
+        style(name).cyan()
+    );
+    if repo.strategy() == HostStrategy::Branch && !host.remotes.is_empty() {
+        println!("  {} is still on {}", host.branch, host.remotes.join(", "));
+    }
+
+    Ok(())
+}
+
+/// Describe where a host's files live: its branch, or its directory with
+/// the directory strategy
+fn location(repo: &Repository, host: &HostInfo) -> (&'static str, String) {
+    match repo.strategy() {
+        HostStrategy::Branch => ("Branch", host.branch.clone()),
+        HostStrategy::Directory => ("Directory", repo.host_dir(&host.name).display().to_string()),
+    }
+}
+
+/// Format the time and summary of the last commit on a host's branch
+fn last_commit(host: &HostInfo) -> String {
+    match host.last_commit {
+        Some(time) => format!(
+            "{}  {}",
+            time.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+            host.last_message.as_deref().unwrap_or("")
+        ),
+        None => style("(no commits)").dim().to_string(),
+    }
+}
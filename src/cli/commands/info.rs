@@ -7,7 +7,7 @@ use crate::fs::FileTracker;
 pub fn run_info() -> Result<()> {
     let repo = Repository::open_default()?;
     let categories = CategoryManager::load(&repo)?;
-    let tracker = FileTracker::new(&repo);
+    let tracker = FileTracker::new(&repo)?;
     let config = Config::load_global()?;
 
     println!();
@@ -1,14 +1,18 @@
 use console::style;
 use std::path::PathBuf;
 
-use crate::core::{Config, Repository};
+use crate::core::{Config, HostBase, HostStrategy, Repository, BASE_BRANCH};
 use crate::error::{ConfectError, Result};
+use crate::fs::FileTracker;
 
 pub fn run_init(
     path: Option<PathBuf>,
     system: bool,
     remote: Option<String>,
     host: Option<String>,
+    strategy: Option<HostStrategy>,
+    base: Option<HostBase>,
+    dry_run_restore: bool,
 ) -> Result<()> {
     let repo_path = if let Some(p) = path {
         p
@@ -59,6 +63,22 @@ pub fn run_init(
         return Err(ConfectError::AlreadyInitialized(repo_path));
     }
 
+    // Join an existing repository rather than starting a new history
+    if let Some(url) = remote.as_ref() {
+        let from = base
+            .clone()
+            .unwrap_or_else(|| HostBase::Branch(BASE_BRANCH.to_string()));
+        if let Some(repo) = Repository::bootstrap(url, &repo_path, &hostname, &from)? {
+            return joined(&repo, url, &hostname, &from, dry_run_restore);
+        }
+        if base.is_some() {
+            return Err(ConfectError::Other(format!(
+                "{} has no branches to start from",
+                url
+            )));
+        }
+    }
+
     println!(
         "{} Initializing confect repository at {}",
         style("[1/3]").bold().dim(),
@@ -66,13 +86,25 @@ pub fn run_init(
     );
 
     // Create repository
-    let repo = Repository::init(&repo_path, &hostname)?;
+    let strategy = match strategy {
+        Some(strategy) => strategy,
+        None => Config::load_global()?.hosts.strategy,
+    };
+    let repo = Repository::init_with_strategy(&repo_path, &hostname, strategy)?;
 
-    println!(
-        "{} Created branch {}",
-        style("[2/3]").bold().dim(),
-        style(format!("host/{}", hostname)).green()
-    );
+    match strategy {
+        HostStrategy::Branch => println!(
+            "{} Created branch {}",
+            style("[2/3]").bold().dim(),
+            style(format!("host/{}", hostname)).green()
+        ),
+        HostStrategy::Directory => println!(
+            "{} Using directory {} on {}",
+            style("[2/3]").bold().dim(),
+            style(repo.host_dir(&hostname).display()).green(),
+            style(BASE_BRANCH).green()
+        ),
+    }
 
     // Set up remote if provided
     if let Some(url) = remote.as_ref() {
@@ -105,3 +137,81 @@ pub fn run_init(
 
     Ok(())
 }
+
+/// Report a host added to a cloned repository
+fn joined(
+    repo: &Repository,
+    url: &str,
+    hostname: &str,
+    base: &HostBase,
+    dry_run_restore: bool,
+) -> Result<()> {
+    println!(
+        "{} Cloned {} into {}",
+        style("[1/3]").bold().dim(),
+        style(url).cyan(),
+        style(repo.path().display()).cyan()
+    );
+
+    let from = match base {
+        HostBase::Host(name) => format!("host {}", name),
+        HostBase::Branch(branch) => branch.clone(),
+    };
+    match repo.strategy() {
+        HostStrategy::Branch => println!(
+            "{} Created branch {} from {}",
+            style("[2/3]").bold().dim(),
+            style(format!("host/{}", hostname)).green(),
+            from
+        ),
+        HostStrategy::Directory => println!(
+            "{} Created directory {} from {}",
+            style("[2/3]").bold().dim(),
+            style(repo.host_dir(hostname).display()).green(),
+            from
+        ),
+    }
+    println!(
+        "{} Registered host {} (pushed by the next 'confect sync')",
+        style("[3/3]").bold().dim(),
+        style(hostname).cyan()
+    );
+
+    // Create global config
+    Config::init_global(hostname)?;
+
+    if dry_run_restore {
+        let tracker = FileTracker::new(repo)?;
+        let files = tracker.list_all_tracked_files()?;
+
+        println!();
+        println!(
+            "{} {} file(s) to restore:",
+            style("Found").bold(),
+            files.len()
+        );
+        for path in &files {
+            let marker = if path.exists() {
+                style("→").yellow()
+            } else {
+                style("+").green()
+            };
+            println!("  {} {}", marker, path.display());
+        }
+        println!();
+        println!("{}", style("Dry run - no changes made.").dim());
+    }
+
+    println!();
+    println!(
+        "{} Host {} added to the repository!",
+        style("✓").green().bold(),
+        style(hostname).cyan()
+    );
+    println!();
+    println!("Next steps:");
+    println!("  1. Apply files:   confect restore");
+    println!("  2. Sync changes:  confect sync");
+
+    Ok(())
+}
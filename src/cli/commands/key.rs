@@ -0,0 +1,232 @@
+use age::secrecy::ExposeSecret;
+use console::style;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use crate::cli::KeyCommands;
+use crate::core::Config;
+use crate::crypto::{read_passphrase, AgeEncryption};
+use crate::error::{ConfectError, Result};
+
+pub fn run_key(cmd: KeyCommands) -> Result<()> {
+    let mut config = Config::load_global()?;
+
+    match cmd {
+        KeyCommands::Generate { force, passphrase } => {
+            generate_key(&mut config, force, passphrase)?;
+        }
+        KeyCommands::Show => {
+            show_key(&config)?;
+        }
+        KeyCommands::Import {
+            path,
+            force,
+            passphrase,
+        } => {
+            import_key(&mut config, &path, force, passphrase)?;
+        }
+        KeyCommands::Export { output } => {
+            export_key(&config, output)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn generate_key(config: &mut Config, force: bool, passphrase: bool) -> Result<()> {
+    let identity_path = config.encryption.identity_path()?;
+
+    if identity_path.exists() && !force {
+        return Err(ConfectError::Other(format!(
+            "Identity already exists at {} (use --force to replace it)",
+            identity_path.display()
+        )));
+    }
+
+    let identity = age::x25519::Identity::generate();
+    install_identity(config, &identity, passphrase)?;
+
+    println!(
+        "{} Generated identity at {}",
+        style("✓").green().bold(),
+        style(identity_path.display()).cyan()
+    );
+    println!("  Public key: {}", style(identity.to_public()).green());
+    println!();
+    println!(
+        "Back it up with {}",
+        style("confect key export -o <file>").cyan()
+    );
+
+    Ok(())
+}
+
+fn show_key(config: &Config) -> Result<()> {
+    let identity_path = config.encryption.identity_path()?;
+
+    if identity_path.exists() {
+        let mode = fs::metadata(&identity_path)?.permissions().mode();
+        if mode & 0o077 != 0 {
+            fs::set_permissions(&identity_path, fs::Permissions::from_mode(0o600))?;
+            eprintln!(
+                "{} Fixed insecure permissions {:04o} on {}",
+                style("Warning:").yellow().bold(),
+                mode & 0o7777,
+                identity_path.display()
+            );
+        }
+    }
+
+    let identity = AgeEncryption::load_identity(&identity_path)?;
+    let public_key = identity.to_public().to_string();
+
+    println!();
+    println!(
+        "{} {}",
+        style("Public key:").bold(),
+        style(&public_key).green()
+    );
+    println!(
+        "{} {}",
+        style("Identity:").bold(),
+        style(identity_path.display()).cyan()
+    );
+    println!(
+        "{} {}",
+        style("Encryption:").bold(),
+        if config.encryption.enabled {
+            "enabled"
+        } else {
+            "disabled"
+        }
+    );
+
+    if config.encryption.public_key.as_deref() != Some(public_key.as_str()) {
+        println!();
+        println!(
+            "{} Configured public key does not match this identity",
+            style("!").yellow().bold()
+        );
+    }
+    println!();
+
+    Ok(())
+}
+
+fn import_key(config: &mut Config, path: &Path, force: bool, passphrase: bool) -> Result<()> {
+    let identity_path = config.encryption.identity_path()?;
+
+    if identity_path.exists() && !force {
+        return Err(ConfectError::Other(format!(
+            "Identity already exists at {} (use --force to replace it)",
+            identity_path.display()
+        )));
+    }
+
+    if !path.exists() {
+        return Err(ConfectError::FileNotFound(path.to_path_buf()));
+    }
+
+    let data = fs::read(path)?;
+
+    // Backups created by `key export` are passphrase-encrypted
+    let content = if AgeEncryption::is_encrypted_data(&data) {
+        let backup_passphrase = read_passphrase("Backup passphrase", false)?;
+        AgeEncryption::decrypt_with_passphrase(&data, &backup_passphrase)?
+    } else {
+        data
+    };
+
+    let content = String::from_utf8(content)
+        .map_err(|_| ConfectError::Decryption("Identity file is not valid UTF-8".to_string()))?;
+    let identity = AgeEncryption::parse_identity(&content)?;
+    install_identity(config, &identity, passphrase)?;
+
+    println!(
+        "{} Imported identity to {}",
+        style("✓").green().bold(),
+        style(identity_path.display()).cyan()
+    );
+    println!("  Public key: {}", style(identity.to_public()).green());
+
+    Ok(())
+}
+
+fn export_key(config: &Config, output: Option<PathBuf>) -> Result<()> {
+    let identity_path = config.encryption.identity_path()?;
+    let identity = AgeEncryption::load_identity(&identity_path)?;
+
+    let passphrase = read_passphrase("Backup passphrase", true)?;
+
+    if passphrase.expose_secret().is_empty() {
+        return Err(ConfectError::Encryption(
+            "Backup passphrase must not be empty".to_string(),
+        ));
+    }
+
+    // Re-serialize so a passphrase-wrapped identity is not wrapped twice
+    let content = AgeEncryption::identity_file_content(&identity);
+    let backup = AgeEncryption::encrypt_with_passphrase(content.as_bytes(), &passphrase)?;
+
+    match output {
+        Some(path) => {
+            let mut file = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .mode(0o600)
+                .open(&path)?;
+            file.write_all(&backup)?;
+
+            println!(
+                "{} Exported passphrase-protected backup of {} to {}",
+                style("✓").green().bold(),
+                style(identity.to_public()).green(),
+                style(path.display()).cyan()
+            );
+        }
+        None => {
+            std::io::stdout().write_all(&backup)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Store an identity and enable encryption with its public key
+///
+/// With `passphrase` the identity file is stored passphrase-encrypted.
+fn install_identity(
+    config: &mut Config,
+    identity: &age::x25519::Identity,
+    passphrase: bool,
+) -> Result<()> {
+    let identity_path = config.encryption.identity_path()?;
+    let public_key = identity.to_public().to_string();
+
+    let passphrase = if passphrase {
+        let passphrase = read_passphrase("Identity passphrase", true)?;
+        if passphrase.expose_secret().is_empty() {
+            return Err(ConfectError::Encryption(
+                "Identity passphrase must not be empty".to_string(),
+            ));
+        }
+        Some(passphrase)
+    } else {
+        None
+    };
+
+    AgeEncryption::save_identity(&identity_path, identity, passphrase.as_ref())?;
+    fs::write(
+        config.encryption.public_key_path()?,
+        format!("{}\n", public_key),
+    )?;
+
+    config.encryption.public_key = Some(public_key);
+    config.encryption.enabled = true;
+    config.save_global()?;
+
+    Ok(())
+}
@@ -0,0 +1,102 @@
+use chrono::Local;
+use console::style;
+use std::path::PathBuf;
+
+use crate::core::{FileChange, Repository};
+use crate::error::Result;
+use crate::fs::FileTracker;
+
+pub fn run_log(
+    path: Option<PathBuf>,
+    category: Option<String>,
+    patch: bool,
+    max_count: Option<usize>,
+) -> Result<()> {
+    let repo = Repository::open_default()?;
+    let tracker = FileTracker::new(&repo)?;
+    let path = path.map(|path| path.canonicalize().unwrap_or(path));
+
+    let entries = tracker.log(path.as_deref(), category.as_deref(), patch, max_count)?;
+    if entries.is_empty() {
+        println!("No history found.");
+        return Ok(());
+    }
+
+    for entry in &entries {
+        println!();
+        println!(
+            "{}  {}  {}",
+            style(&entry.id.to_string()[..7]).yellow(),
+            style(entry.time.with_timezone(&Local).format("%Y-%m-%d %H:%M")).dim(),
+            style(&entry.branch).cyan()
+        );
+        println!("    {}", entry.summary);
+
+        // Show files by the system path they restore to where there is one
+        let files: Vec<(String, bool, &FileChange)> = entry
+            .files
+            .iter()
+            .map(
+                |file| match tracker.system_path_of(&file.path).ok().flatten() {
+                    Some(system_path) => (
+                        system_path.display().to_string(),
+                        tracker.is_stored_encrypted(&system_path),
+                        file,
+                    ),
+                    None => (file.path.display().to_string(), false, file),
+                },
+            )
+            .collect();
+        let width = files
+            .iter()
+            .map(|(name, _, _)| name.len())
+            .max()
+            .unwrap_or(0);
+
+        for (name, encrypted, file) in &files {
+            let stat = if *encrypted {
+                style("encrypted".to_string()).magenta()
+            } else if file.binary {
+                style("binary".to_string()).dim()
+            } else {
+                style(format!(
+                    "{} {}",
+                    style(format!("+{}", file.insertions)).green(),
+                    style(format!("-{}", file.deletions)).red()
+                ))
+            };
+            println!("    {:width$}  {}", name, stat, width = width);
+        }
+
+        if patch {
+            for (name, encrypted, file) in &files {
+                if *encrypted {
+                    println!();
+                    println!("{} {}", style("Encrypted file changed:").dim(), name);
+                } else if let Some(patch) = &file.patch {
+                    println!();
+                    print_patch(patch);
+                }
+            }
+        }
+    }
+    println!();
+
+    Ok(())
+}
+
+fn print_patch(patch: &str) {
+    for line in patch.lines() {
+        if line.starts_with('+') && !line.starts_with("+++") {
+            println!("{}", style(line).green());
+        } else if line.starts_with('-') && !line.starts_with("---") {
+            println!("{}", style(line).red());
+        } else if line.starts_with("@@") {
+            println!("{}", style(line).cyan());
+        } else if line.starts_with("diff ") {
+            println!("{}", style(line).bold());
+        } else {
+            println!("{}", line);
+        }
+    }
+}
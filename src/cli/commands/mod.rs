@@ -1,21 +1,37 @@
 mod add;
 mod category;
 mod diff;
+mod host;
 mod info;
 mod init;
+mod key;
+mod log;
+mod rekey;
 mod remove;
+mod resolve;
 mod restore;
 mod self_update;
+mod share;
+mod snapshot;
 pub mod status;
 mod sync;
+mod which;
 
 pub use add::run_add;
 pub use category::run_category;
 pub use diff::run_diff;
+pub use host::run_host;
 pub use info::run_info;
 pub use init::run_init;
+pub use key::run_key;
+pub use log::run_log;
+pub use rekey::run_rekey;
 pub use remove::run_remove;
+pub use resolve::run_resolve;
 pub use restore::run_restore;
 pub use self_update::run_self_update;
+pub use share::run_share;
+pub use snapshot::run_snapshot;
 pub use status::{run_status, FileStatus};
 pub use sync::run_sync;
+pub use which::run_which;
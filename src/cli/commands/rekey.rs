@@ -0,0 +1,54 @@
+use console::style;
+
+use crate::core::Repository;
+use crate::error::Result;
+use crate::fs::FileTracker;
+
+pub fn run_rekey(category: Option<String>, message: Option<String>) -> Result<()> {
+    let repo = Repository::open_default()?;
+    let tracker = FileTracker::new(&repo)?;
+
+    println!("{} Re-encrypting files...", style("[1/2]").bold().dim());
+
+    let result = tracker.rekey(category.as_deref())?;
+
+    if !result.failed.is_empty() {
+        println!(
+            "{} Could not decrypt {} file(s) with the local identity:",
+            style("!").yellow().bold(),
+            result.failed.len()
+        );
+        for (path, err) in &result.failed {
+            println!("  {} {}: {}", style("✗").red(), path.display(), err);
+        }
+    }
+
+    if result.rekeyed.is_empty() {
+        println!("No files were re-encrypted.");
+        return Ok(());
+    }
+
+    let commit_message = message.unwrap_or_else(|| match &category {
+        Some(name) => format!("Rekey {} ({} files)", name, result.rekeyed.len()),
+        None => format!("Rekey {} files", result.rekeyed.len()),
+    });
+
+    println!("{} Creating commit...", style("[2/2]").bold().dim());
+
+    repo.commit_all(&commit_message)?;
+
+    println!(
+        "{} Committed: {}",
+        style("✓").green(),
+        style(&commit_message).italic()
+    );
+    println!();
+    println!(
+        "{} Re-encrypted {} file(s)",
+        style("✓").green().bold(),
+        result.rekeyed.len()
+    );
+    println!("Run {} to push changes.", style("confect sync").cyan());
+
+    Ok(())
+}
@@ -8,7 +8,7 @@ use crate::fs::FileTracker;
 pub fn run_remove(path: PathBuf, delete: bool) -> Result<()> {
     let repo = Repository::open_default()?;
     let mut categories = CategoryManager::load(&repo)?;
-    let tracker = FileTracker::new(&repo);
+    let tracker = FileTracker::new(&repo)?;
 
     // Canonicalize path if it exists, otherwise use as-is
     let path = path.canonicalize().unwrap_or(path);
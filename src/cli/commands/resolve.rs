@@ -0,0 +1,321 @@
+use console::{style, Term};
+use dialoguer::Select;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::core::{
+    BranchFile, Config, ConflictSide, HostStrategy, MergeOutcome, Repository, COMMON_DIR,
+};
+use crate::crypto::AgeEncryption;
+use crate::error::{ConfectError, Result};
+use crate::fs::{FileTracker, MetadataStore};
+
+const STATE_DIR: &str = ".confect";
+const METADATA_FILE: &str = "metadata.toml";
+
+/// The version of a conflicted file to keep
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Choice {
+    Mine,
+    Theirs,
+    System,
+    Edited,
+}
+
+pub fn run_resolve() -> Result<()> {
+    let repo = Repository::open_default()?;
+    let editor = editor()?;
+
+    let mut conflicts = repo.conflicts()?;
+    if conflicts.is_empty() && repo.ensure_resolved().is_ok() {
+        println!("{} Nothing to resolve", style("✓").green());
+        return Ok(());
+    }
+
+    loop {
+        if !conflicts.is_empty() {
+            if !Term::stderr().is_term() {
+                return Err(ConfectError::Other(format!(
+                    "confect resolve needs an interactive terminal to resolve {} conflicted file(s)",
+                    conflicts.len()
+                )));
+            }
+            println!();
+            println!("Resolving {} conflicted file(s)", conflicts.len());
+            resolve_files(&repo, &conflicts, &editor)?;
+        }
+
+        match repo.finish_merge()? {
+            MergeOutcome::Conflicts(paths) => {
+                println!();
+                println!(
+                    "{} The next local commit conflicts in {} file(s)",
+                    style("!").yellow().bold(),
+                    paths.len()
+                );
+                conflicts = paths;
+            }
+            MergeOutcome::Rebased => {
+                println!();
+                println!(
+                    "{} Rebased local changes onto the remote",
+                    style("✓").green()
+                );
+                break;
+            }
+            _ => {
+                println!();
+                println!("{} Merged remote changes", style("✓").green());
+                break;
+            }
+        }
+    }
+
+    println!();
+    println!("Run 'confect restore' to apply the resolved files to this system");
+
+    Ok(())
+}
+
+/// Ask for the version to keep of each conflicted file and stage it
+///
+/// Conflicts in confect's own state go first, as the tracker reads categories
+/// and indexes from it. Metadata files are merged last, taking each file's
+/// entry from the version that was kept.
+fn resolve_files(repo: &Repository, conflicts: &[PathBuf], editor: &str) -> Result<()> {
+    let (metadata, rest): (Vec<&PathBuf>, Vec<&PathBuf>) =
+        conflicts.iter().partition(|path| is_metadata_file(path));
+    let (state, files): (Vec<&PathBuf>, Vec<&PathBuf>) = rest
+        .into_iter()
+        .partition(|path| path.components().any(|c| c.as_os_str() == STATE_DIR));
+
+    for path in state {
+        println!();
+        println!("{}", style(path.display()).bold());
+        let choice = choose(repo, path, None, editor)?;
+        keep(repo, path, choice)?;
+    }
+
+    let tracker = FileTracker::new(repo)?;
+    let mut kept: BTreeMap<PathBuf, Vec<(PathBuf, Choice)>> = BTreeMap::new();
+    for path in files {
+        let system_path = tracker.system_path_of(path)?;
+
+        println!();
+        match &system_path {
+            Some(system_path) => println!(
+                "{} {}",
+                style(system_path.display()).bold(),
+                style(format!("({})", path.display())).dim()
+            ),
+            None => println!("{}", style(path.display()).bold()),
+        }
+
+        let choice = choose(repo, path, system_path.as_deref(), editor)?;
+        match (choice, &system_path) {
+            (Choice::System, Some(system_path)) => {
+                tracker.store_system_copy(system_path, path)?;
+                repo.mark_resolved(path)?;
+            }
+            _ => keep(repo, path, choice)?,
+        }
+
+        if let Some(system_path) = system_path {
+            kept.entry(metadata_dir(repo, path)?)
+                .or_default()
+                .push((system_path, choice));
+        }
+    }
+
+    let mut dirs: Vec<PathBuf> = kept.keys().cloned().collect();
+    for path in metadata {
+        let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        if !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+    for dir in dirs {
+        let files = kept.get(&dir).map(Vec::as_slice).unwrap_or_default();
+        merge_metadata(repo, &tracker, &dir, conflicts, files)?;
+    }
+
+    Ok(())
+}
+
+/// Prompt for the version of a file to keep, editing it if asked to
+fn choose(
+    repo: &Repository,
+    path: &Path,
+    system_path: Option<&Path>,
+    editor: &str,
+) -> Result<Choice> {
+    let mine = repo.read_conflict_file(ConflictSide::Mine, path)?;
+    let theirs = repo.read_conflict_file(ConflictSide::Theirs, path)?;
+
+    let describe = |file: &Option<BranchFile>| if file.is_some() { "" } else { " (deleted)" };
+    let mut options = vec![
+        (format!("Take mine{}", describe(&mine)), Choice::Mine),
+        (format!("Take theirs{}", describe(&theirs)), Choice::Theirs),
+    ];
+    if system_path.is_some_and(|p| p.exists() || p.is_symlink()) {
+        options.push(("Take the system file".to_string(), Choice::System));
+    }
+    // Markers in ciphertext or symlink targets cannot be edited
+    if is_text(&mine) && is_text(&theirs) && repo.path().join(path).is_file() {
+        options.push(("Open in editor".to_string(), Choice::Edited));
+    }
+    let labels: Vec<&str> = options.iter().map(|(label, _)| label.as_str()).collect();
+
+    loop {
+        let selected = Select::new()
+            .with_prompt("Which version should be kept?")
+            .items(&labels)
+            .default(0)
+            .interact()?;
+
+        let choice = options[selected].1;
+        if choice != Choice::Edited {
+            return Ok(choice);
+        }
+
+        let full_path = repo.path().join(path);
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(format!("{} \"$0\"", editor))
+            .arg(&full_path)
+            .status()?;
+        if !status.success() {
+            eprintln!(
+                "{} Editor exited with {}",
+                style("!").yellow().bold(),
+                status
+            );
+            continue;
+        }
+
+        let content = fs::read_to_string(&full_path)?;
+        if has_markers(&content) {
+            eprintln!(
+                "{} The file still has conflict markers",
+                style("!").yellow().bold()
+            );
+            continue;
+        }
+        return Ok(choice);
+    }
+}
+
+/// Write the chosen side of a conflict to the working tree and stage it
+fn keep(repo: &Repository, path: &Path, choice: Choice) -> Result<()> {
+    match choice {
+        Choice::Mine => repo.write_file(
+            path,
+            repo.read_conflict_file(ConflictSide::Mine, path)?.as_ref(),
+        )?,
+        Choice::Theirs => repo.write_file(
+            path,
+            repo.read_conflict_file(ConflictSide::Theirs, path)?
+                .as_ref(),
+        )?,
+        // Already in place
+        Choice::System | Choice::Edited => {}
+    }
+    repo.mark_resolved(path)
+}
+
+/// Write the metadata of a layer, with entries for the files kept
+fn merge_metadata(
+    repo: &Repository,
+    tracker: &FileTracker,
+    dir: &Path,
+    conflicts: &[PathBuf],
+    kept: &[(PathBuf, Choice)],
+) -> Result<()> {
+    let file = dir.join(METADATA_FILE);
+    let read = |side| -> Result<Option<String>> {
+        Ok(match repo.read_conflict_file(side, &file)? {
+            Some(BranchFile::Blob { content, .. }) => {
+                Some(String::from_utf8_lossy(&content).into_owned())
+            }
+            _ => None,
+        })
+    };
+    let full_dir = repo.path().join(dir);
+    let mine = read(ConflictSide::Mine)?;
+    let theirs = read(ConflictSide::Theirs)?;
+
+    let mut store = if conflicts.contains(&file) {
+        MetadataStore::merged(
+            &full_dir,
+            read(ConflictSide::Base)?.as_deref(),
+            mine.as_deref(),
+            theirs.as_deref(),
+        )?
+    } else {
+        MetadataStore::load_dir(&full_dir)?
+    };
+    let mine = MetadataStore::from_toml(&full_dir, mine.as_deref().unwrap_or_default())?;
+    let theirs = MetadataStore::from_toml(&full_dir, theirs.as_deref().unwrap_or_default())?;
+
+    for (system_path, choice) in kept {
+        let side = match choice {
+            Choice::System => {
+                store.update_from_system(system_path, tracker.is_stored_encrypted(system_path))?;
+                continue;
+            }
+            Choice::Theirs => &theirs,
+            Choice::Mine | Choice::Edited => &mine,
+        };
+        match side.get(system_path) {
+            Some(meta) => store.set(system_path, meta.clone()),
+            None => store.remove(system_path),
+        }
+    }
+
+    store.save()?;
+    repo.mark_resolved(&file)
+}
+
+/// Get the directory, relative to the repository, holding a file's metadata
+fn metadata_dir(repo: &Repository, path: &Path) -> Result<PathBuf> {
+    if repo.strategy() == HostStrategy::Directory && path.starts_with(COMMON_DIR) {
+        return Ok(Path::new(COMMON_DIR).join(STATE_DIR));
+    }
+    Ok(repo.host_dir(repo.current_host()?).join(STATE_DIR))
+}
+
+fn is_metadata_file(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name == METADATA_FILE)
+        && path
+            .parent()
+            .and_then(Path::file_name)
+            .is_some_and(|name| name == STATE_DIR)
+}
+
+/// Whether a version can be merged by hand: missing or unencrypted content
+fn is_text(file: &Option<BranchFile>) -> bool {
+    match file {
+        Some(BranchFile::Blob { content, .. }) => !AgeEncryption::is_encrypted_data(content),
+        Some(BranchFile::Symlink(_)) => false,
+        None => true,
+    }
+}
+
+fn has_markers(content: &str) -> bool {
+    content
+        .lines()
+        .any(|line| line.starts_with("<<<<<<<") || line.starts_with(">>>>>>>"))
+}
+
+/// Get the editor to resolve conflicts in
+fn editor() -> Result<String> {
+    let config = Config::load_global()?;
+    Ok(config
+        .global
+        .editor
+        .or_else(|| std::env::var("VISUAL").ok())
+        .or_else(|| std::env::var("EDITOR").ok())
+        .unwrap_or_else(|| "vi".to_string()))
+}
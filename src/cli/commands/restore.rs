@@ -1,10 +1,11 @@
+use chrono::Local;
 use console::style;
 use dialoguer::Confirm;
 use std::path::PathBuf;
 
 use crate::core::{CategoryManager, Repository};
 use crate::error::Result;
-use crate::fs::{FileTracker, MetadataStore};
+use crate::fs::FileTracker;
 
 pub fn run_restore(
     category: Option<String>,
@@ -12,15 +13,26 @@ pub fn run_restore(
     dry_run: bool,
     force: bool,
     backup: bool,
+    at: Option<String>,
 ) -> Result<()> {
     let repo = Repository::open_default()?;
     let categories = CategoryManager::load(&repo)?;
-    let tracker = FileTracker::new(&repo);
-    let metadata = MetadataStore::load(&repo)?;
+    let tracker = FileTracker::new(&repo)?;
+
+    // Restoring from history reads committed trees, never the working tree
+    let revision = at
+        .as_deref()
+        .map(|spec| repo.resolve_revision(spec))
+        .transpose()?;
+    if revision.is_none() {
+        repo.ensure_resolved()?;
+    }
 
     // Determine what to restore
     let files_to_restore = if let Some(path) = file {
         vec![path]
+    } else if let Some(revision) = &revision {
+        tracker.list_files_at(revision.id, category.as_deref())?
     } else if let Some(cat_name) = &category {
         let cat = categories.get(cat_name)?;
         tracker.list_files_in_category(&cat.name)?
@@ -34,6 +46,15 @@ pub fn run_restore(
     }
 
     println!();
+    if let Some(revision) = &revision {
+        println!(
+            "{} {} {} ({})",
+            style("Restoring from").bold(),
+            style(&revision.id.to_string()[..7]).yellow(),
+            revision.summary,
+            revision.time.with_timezone(&Local).format("%Y-%m-%d %H:%M")
+        );
+    }
     println!(
         "{} {} file(s) to restore:",
         style("Found").bold(),
@@ -89,10 +110,27 @@ pub fn run_restore(
     let mut errors = Vec::new();
 
     for path in &files_to_restore {
-        match tracker.restore_file(path) {
-            Ok(_) => {
-                // Apply metadata (permissions, owner)
-                if let Err(e) = metadata.apply_to(path) {
+        let result = match &revision {
+            Some(revision) => tracker.restore_file_at(path, revision.id),
+            None => tracker
+                .restore_file(path)
+                .and_then(|()| tracker.stored_metadata(path)),
+        };
+        match result {
+            Ok(meta) => {
+                // Apply metadata (permissions, owner). Inherited files keep
+                // theirs in the group or shared layer they come from
+                let applied = match meta {
+                    Some(meta) => meta.apply_to(path),
+                    None if revision.is_some() => Ok(()),
+                    None => tracker
+                        .inherited_metadata(path)
+                        .and_then(|meta| match meta {
+                            Some(meta) => meta.apply_to(path),
+                            None => Ok(()),
+                        }),
+                };
+                if let Err(e) = applied {
                     eprintln!(
                         "  {} Failed to restore permissions for {}: {}",
                         style("!").yellow(),
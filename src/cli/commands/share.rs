@@ -0,0 +1,86 @@
+use console::style;
+use std::path::{Path, PathBuf};
+
+use crate::core::{HostStrategy, Repository, BASE_BRANCH, COMMON_DIR};
+use crate::error::{ConfectError, Result};
+use crate::fs::FileTracker;
+
+pub fn run_share(path: PathBuf, no_push: bool) -> Result<()> {
+    let repo = Repository::open_default()?;
+    repo.ensure_resolved()?;
+    let tracker = FileTracker::new(&repo)?;
+    let path = path.canonicalize().unwrap_or(path);
+
+    // Share what is on the system, not an older synced copy
+    if let Some(changed) = tracker
+        .status(None)?
+        .into_keys()
+        .find(|changed| changed.starts_with(&path))
+    {
+        return Err(ConfectError::Other(format!(
+            "{} has unsynced changes, run 'confect sync' first",
+            changed.display()
+        )));
+    }
+
+    // With the directory strategy the shared layer is a directory on the
+    // same branch, so moving the files there and committing is enough
+    let directory = repo.strategy() == HostStrategy::Directory;
+    let layer = if directory { COMMON_DIR } else { BASE_BRANCH };
+
+    println!("{} Copying to {}...", style("[1/3]").bold().dim(), layer);
+
+    let shared = tracker.share(&path)?;
+    for (system_path, _) in &shared {
+        println!("  {} {}", style("✓").green(), system_path.display());
+    }
+
+    println!("{} Removing host copies...", style("[2/3]").bold().dim());
+
+    let metadata_path = repo
+        .host_dir(repo.current_host()?)
+        .join(".confect/metadata.toml");
+    let mut paths: Vec<&Path> = shared
+        .iter()
+        .map(|(_, repo_path)| repo_path.as_path())
+        .collect();
+    paths.push(&metadata_path);
+    let message = if directory {
+        paths.push(Path::new(COMMON_DIR));
+        format!(
+            "Share {} file(s) from {}",
+            shared.len(),
+            repo.current_host()?
+        )
+    } else {
+        format!("Use shared {} file(s) from {}", shared.len(), BASE_BRANCH)
+    };
+    repo.commit_paths(&paths, &message)?;
+
+    if !no_push && repo.has_remote("origin")? {
+        println!("{} Pushing to remote...", style("[3/3]").bold().dim());
+        if !directory {
+            repo.push_branch("origin", BASE_BRANCH)?;
+        }
+        repo.push("origin")?;
+        println!("{} Pushed to origin", style("✓").green());
+    } else {
+        println!(
+            "{} Skipping push (no remote or --no-push)",
+            style("[3/3]").bold().dim()
+        );
+    }
+
+    println!();
+    println!(
+        "{} Shared {} file(s) with all hosts",
+        style("✓").green().bold(),
+        shared.len()
+    );
+    println!(
+        "  Other hosts pick them up with {}",
+        style("confect pull --restore").cyan()
+    );
+
+    Ok(())
+}
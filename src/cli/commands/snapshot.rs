@@ -0,0 +1,160 @@
+use chrono::Local;
+use console::style;
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use super::diff::print_diff;
+use super::{run_restore, run_sync};
+use crate::cli::SnapshotCommands;
+use crate::core::Repository;
+use crate::error::{ConfectError, Result};
+use crate::fs::FileTracker;
+
+pub fn run_snapshot(cmd: SnapshotCommands) -> Result<()> {
+    match cmd {
+        SnapshotCommands::Create {
+            name,
+            message,
+            no_push,
+        } => {
+            create_snapshot(&name, message, no_push)?;
+        }
+        SnapshotCommands::List => {
+            list_snapshots()?;
+        }
+        SnapshotCommands::Diff {
+            name,
+            category,
+            file,
+        } => {
+            diff_snapshot(&name, category, file)?;
+        }
+        SnapshotCommands::Restore {
+            name,
+            category,
+            file,
+            dry_run,
+            force,
+            backup,
+        } => {
+            // Restoring a snapshot is restoring its tag
+            let snapshot = Repository::open_default()?.snapshot(&name)?;
+            run_restore(
+                category,
+                file,
+                dry_run,
+                force,
+                backup,
+                Some(format!("refs/tags/{}", snapshot.tag)),
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn create_snapshot(name: &str, message: Option<String>, no_push: bool) -> Result<()> {
+    let repo = Repository::open_default()?;
+    if repo.snapshot(name).is_ok() {
+        return Err(ConfectError::Other(format!(
+            "Snapshot '{}' already exists",
+            name
+        )));
+    }
+
+    // Commit the system's current state first, so the snapshot matches it
+    match run_sync(None, true, false) {
+        Ok(()) => {}
+        Err(ConfectError::NoChanges) => {
+            println!("{} No local changes to commit", style("✓").green());
+        }
+        Err(e) => return Err(e),
+    }
+
+    let message = message.unwrap_or_else(|| format!("Snapshot {}", name));
+    let snapshot = repo.create_snapshot(name, &message)?;
+
+    println!();
+    println!(
+        "{} Created snapshot {} at {}",
+        style("✓").green().bold(),
+        style(&snapshot.tag).cyan(),
+        style(&snapshot.id.to_string()[..7]).yellow()
+    );
+
+    if !no_push && repo.has_remote("origin")? {
+        repo.push("origin")?;
+        println!("{} Pushed to origin", style("✓").green());
+    }
+
+    Ok(())
+}
+
+fn list_snapshots() -> Result<()> {
+    let repo = Repository::open_default()?;
+    let tracker = FileTracker::new(&repo)?;
+    let snapshots = repo.list_snapshots()?;
+
+    if snapshots.is_empty() {
+        println!("No snapshots of {}.", repo.current_host()?);
+        return Ok(());
+    }
+
+    let width = snapshots.iter().map(|s| s.name.len()).max().unwrap_or(0);
+
+    println!();
+    println!("{}", style("Snapshots:").bold());
+    println!();
+
+    for snapshot in &snapshots {
+        let files = tracker.list_files_at(snapshot.id, None)?.len();
+        println!(
+            "  {:width$}  {}  {}  {:>4} file(s)  {}",
+            style(&snapshot.name).cyan().bold(),
+            style(snapshot.time.with_timezone(&Local).format("%Y-%m-%d %H:%M")).dim(),
+            style(&snapshot.id.to_string()[..7]).yellow(),
+            files,
+            style(&snapshot.message).dim(),
+            width = width
+        );
+    }
+    println!();
+
+    Ok(())
+}
+
+fn diff_snapshot(name: &str, category: Option<String>, file: Option<PathBuf>) -> Result<()> {
+    let repo = Repository::open_default()?;
+    let tracker = FileTracker::new(&repo)?;
+    let snapshot = repo.snapshot(name)?;
+
+    // Files the snapshot stored and files tracked since
+    let files_to_diff: BTreeSet<PathBuf> = if let Some(path) = file {
+        BTreeSet::from([path.canonicalize().unwrap_or(path)])
+    } else {
+        let mut files: BTreeSet<PathBuf> = tracker
+            .list_files_at(snapshot.id, category.as_deref())?
+            .into_iter()
+            .collect();
+        match &category {
+            Some(cat_name) => files.extend(tracker.list_files_in_category(cat_name)?),
+            None => files.extend(tracker.list_all_tracked_files()?),
+        }
+        files
+    };
+
+    let mut has_diff = false;
+    for path in &files_to_diff {
+        let diff = tracker.diff_file_at(path, snapshot.id)?;
+        if !diff.is_empty() {
+            has_diff = true;
+            print_diff(path, &diff);
+        }
+    }
+
+    if !has_diff {
+        println!("No differences found.");
+    }
+
+    Ok(())
+}
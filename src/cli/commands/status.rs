@@ -1,4 +1,5 @@
 use console::style;
+use std::path::Path;
 
 use crate::core::{CategoryManager, Repository};
 use crate::error::Result;
@@ -7,7 +8,7 @@ use crate::fs::FileTracker;
 pub fn run_status(category: Option<String>, show_diff: bool) -> Result<()> {
     let repo = Repository::open_default()?;
     let _categories = CategoryManager::load(&repo)?;
-    let tracker = FileTracker::new(&repo);
+    let tracker = FileTracker::new(&repo)?;
 
     // Get status for all or specific category
     let status = tracker.status(category.as_deref())?;
@@ -33,6 +34,15 @@ pub fn run_status(category: Option<String>, show_diff: bool) -> Result<()> {
         return Ok(());
     }
 
+    // Mark files stored encrypted
+    let marker = |path: &Path| {
+        if tracker.is_stored_encrypted(path) {
+            format!("{} ", style("E").magenta())
+        } else {
+            String::new()
+        }
+    };
+
     // Group by status type
     let mut modified = Vec::new();
     let mut added = Vec::new();
@@ -51,7 +61,12 @@ pub fn run_status(category: Option<String>, show_diff: bool) -> Result<()> {
     if !modified.is_empty() {
         println!("{}", style("Modified files:").yellow().bold());
         for path in &modified {
-            println!("  {} {}", style("M").yellow(), path.display());
+            println!(
+                "  {} {}{}",
+                style("M").yellow(),
+                marker(path),
+                path.display()
+            );
             if show_diff {
                 // Show inline diff (abbreviated)
                 if let Ok(diff) = tracker.diff_file(path) {
@@ -73,7 +88,12 @@ pub fn run_status(category: Option<String>, show_diff: bool) -> Result<()> {
             style("New files (in system, not in repo):").green().bold()
         );
         for path in &added {
-            println!("  {} {}", style("A").green(), path.display());
+            println!(
+                "  {} {}{}",
+                style("A").green(),
+                marker(path),
+                path.display()
+            );
         }
         println!();
     }
@@ -86,7 +106,7 @@ pub fn run_status(category: Option<String>, show_diff: bool) -> Result<()> {
                 .bold()
         );
         for path in &deleted {
-            println!("  {} {}", style("D").red(), path.display());
+            println!("  {} {}{}", style("D").red(), marker(path), path.display());
         }
         println!();
     }
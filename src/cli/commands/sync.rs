@@ -2,21 +2,50 @@ use console::style;
 
 use crate::core::Repository;
 use crate::error::{ConfectError, Result};
-use crate::fs::{FileTracker, MetadataStore};
+use crate::fs::FileTracker;
 
-pub fn run_sync(message: Option<String>, no_push: bool, _all_hosts: bool) -> Result<()> {
+pub fn run_sync(message: Option<String>, no_push: bool, all_hosts: bool) -> Result<()> {
     let repo = Repository::open_default()?;
-    let tracker = FileTracker::new(&repo);
+    repo.ensure_resolved()?;
+    let tracker = FileTracker::new(&repo)?;
 
     println!("{} Checking for changes...", style("[1/4]").bold().dim());
 
     // Refresh files from system to repository
     let refreshed = tracker.refresh_all()?;
 
+    for (path, findings) in &refreshed.blocked {
+        let rules: Vec<_> = findings
+            .iter()
+            .map(|f| format!("{} on line {}", f.rule, f.line))
+            .collect();
+        eprintln!(
+            "{} Skipped {}: possible secret ({})",
+            style("!").yellow().bold(),
+            path.display(),
+            rules.join(", ")
+        );
+    }
+    if !refreshed.blocked.is_empty() {
+        eprintln!(
+            "  Encrypt these files or list them in {} in .confect/categories.toml",
+            style("allow_secrets").cyan()
+        );
+    }
+
     // Check if there are any git changes (including untracked files from `add`)
     let has_git_changes = repo.has_changes()?;
 
     if refreshed.is_empty() && !has_git_changes {
+        if !no_push && all_hosts && !repo.list_remotes()?.is_empty() {
+            println!("{} No local changes to commit", style("[2/4]").bold().dim());
+            println!("{} Syncing all hosts...", style("[3/4]").bold().dim());
+            sync_all_hosts(&repo)?;
+            println!();
+            println!("{} Sync completed successfully!", style("✓").green().bold());
+            return Ok(());
+        }
+
         if !no_push && repo.has_remote("origin")? {
             println!("{} No local changes to commit", style("[2/4]").bold().dim());
             println!("{} Pushing to remote...", style("[3/4]").bold().dim());
@@ -46,14 +75,7 @@ pub fn run_sync(message: Option<String>, no_push: bool, _all_hosts: bool) -> Res
 
     // Update metadata for refreshed files
     if !refreshed.is_empty() {
-        let mut metadata = MetadataStore::load(&repo)?;
-        for path in &refreshed.updated {
-            metadata.update_from_system(path)?;
-        }
-        for path in &refreshed.deleted {
-            metadata.remove(path);
-        }
-        metadata.save()?;
+        tracker.update_metadata(&refreshed)?;
     }
 
     // Generate commit message
@@ -82,8 +104,13 @@ pub fn run_sync(message: Option<String>, no_push: bool, _all_hosts: bool) -> Res
             let mut category_counts: std::collections::HashMap<String, usize> =
                 std::collections::HashMap::new();
 
+            let host_dir = repo.host_dir(repo.current_host().unwrap_or_default());
             for (path, _) in &git_status {
-                // Category is the first path component (e.g., "nginx/etc/..." → "nginx")
+                // Category is the first path component (e.g., "nginx/etc/..." → "nginx"),
+                // under the host's directory with the directory strategy
+                let Ok(path) = path.strip_prefix(&host_dir) else {
+                    continue;
+                };
                 if let Some(cat) = path.components().next() {
                     let cat_name = cat.as_os_str().to_string_lossy().to_string();
                     // Skip .confect directory
@@ -124,7 +151,10 @@ pub fn run_sync(message: Option<String>, no_push: bool, _all_hosts: bool) -> Res
     );
 
     // Push if enabled
-    if !no_push && repo.has_remote("origin")? {
+    if !no_push && all_hosts && !repo.list_remotes()?.is_empty() {
+        println!("{} Syncing all hosts...", style("[4/4]").bold().dim());
+        sync_all_hosts(&repo)?;
+    } else if !no_push && repo.has_remote("origin")? {
         println!("{} Pushing to remote...", style("[4/4]").bold().dim());
         repo.push("origin")?;
         println!("{} Pushed to origin", style("✓").green());
@@ -140,3 +170,68 @@ pub fn run_sync(message: Option<String>, no_push: bool, _all_hosts: bool) -> Res
 
     Ok(())
 }
+
+/// Fetch, fast-forward and push every host branch, reporting each one
+fn sync_all_hosts(repo: &Repository) -> Result<()> {
+    let results = repo.sync_all_hosts()?;
+    let mut failed = 0;
+
+    for (remote, e) in &results.unreachable {
+        println!(
+            "  {} {}: could not fetch: {}",
+            style("✗").red(),
+            style(remote).cyan(),
+            e
+        );
+    }
+
+    for result in &results.branches {
+        let branch = style(&result.branch).cyan();
+
+        if let Some(e) = &result.error {
+            println!("  {} {}: {}", style("✗").red(), branch, e);
+            failed += 1;
+            continue;
+        }
+        if result.is_up_to_date() {
+            println!("  {} {} up to date", style("=").dim(), branch);
+            continue;
+        }
+
+        let mut actions = Vec::new();
+        if let Some(remote) = &result.fast_forwarded {
+            actions.push(format!("fast-forwarded from {}", remote));
+        }
+        if !result.pushed.is_empty() {
+            actions.push(format!("pushed to {}", result.pushed.join(", ")));
+        }
+        if !actions.is_empty() {
+            println!("  {} {} {}", style("✓").green(), branch, actions.join(", "));
+        }
+        if !result.diverged.is_empty() {
+            println!(
+                "  {} {} diverged from {}",
+                style("!").yellow().bold(),
+                branch,
+                result.diverged.join(", ")
+            );
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        return Err(ConfectError::Other(format!(
+            "{} of {} host branch(es) could not be synced",
+            failed,
+            results.branches.len()
+        )));
+    }
+    if !results.unreachable.is_empty() {
+        return Err(ConfectError::Other(format!(
+            "{} remote(s) could not be fetched",
+            results.unreachable.len()
+        )));
+    }
+
+    Ok(())
+}
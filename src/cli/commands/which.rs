@@ -0,0 +1,44 @@
+use console::style;
+use std::path::PathBuf;
+
+use crate::core::Repository;
+use crate::error::Result;
+use crate::fs::FileTracker;
+
+pub fn run_which(path: PathBuf) -> Result<()> {
+    let repo = Repository::open_default()?;
+    let tracker = FileTracker::new(&repo)?;
+    let path = path.canonicalize().unwrap_or(path);
+
+    let category = tracker.get_category(&path)?;
+    let copies = tracker.which(&path)?;
+
+    println!();
+    println!(
+        "{} {}",
+        style(path.display()).bold(),
+        style(format!("({})", category)).dim()
+    );
+
+    if copies.is_empty() {
+        println!("  {} Not stored in any layer", style("!").yellow().bold());
+        println!();
+        return Ok(());
+    }
+
+    // Layers are listed in the order they are resolved, the first one wins
+    let width = copies.iter().map(|c| c.layer.len()).max().unwrap_or(0);
+    for (i, copy) in copies.iter().enumerate() {
+        let marker = if i == 0 { "→" } else { " " };
+        println!(
+            "  {} {:width$}  {}",
+            style(marker).green().bold(),
+            style(&copy.layer).cyan(),
+            style(copy.path.display()).dim(),
+            width = width
+        );
+    }
+    println!();
+
+    Ok(())
+}
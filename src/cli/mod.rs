@@ -1,4 +1,4 @@
 pub mod args;
 pub mod commands;
 
-pub use args::{CategoryCommands, Cli, Commands};
+pub use args::{CategoryCommands, Cli, Commands, HostCommands, KeyCommands, SnapshotCommands};
@@ -4,9 +4,20 @@ use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 
-use crate::core::Repository;
+use crate::core::{BranchFile, Repository, SensitivePathPolicy};
 use crate::error::{ConfectError, Result};
 
+/// A secret value inside an otherwise plain file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlaceholderRule {
+    /// File the rule applies to (glob pattern supported)
+    pub path: String,
+    /// Regular expression whose first capture group is the secret value
+    pub pattern: String,
+    /// Name of the secret in the vault
+    pub name: String,
+}
+
 /// A category groups related configuration files together
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Category {
@@ -22,6 +33,28 @@ pub struct Category {
     /// Exclusion patterns
     #[serde(default)]
     pub exclude: Vec<String>,
+    /// age recipients for encrypted files (default: team recipients)
+    #[serde(default)]
+    pub recipients: Vec<String>,
+    /// Encrypt files with a passphrase instead of recipients
+    #[serde(default)]
+    pub passphrase: bool,
+    /// Store encrypted files under opaque IDs instead of their paths
+    #[serde(default)]
+    pub hide_paths: bool,
+    /// Patterns for unencrypted files allowed to contain possible secrets
+    #[serde(default)]
+    pub allow_secrets: Vec<String>,
+    /// Values replaced by `{{ secret "name" }}` placeholders in the repository
+    #[serde(default)]
+    pub placeholders: Vec<PlaceholderRule>,
+    /// Sensitive paths that are encrypted regardless of `encrypt`
+    #[serde(skip)]
+    pub policy: SensitivePathPolicy,
+    /// Directory the category directory is in, relative to the repository
+    /// root (`hosts/<name>` with the directory strategy)
+    #[serde(skip)]
+    pub root: PathBuf,
 }
 
 impl Category {
@@ -33,6 +66,13 @@ impl Category {
             paths: Vec::new(),
             encrypt: Vec::new(),
             exclude: Vec::new(),
+            recipients: Vec::new(),
+            passphrase: false,
+            hide_paths: false,
+            allow_secrets: Vec::new(),
+            placeholders: Vec::new(),
+            policy: SensitivePathPolicy::default(),
+            root: PathBuf::new(),
         }
     }
 
@@ -58,30 +98,59 @@ impl Category {
     }
 
     /// Check if a file should be encrypted
+    ///
+    /// Sensitive paths from the policy are always encrypted.
     pub fn should_encrypt(&self, path: &Path) -> bool {
         let path_str = path.to_string_lossy();
 
-        for pattern in &self.encrypt {
-            if let Ok(p) = Pattern::new(pattern) {
-                if p.matches(&path_str) {
-                    return true;
-                }
-            }
-        }
+        self.policy.requires_encryption(path)
+            || self
+                .encrypt
+                .iter()
+                .any(|pattern| pattern_covers_path(pattern, path, &path_str))
+    }
 
-        false
+    /// Check if an unencrypted file may contain possible secrets
+    pub fn allows_secrets(&self, path: &Path) -> bool {
+        let path_str = path.to_string_lossy();
+
+        self.allow_secrets
+            .iter()
+            .any(|pattern| pattern_covers_path(pattern, path, &path_str))
+    }
+
+    /// Get the placeholder rules for a file
+    pub fn placeholder_rules(&self, path: &Path) -> Vec<&PlaceholderRule> {
+        let path_str = path.to_string_lossy();
+
+        self.placeholders
+            .iter()
+            .filter(|rule| pattern_covers_path(&rule.path, path, &path_str))
+            .collect()
+    }
+
+    /// Get the directory of the category, relative to the repository root
+    pub fn dir(&self) -> PathBuf {
+        self.root.join(&self.name)
     }
 
     /// Get the repository path for a system path
     pub fn repo_path_for(&self, system_path: &Path) -> PathBuf {
+        self.root.join(self.layer_path_for(system_path))
+    }
+
+    /// Get the path for a system path inside a layer such as `main` or `common/`
+    pub fn layer_path_for(&self, system_path: &Path) -> PathBuf {
         // category_name/etc/nginx/nginx.conf
         let path_str = system_path.to_string_lossy();
         let relative = path_str.trim_start_matches('/');
         PathBuf::from(&self.name).join(relative)
     }
 
-    /// Get the system path from a repository path
+    /// Get the system path from a repository or layer path
     pub fn system_path_for(&self, repo_path: &Path) -> Option<PathBuf> {
+        let repo_path = repo_path.strip_prefix(&self.root).unwrap_or(repo_path);
+
         // Strip category prefix and add leading /
         let components: Vec<_> = repo_path.components().collect();
         if components.is_empty() {
@@ -98,7 +167,7 @@ impl Category {
     }
 }
 
-fn pattern_covers_path(pattern: &str, path: &Path, path_str: &str) -> bool {
+pub(crate) fn pattern_covers_path(pattern: &str, path: &Path, path_str: &str) -> bool {
     if has_glob_metachar(pattern) {
         return Pattern::new(pattern)
             .map(|p| p.matches(path_str))
@@ -140,6 +209,18 @@ mod tests {
         assert!(category.matches(Path::new("/etc/nginx/nginx.conf")));
         assert!(!category.matches(Path::new("/etc/nginx/cache/state.db")));
     }
+
+    #[test]
+    fn exact_encrypted_directory_path_covers_children() {
+        let mut category = Category::new("ssh");
+        category.paths.push("/etc/ssh".to_string());
+        category.encrypt.push("/etc/ssh/private".to_string());
+        category.encrypt.push("/etc/ssh/*_key".to_string());
+
+        assert!(category.should_encrypt(Path::new("/etc/ssh/private/id")));
+        assert!(category.should_encrypt(Path::new("/etc/ssh/ssh_host_rsa_key")));
+        assert!(!category.should_encrypt(Path::new("/etc/ssh/ssh_host_rsa_key.pub")));
+    }
 }
 
 /// Categories file structure
@@ -159,6 +240,16 @@ struct CategoryData {
     encrypt: Vec<String>,
     #[serde(default)]
     exclude: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    recipients: Vec<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    passphrase: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    hide_paths: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    allow_secrets: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    placeholders: Vec<PlaceholderRule>,
 }
 
 impl From<&Category> for CategoryData {
@@ -168,39 +259,73 @@ impl From<&Category> for CategoryData {
             paths: cat.paths.clone(),
             encrypt: cat.encrypt.clone(),
             exclude: cat.exclude.clone(),
+            recipients: cat.recipients.clone(),
+            passphrase: cat.passphrase,
+            hide_paths: cat.hide_paths,
+            allow_secrets: cat.allow_secrets.clone(),
+            placeholders: cat.placeholders.clone(),
         }
     }
 }
 
+/// Parse the content of a categories file
+fn parse_categories(
+    content: &str,
+    policy: &SensitivePathPolicy,
+    root: &Path,
+) -> Result<HashMap<String, Category>> {
+    let file: CategoriesFile = toml::from_str(content)?;
+
+    Ok(file
+        .categories
+        .into_iter()
+        .map(|(name, data)| {
+            let cat = Category {
+                name: name.clone(),
+                description: data.description,
+                paths: data.paths,
+                encrypt: data.encrypt,
+                exclude: data.exclude,
+                recipients: data.recipients,
+                passphrase: data.passphrase,
+                hide_paths: data.hide_paths,
+                allow_secrets: data.allow_secrets,
+                placeholders: data.placeholders,
+                policy: policy.clone(),
+                root: root.to_path_buf(),
+            };
+            (name, cat)
+        })
+        .collect())
+}
+
 /// Manages categories for a repository
 pub struct CategoryManager {
     categories: HashMap<String, Category>,
     repo_path: PathBuf,
+    policy: SensitivePathPolicy,
+    root: PathBuf,
 }
 
 impl CategoryManager {
     /// Load categories from repository
     pub fn load(repo: &Repository) -> Result<Self> {
+        Self::load_for_host(repo, repo.current_host()?)
+    }
+
+    /// Load categories with the files of another host
+    ///
+    /// Only differs from [`Self::load`] with the directory strategy, where
+    /// all hosts share the categories but keep their files apart.
+    pub fn load_for_host(repo: &Repository, host: &str) -> Result<Self> {
         let repo_path = repo.path().to_path_buf();
         let categories_file = repo_path.join(".confect").join("categories.toml");
+        let policy = SensitivePathPolicy::load(&repo_path)?;
+        let root = repo.host_dir(host);
 
         let categories = if categories_file.exists() {
             let content = fs::read_to_string(&categories_file)?;
-            let file: CategoriesFile = toml::from_str(&content)?;
-
-            file.categories
-                .into_iter()
-                .map(|(name, data)| {
-                    let cat = Category {
-                        name: name.clone(),
-                        description: data.description,
-                        paths: data.paths,
-                        encrypt: data.encrypt,
-                        exclude: data.exclude,
-                    };
-                    (name, cat)
-                })
-                .collect()
+            parse_categories(&content, &policy, &root)?
         } else {
             HashMap::new()
         };
@@ -208,6 +333,32 @@ impl CategoryManager {
         Ok(Self {
             categories,
             repo_path,
+            policy,
+            root,
+        })
+    }
+
+    /// Load the categories another branch defines, without checking it out
+    ///
+    /// The sensitive-path policy is taken from the working tree.
+    pub fn load_branch(repo: &Repository, branch: &str) -> Result<Self> {
+        let repo_path = repo.path().to_path_buf();
+        let policy = SensitivePathPolicy::load(&repo_path)?;
+        let root = PathBuf::new();
+
+        let categories =
+            match repo.read_branch_file(branch, Path::new(".confect/categories.toml"))? {
+                Some(BranchFile::Blob { content, .. }) => {
+                    parse_categories(&String::from_utf8_lossy(&content), &policy, &root)?
+                }
+                _ => HashMap::new(),
+            };
+
+        Ok(Self {
+            categories,
+            repo_path,
+            policy,
+            root,
         })
     }
 
@@ -255,11 +406,18 @@ impl CategoryManager {
             .ok_or_else(|| ConfectError::CategoryNotFound(name.to_string()))
     }
 
+    /// Get the sensitive-path policy of the repository
+    pub fn policy(&self) -> &SensitivePathPolicy {
+        &self.policy
+    }
+
     /// Add a new category
-    pub fn add(&mut self, category: Category) -> Result<()> {
+    pub fn add(&mut self, mut category: Category) -> Result<()> {
         if self.categories.contains_key(&category.name) {
             return Err(ConfectError::CategoryAlreadyExists(category.name.clone()));
         }
+        category.policy = self.policy.clone();
+        category.root = self.root.clone();
         self.categories.insert(category.name.clone(), category);
         Ok(())
     }
@@ -277,6 +435,13 @@ impl CategoryManager {
             paths,
             encrypt: Vec::new(),
             exclude: Vec::new(),
+            recipients: Vec::new(),
+            passphrase: false,
+            hide_paths: false,
+            allow_secrets: Vec::new(),
+            placeholders: Vec::new(),
+            policy: self.policy.clone(),
+            root: self.root.clone(),
         };
         self.add(cat)
     }
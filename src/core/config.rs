@@ -25,6 +25,9 @@ pub struct GlobalConfig {
     pub editor: Option<String>,
     #[serde(default)]
     pub repo_path: Option<PathBuf>,
+    /// How `pull` joins local commits with diverged remote ones
+    #[serde(default)]
+    pub pull: PullStrategy,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -35,16 +38,42 @@ pub struct EncryptionConfig {
     pub public_key: Option<String>,
     #[serde(default)]
     pub recipients_file: Option<PathBuf>,
+    /// Private key used for decryption (default: ~/.config/confect/age-key.txt)
+    #[serde(default)]
+    pub identity_file: Option<PathBuf>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct HostsConfig {
-    #[serde(default = "default_strategy")]
-    pub strategy: String,
+    /// Layout for new repositories
+    #[serde(default)]
+    pub strategy: HostStrategy,
     #[serde(default)]
     pub current: Option<String>,
 }
 
+/// How host-specific files are kept apart in a repository
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum HostStrategy {
+    /// A `host/<name>` branch per host, layered on `main`
+    #[default]
+    Branch,
+    /// `hosts/<name>/` directories on `main`, layered on `common/`
+    Directory,
+}
+
+/// How `pull` handles a branch that diverged from the remote
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PullStrategy {
+    /// Record a merge commit joining both histories
+    #[default]
+    Merge,
+    /// Replay local commits on top of the remote branch
+    Rebase,
+}
+
 fn default_remote() -> String {
     "origin".to_string()
 }
@@ -53,10 +82,6 @@ fn default_true() -> bool {
     true
 }
 
-fn default_strategy() -> String {
-    "branch".to_string()
-}
-
 impl Default for GlobalConfig {
     fn default() -> Self {
         Self {
@@ -64,25 +89,74 @@ impl Default for GlobalConfig {
             auto_push: true,
             editor: None,
             repo_path: None,
+            pull: PullStrategy::default(),
         }
     }
 }
 
-impl Default for HostsConfig {
-    fn default() -> Self {
-        Self {
-            strategy: default_strategy(),
-            current: None,
+impl std::str::FromStr for HostStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "branch" => Ok(Self::Branch),
+            "directory" => Ok(Self::Directory),
+            _ => Err(format!(
+                "unknown host strategy '{}' (expected 'branch' or 'directory')",
+                s
+            )),
         }
     }
 }
 
+impl EncryptionConfig {
+    /// Get the path to the age identity (private key) file
+    pub fn identity_path(&self) -> Result<PathBuf> {
+        match &self.identity_file {
+            Some(path) => Ok(path.clone()),
+            None => Ok(Config::config_dir()?.join("age-key.txt")),
+        }
+    }
+
+    /// Get the identity file used for decryption
+    ///
+    /// Without an explicit `identity_file`, the confect age key is preferred and
+    /// the user's SSH key is used as a fallback.
+    pub fn decryption_identity_path(&self) -> Result<PathBuf> {
+        let age_key = self.identity_path()?;
+        if self.identity_file.is_some() || age_key.exists() {
+            return Ok(age_key);
+        }
+
+        if let Some(home) = dirs::home_dir() {
+            for name in ["id_ed25519", "id_rsa"] {
+                let ssh_key = home.join(".ssh").join(name);
+                if ssh_key.exists() {
+                    return Ok(ssh_key);
+                }
+            }
+        }
+
+        Ok(age_key)
+    }
+
+    /// Get the path to the public key file stored next to the identity
+    pub fn public_key_path(&self) -> Result<PathBuf> {
+        Ok(self.identity_path()?.with_file_name("age-pubkey.txt"))
+    }
+}
+
 impl Config {
-    /// Get the path to the global config file
-    pub fn global_path() -> Result<PathBuf> {
+    /// Get the confect config directory (~/.config/confect)
+    pub fn config_dir() -> Result<PathBuf> {
         let config_dir = dirs::config_dir()
             .ok_or_else(|| ConfectError::Config("Could not find config directory".to_string()))?;
-        Ok(config_dir.join("confect").join("config.toml"))
+        Ok(config_dir.join("confect"))
+    }
+
+    /// Get the path to the global config file
+    pub fn global_path() -> Result<PathBuf> {
+        Ok(Self::config_dir()?.join("config.toml"))
     }
 
     /// Load global configuration
@@ -147,6 +221,8 @@ pub struct RepoConfig {
     pub repository: RepoMeta,
     #[serde(default)]
     pub hosts: RepoHostsConfig,
+    #[serde(default, skip_serializing_if = "PolicyConfig::is_empty")]
+    pub policy: PolicyConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -163,8 +239,8 @@ fn default_version() -> u32 {
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct RepoHostsConfig {
-    #[serde(default = "default_strategy")]
-    pub strategy: String,
+    #[serde(default)]
+    pub strategy: HostStrategy,
     #[serde(default)]
     pub list: std::collections::HashMap<String, HostEntry>,
 }
@@ -172,9 +248,37 @@ pub struct RepoHostsConfig {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HostEntry {
     pub branch: String,
+    /// Groups the host inherits files from, most specific first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct PolicyConfig {
+    /// Extra patterns for files that must always be encrypted
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub encrypt: Vec<String>,
+}
+
+impl PolicyConfig {
+    fn is_empty(&self) -> bool {
+        self.encrypt.is_empty()
+    }
 }
 
 impl RepoConfig {
+    /// Load from repository path (defaults if the file does not exist)
+    pub fn load(repo_path: &Path) -> Result<Self> {
+        let config_path = repo_path.join(".confect").join("config.toml");
+
+        if !config_path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(&config_path)?;
+        Ok(toml::from_str(&content)?)
+    }
+
     /// Save to repository path
     pub fn save(&self, repo_path: &Path) -> Result<()> {
         let confect_dir = repo_path.join(".confect");
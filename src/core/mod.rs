@@ -1,7 +1,12 @@
 mod category;
 mod config;
+mod policy;
 mod repository;
 
-pub use category::{Category, CategoryManager};
-pub use config::Config;
-pub use repository::Repository;
+pub use category::{Category, CategoryManager, PlaceholderRule};
+pub use config::{Config, EncryptionConfig, HostEntry, HostStrategy, PullStrategy, RepoConfig};
+pub use policy::SensitivePathPolicy;
+pub use repository::{
+    group_branch, BranchFile, ConflictSide, FileChange, HostBase, HostInfo, LogEntry, MergeOutcome,
+    Repository, Revision, Snapshot, BASE_BRANCH, COMMON_DIR, GROUPS_DIR,
+};
@@ -0,0 +1,107 @@
+use std::path::Path;
+
+use crate::core::category::pattern_covers_path;
+use crate::core::config::RepoConfig;
+use crate::error::Result;
+
+/// Paths that must never be stored in plaintext
+///
+/// `~/` patterns cover `/root` and every home directory under `/home`.
+pub const DEFAULT_SENSITIVE_PATHS: &[&str] = &[
+    "/etc/shadow",
+    "/etc/shadow-",
+    "/etc/gshadow",
+    "/etc/gshadow-",
+    "/etc/ssh/ssh_host_*_key",
+    "/etc/ssl/private",
+    "/etc/wireguard/*.conf",
+    "/etc/NetworkManager/system-connections",
+    "~/.aws/credentials",
+    "~/.ssh/id_dsa",
+    "~/.ssh/id_ecdsa",
+    "~/.ssh/id_ed25519",
+    "~/.ssh/id_rsa",
+    "~/.gnupg/private-keys-v1.d",
+    "~/.netrc",
+    "~/.pgpass",
+    "~/.config/confect/age-key.txt",
+];
+
+/// Policy deciding which paths are always encrypted, whatever their category
+#[derive(Debug, Clone)]
+pub struct SensitivePathPolicy {
+    patterns: Vec<String>,
+}
+
+impl Default for SensitivePathPolicy {
+    fn default() -> Self {
+        Self::new(DEFAULT_SENSITIVE_PATHS.iter().map(|p| p.to_string()))
+    }
+}
+
+impl SensitivePathPolicy {
+    /// Create a policy from patterns, expanding `~/` to home directories
+    pub fn new(patterns: impl IntoIterator<Item = String>) -> Self {
+        let mut expanded = Vec::new();
+
+        for pattern in patterns {
+            match pattern.strip_prefix("~/") {
+                Some(rest) => {
+                    expanded.push(format!("/root/{}", rest));
+                    expanded.push(format!("/home/*/{}", rest));
+                    if let Some(home) = dirs::home_dir() {
+                        expanded.push(home.join(rest).to_string_lossy().to_string());
+                    }
+                }
+                None => expanded.push(pattern),
+            }
+        }
+
+        expanded.sort();
+        expanded.dedup();
+
+        Self { patterns: expanded }
+    }
+
+    /// Load the default policy extended by `[policy] encrypt` in the repo config
+    pub fn load(repo_path: &Path) -> Result<Self> {
+        let config = RepoConfig::load(repo_path)?;
+
+        Ok(Self::new(
+            DEFAULT_SENSITIVE_PATHS
+                .iter()
+                .map(|p| p.to_string())
+                .chain(config.policy.encrypt),
+        ))
+    }
+
+    /// Check if a path must be encrypted
+    pub fn requires_encryption(&self, path: &Path) -> bool {
+        let path_str = path.to_string_lossy();
+
+        self.patterns
+            .iter()
+            .any(|pattern| pattern_covers_path(pattern, path, &path_str))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SensitivePathPolicy;
+    use std::path::Path;
+
+    #[test]
+    fn default_policy_covers_system_and_home_secrets() {
+        let policy = SensitivePathPolicy::default();
+
+        assert!(policy.requires_encryption(Path::new("/etc/shadow")));
+        assert!(policy.requires_encryption(Path::new("/etc/ssh/ssh_host_ed25519_key")));
+        assert!(policy.requires_encryption(Path::new("/etc/wireguard/wg0.conf")));
+        assert!(policy.requires_encryption(Path::new("/home/alice/.aws/credentials")));
+        assert!(policy.requires_encryption(Path::new("/root/.ssh/id_ed25519")));
+
+        assert!(!policy.requires_encryption(Path::new("/etc/passwd")));
+        assert!(!policy.requires_encryption(Path::new("/etc/ssh/ssh_host_ed25519_key.pub")));
+        assert!(!policy.requires_encryption(Path::new("/home/alice/.ssh/id_ed25519.pub")));
+    }
+}
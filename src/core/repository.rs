@@ -1,24 +1,178 @@
-use chrono::Utc;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use git2::build::RepoBuilder;
 use git2::{
-    Cred, CredentialType, FetchOptions, PushOptions, RemoteCallbacks, Repository as Git2Repo,
-    Signature, StatusOptions,
+    AnnotatedCommit, BranchType, Commit, Cred, CredentialType, DiffOptions, ErrorCode,
+    FetchOptions, Index, IndexEntry, IndexTime, ObjectType, Oid, PushOptions, Rebase,
+    RemoteCallbacks, Repository as Git2Repo, RepositoryInitOptions, RepositoryState, Signature,
+    StatusOptions, TreeWalkMode, TreeWalkResult,
 };
+use std::collections::HashSet;
+use std::ffi::OsString;
 use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
 
-use crate::core::config::{Config, HostEntry, RepoConfig};
+use crate::core::config::{Config, HostEntry, HostStrategy, PullStrategy, RepoConfig};
 use crate::error::{ConfectError, Result};
 
+/// Branch holding configs shared by all hosts, overlaid by host branches
+pub const BASE_BRANCH: &str = "main";
+
+/// Directory holding configs shared by all hosts with the directory strategy
+pub const COMMON_DIR: &str = "common";
+
+/// Directory holding group directories with the directory strategy
+pub const GROUPS_DIR: &str = "groups";
+
+/// A file read from a branch without checking it out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BranchFile {
+    Blob { content: Vec<u8>, executable: bool },
+    Symlink(PathBuf),
+}
+
+/// Result of syncing one branch with every remote
+#[derive(Debug, Default)]
+pub struct BranchSync {
+    pub branch: String,
+    /// Remote the local branch was fast-forwarded from
+    pub fast_forwarded: Option<String>,
+    /// Remotes the branch was pushed to
+    pub pushed: Vec<String>,
+    /// Remotes whose branch has diverged from the local one
+    pub diverged: Vec<String>,
+    /// Error that stopped syncing the branch
+    pub error: Option<ConfectError>,
+}
+
+impl BranchSync {
+    /// Check if the branch matches on all remotes
+    pub fn is_up_to_date(&self) -> bool {
+        self.fast_forwarded.is_none()
+            && self.pushed.is_empty()
+            && self.diverged.is_empty()
+            && self.error.is_none()
+    }
+}
+
+/// Result of syncing every host branch, see [`Repository::sync_all_hosts`]
+#[derive(Debug, Default)]
+pub struct HostsSync {
+    /// Remotes that could not be fetched, left out of the sync
+    pub unreachable: Vec<(String, ConfectError)>,
+    pub branches: Vec<BranchSync>,
+}
+
+/// Result of merging a branch into the checked-out branch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeOutcome {
+    UpToDate,
+    FastForward,
+    Merged,
+    /// Local commits were replayed on top of the other branch
+    Rebased,
+    /// Paths, relative to the repository root, that could not be merged
+    Conflicts(Vec<PathBuf>),
+}
+
+/// A commit listed by [`Repository::log`]
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub id: Oid,
+    pub time: DateTime<Utc>,
+    /// Branch the commit was made on, or the host directory it changed
+    pub branch: String,
+    pub summary: String,
+    /// Files changed since the first parent, empty for merges
+    pub files: Vec<FileChange>,
+}
+
+/// A commit named by [`Repository::resolve_revision`]
+#[derive(Debug, Clone)]
+pub struct Revision {
+    pub id: Oid,
+    pub time: DateTime<Utc>,
+    pub summary: String,
+}
+
+/// A named snapshot of a host's branch, stored as an annotated tag
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub name: String,
+    /// Full tag name, `snapshot/<host>/<name>`
+    pub tag: String,
+    /// Commit the tag points to
+    pub id: Oid,
+    /// When the snapshot was taken
+    pub time: DateTime<Utc>,
+    pub message: String,
+}
+
+/// A file changed by a commit
+#[derive(Debug, Clone)]
+pub struct FileChange {
+    /// Path relative to the repository root
+    pub path: PathBuf,
+    pub insertions: usize,
+    pub deletions: usize,
+    pub binary: bool,
+    /// Unified diff of the change, when asked for
+    pub patch: Option<String>,
+}
+
+/// One version of a file with a conflict, seen from this machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictSide {
+    /// The last version both sides share
+    Base,
+    /// The local version
+    Mine,
+    /// The version pulled from the remote
+    Theirs,
+}
+
+/// What a host added with [`Repository::bootstrap`] starts from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HostBase {
+    /// Another host's files and groups
+    Host(String),
+    /// A branch, usually `main`
+    Branch(String),
+}
+
+/// A host in the repository config
+#[derive(Debug, Clone)]
+pub struct HostInfo {
+    pub name: String,
+    pub branch: String,
+    /// Time of the last commit on the branch, locally or on a remote
+    pub last_commit: Option<DateTime<Utc>>,
+    /// Summary line of the last commit
+    pub last_message: Option<String>,
+    /// Remotes that have the branch
+    pub remotes: Vec<String>,
+    /// Groups the host inherits files from, most specific first
+    pub groups: Vec<String>,
+}
+
 /// Wrapper around git2::Repository with confect-specific functionality
 pub struct Repository {
     git: Git2Repo,
     path: PathBuf,
     hostname: String,
+    strategy: HostStrategy,
 }
 
 impl Repository {
-    /// Initialize a new confect repository
+    /// Initialize a new confect repository with a branch per host
     pub fn init(path: &Path, hostname: &str) -> Result<Self> {
+        Self::init_with_strategy(path, hostname, HostStrategy::Branch)
+    }
+
+    /// Initialize a new confect repository
+    pub fn init_with_strategy(path: &Path, hostname: &str, strategy: HostStrategy) -> Result<Self> {
         if path.join(".git").exists() {
             return Err(ConfectError::AlreadyInitialized(path.to_path_buf()));
         }
@@ -26,8 +180,9 @@ impl Repository {
         // Create directory if needed
         fs::create_dir_all(path)?;
 
-        // Initialize git repository
-        let git = Git2Repo::init(path)?;
+        // Initialize git repository, with shared configs on main
+        let git =
+            Git2Repo::init_opts(path, RepositoryInitOptions::new().initial_head(BASE_BRANCH))?;
 
         // Create .confect directory structure
         let confect_dir = path.join(".confect");
@@ -36,10 +191,12 @@ impl Repository {
         // Create initial repo config
         let mut repo_config = RepoConfig::default();
         repo_config.repository.created = Some(Utc::now().to_rfc3339());
+        repo_config.hosts.strategy = strategy;
         repo_config.hosts.list.insert(
             hostname.to_string(),
             HostEntry {
-                branch: format!("host/{}", hostname),
+                branch: host_branch(strategy, hostname),
+                groups: Vec::new(),
             },
         );
         repo_config.save(path)?;
@@ -47,9 +204,6 @@ impl Repository {
         // Create empty categories file
         fs::write(confect_dir.join("categories.toml"), "[categories]\n")?;
 
-        // Create empty metadata file
-        fs::write(confect_dir.join("metadata.toml"), "[files]\n")?;
-
         // Create .gitignore
         fs::write(path.join(".gitignore"), "*.confect-backup\n")?;
 
@@ -57,17 +211,174 @@ impl Repository {
             git,
             path: path.to_path_buf(),
             hostname: hostname.to_string(),
+            strategy,
         };
 
+        // Create empty metadata file
+        let state_dir = repo.state_dir();
+        fs::create_dir_all(&state_dir)?;
+        fs::write(state_dir.join("metadata.toml"), "[files]\n")?;
+
         // Create initial commit on main branch
         repo.commit_all("Initialize confect repository")?;
 
         // Create and switch to host branch
-        repo.create_host_branch(hostname)?;
+        if strategy == HostStrategy::Branch {
+            repo.create_host_branch(hostname)?;
+        }
 
         Ok(repo)
     }
 
+    /// Clone an existing repository and register this machine as a new host
+    ///
+    /// With the branch strategy `host/<hostname>` is created from `base` and
+    /// checked out. With the directory strategy a base host's directory is
+    /// copied to `hosts/<hostname>` on `main`. The registration is committed
+    /// but not pushed.
+    ///
+    /// Returns `None` when the remote has no branches yet. Nothing is left
+    /// behind at `path` then, or if this fails.
+    pub fn bootstrap(
+        url: &str,
+        path: &Path,
+        hostname: &str,
+        base: &HostBase,
+    ) -> Result<Option<Self>> {
+        if path.join(".git").exists() {
+            return Err(ConfectError::AlreadyInitialized(path.to_path_buf()));
+        }
+
+        // An existing directory keeps what it held, only the clone is removed
+        let existing: Option<HashSet<OsString>> = if path.exists() {
+            Some(
+                fs::read_dir(path)?
+                    .map(|entry| Ok(entry?.file_name()))
+                    .collect::<io::Result<_>>()?,
+            )
+        } else {
+            None
+        };
+
+        let result = Self::clone_as_host(url, path, hostname, base);
+        if !matches!(result, Ok(Some(_))) {
+            match existing {
+                Some(existing) => {
+                    for entry in fs::read_dir(path)?.flatten() {
+                        if existing.contains(&entry.file_name()) {
+                            continue;
+                        }
+                        let _ = if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                            fs::remove_dir_all(entry.path())
+                        } else {
+                            fs::remove_file(entry.path())
+                        };
+                    }
+                }
+                None => {
+                    let _ = fs::remove_dir_all(path);
+                }
+            }
+        }
+        result
+    }
+
+    fn clone_as_host(
+        url: &str,
+        path: &Path,
+        hostname: &str,
+        base: &HostBase,
+    ) -> Result<Option<Self>> {
+        let mut fetch_options = FetchOptions::new();
+        fetch_options.remote_callbacks(create_credentials_callback());
+        let git = RepoBuilder::new()
+            .fetch_options(fetch_options)
+            .clone(url, path)?;
+
+        let mut repo = Self {
+            git,
+            path: path.to_path_buf(),
+            hostname: hostname.to_string(),
+            strategy: HostStrategy::default(),
+        };
+        if repo.git.branches(None)?.next().is_none() {
+            return Ok(None);
+        }
+
+        // Shared configs are on main, whatever the remote's HEAD points to
+        if !repo.has_branch(BASE_BRANCH)? {
+            return Err(ConfectError::Other(format!(
+                "{} has no '{}' branch, is it a confect repository?",
+                url, BASE_BRANCH
+            )));
+        }
+        repo.checkout_branch(BASE_BRANCH)?;
+        let config = RepoConfig::load(path)?;
+        repo.strategy = config.hosts.strategy;
+
+        let branch = host_branch(repo.strategy, hostname);
+        if config.hosts.list.contains_key(hostname)
+            || (repo.strategy == HostStrategy::Branch && repo.has_branch(&branch)?)
+        {
+            return Err(ConfectError::Other(format!(
+                "Host '{}' already exists in the repository, pick another name with --host",
+                hostname
+            )));
+        }
+
+        let groups = match (repo.strategy, base) {
+            (HostStrategy::Branch, HostBase::Host(name)) => {
+                let base_branch = host_branch(repo.strategy, name);
+                if !repo.has_branch(&base_branch)? {
+                    return Err(ConfectError::HostNotFound(name.clone()));
+                }
+                repo.create_branch_from(&branch, &base_branch)?;
+                repo.host_groups(name)?
+            }
+            (HostStrategy::Branch, HostBase::Branch(base_branch)) => {
+                repo.create_branch_from(&branch, base_branch)?;
+                Vec::new()
+            }
+            (HostStrategy::Directory, HostBase::Host(name)) => {
+                let entry = config
+                    .hosts
+                    .list
+                    .get(name)
+                    .ok_or_else(|| ConfectError::HostNotFound(name.clone()))?;
+                copy_dir(
+                    &path.join(repo.host_dir(name)),
+                    &path.join(repo.host_dir(hostname)),
+                )?;
+                entry.groups.clone()
+            }
+            (HostStrategy::Directory, HostBase::Branch(base_branch)) => {
+                if base_branch != BASE_BRANCH {
+                    return Err(ConfectError::Other(format!(
+                        "All hosts are on '{}' with the directory strategy, use --from-host instead",
+                        BASE_BRANCH
+                    )));
+                }
+                Vec::new()
+            }
+        };
+
+        let mut config = RepoConfig::load(path)?;
+        config
+            .hosts
+            .list
+            .insert(hostname.to_string(), HostEntry { branch, groups });
+        config.save(path)?;
+
+        let host_dir = repo.host_dir(hostname);
+        let mut paths = vec![Path::new(".confect/config.toml")];
+        if repo.strategy == HostStrategy::Directory {
+            paths.push(host_dir.as_path());
+        }
+        repo.commit_paths(&paths, &format!("Add host {}", hostname))?;
+
+        Ok(Some(repo))
+    }
+
     /// Open an existing repository
     pub fn open(path: &Path) -> Result<Self> {
         if !path.join(".git").exists() {
@@ -88,10 +399,13 @@ impl Repository {
                 .unwrap_or_else(|_| "unknown".to_string())
         });
 
+        let strategy = RepoConfig::load(path)?.hosts.strategy;
+
         Ok(Self {
             git,
             path: path.to_path_buf(),
             hostname,
+            strategy,
         })
     }
 
@@ -112,6 +426,203 @@ impl Repository {
         Ok(&self.hostname)
     }
 
+    /// Get the name of the checked-out branch
+    pub fn current_branch(&self) -> Result<String> {
+        let head = self.git.head()?;
+        Ok(head.shorthand().unwrap_or(BASE_BRANCH).to_string())
+    }
+
+    /// Get how host-specific files are kept apart
+    pub fn strategy(&self) -> HostStrategy {
+        self.strategy
+    }
+
+    /// Get the directory holding a host's categories, relative to the repository root
+    ///
+    /// Empty with the branch strategy, where each host has the root of its
+    /// own branch.
+    pub fn host_dir(&self, host: &str) -> PathBuf {
+        match self.strategy {
+            HostStrategy::Branch => PathBuf::new(),
+            HostStrategy::Directory => Path::new("hosts").join(host),
+        }
+    }
+
+    /// Get the directory holding the current host's metadata, vault and indexes
+    pub fn state_dir(&self) -> PathBuf {
+        self.path
+            .join(self.host_dir(&self.hostname))
+            .join(".confect")
+    }
+
+    /// Get the branch layered under the checked-out host branch
+    ///
+    /// `None` when `main` does not exist or is itself checked out.
+    pub fn base_branch(&self) -> Option<&'static str> {
+        let refname = format!("refs/heads/{}", BASE_BRANCH);
+        let head = self.git.head().ok()?;
+
+        (self.git.refname_to_id(&refname).is_ok() && head.name() != Some(refname.as_str()))
+            .then_some(BASE_BRANCH)
+    }
+
+    /// Read a file from the tip of a branch
+    ///
+    /// `path` is relative to the repository root. Branches that only exist
+    /// on a remote are read from there.
+    pub fn read_branch_file(&self, branch: &str, path: &Path) -> Result<Option<BranchFile>> {
+        read_tree_file(&self.git, &self.branch_tree(branch)?, path)
+    }
+
+    /// List the files under a directory on a branch, relative to the repository root
+    pub fn list_branch_files(&self, branch: &str, dir: &Path) -> Result<Vec<PathBuf>> {
+        list_tree_files(&self.git, &self.branch_tree(branch)?, dir)
+    }
+
+    /// Read a file as a commit stored it, `None` if it did not exist then
+    pub fn read_commit_file(&self, id: Oid, path: &Path) -> Result<Option<BranchFile>> {
+        read_tree_file(&self.git, &self.git.find_commit(id)?.tree()?, path)
+    }
+
+    /// List the files under a directory at a commit, relative to the repository root
+    pub fn list_commit_files(&self, id: Oid, dir: &Path) -> Result<Vec<PathBuf>> {
+        list_tree_files(&self.git, &self.git.find_commit(id)?.tree()?, dir)
+    }
+
+    /// Find the commit a revision, tag or date names
+    ///
+    /// Anything git understands, such as `HEAD~3`, a tag or a commit id, is
+    /// tried first. A date (`2026-10-01`, `2026-10-01 14:30` or RFC 3339)
+    /// names the last commit of the checked-out branch made by then; a day
+    /// without a time includes the whole day.
+    pub fn resolve_revision(&self, spec: &str) -> Result<Revision> {
+        let commit = match self.git.revparse_single(spec) {
+            Ok(object) => object.peel_to_commit()?,
+            Err(_) => {
+                let cutoff = parse_date(spec).ok_or_else(|| {
+                    ConfectError::Other(format!("Unknown revision or date: {}", spec))
+                })?;
+                self.last_commit_before(cutoff)?
+                    .ok_or_else(|| ConfectError::Other(format!("No commits made by {}", spec)))?
+            }
+        };
+
+        Ok(Revision {
+            id: commit.id(),
+            time: Utc
+                .timestamp_opt(commit.time().seconds(), 0)
+                .single()
+                .unwrap_or_default(),
+            summary: commit.summary().unwrap_or_default().to_string(),
+        })
+    }
+
+    /// Tag the checked-out commit as a snapshot of the current host
+    pub fn create_snapshot(&self, name: &str, message: &str) -> Result<Snapshot> {
+        let tag = snapshot_tag(self.current_host()?, name);
+        if !git2::Reference::is_valid_name(&format!("refs/tags/{}", tag)) {
+            return Err(ConfectError::Other(format!(
+                "Invalid snapshot name: {}",
+                name
+            )));
+        }
+        if self
+            .git
+            .find_reference(&format!("refs/tags/{}", tag))
+            .is_ok()
+        {
+            return Err(ConfectError::Other(format!(
+                "Snapshot '{}' already exists",
+                name
+            )));
+        }
+
+        let head = self.git.head()?.peel_to_commit()?;
+        let sig = self.signature()?;
+        self.git.tag(&tag, head.as_object(), &sig, message, false)?;
+
+        self.snapshot(name)
+    }
+
+    /// List the current host's snapshots, oldest first
+    pub fn list_snapshots(&self) -> Result<Vec<Snapshot>> {
+        let prefix = snapshot_tag(self.current_host()?, "");
+        let mut snapshots = Vec::new();
+        for tag in self
+            .git
+            .tag_names(Some(&format!("{}*", prefix)))?
+            .iter()
+            .flatten()
+        {
+            if let Some(name) = tag.strip_prefix(&prefix) {
+                snapshots.push(self.snapshot(name)?);
+            }
+        }
+        snapshots.sort_by_key(|snapshot| snapshot.time);
+        Ok(snapshots)
+    }
+
+    /// Find one of the current host's snapshots by name
+    pub fn snapshot(&self, name: &str) -> Result<Snapshot> {
+        let tag = snapshot_tag(self.current_host()?, name);
+        let reference = self
+            .git
+            .find_reference(&format!("refs/tags/{}", tag))
+            .map_err(|_| ConfectError::Other(format!("Snapshot '{}' not found", name)))?;
+        let commit = reference.peel_to_commit()?;
+
+        // Lightweight tags made by hand carry the commit's date and message
+        let (time, message) = match reference.peel_to_tag() {
+            Ok(annotated) => (
+                annotated
+                    .tagger()
+                    .map(|tagger| tagger.when())
+                    .unwrap_or_else(|| commit.time()),
+                annotated.message().unwrap_or_default().trim().to_string(),
+            ),
+            Err(_) => (
+                commit.time(),
+                commit.summary().unwrap_or_default().to_string(),
+            ),
+        };
+
+        Ok(Snapshot {
+            name: name.to_string(),
+            tag,
+            id: commit.id(),
+            time: Utc
+                .timestamp_opt(time.seconds(), 0)
+                .single()
+                .unwrap_or_default(),
+            message,
+        })
+    }
+
+    /// Find the newest commit of the checked-out branch made at or before a time
+    fn last_commit_before(&self, cutoff: DateTime<Utc>) -> Result<Option<Commit<'_>>> {
+        let mut revwalk = self.git.revwalk()?;
+        revwalk.push_head()?;
+        revwalk.set_sorting(git2::Sort::TIME)?;
+
+        for id in revwalk {
+            let commit = self.git.find_commit(id?)?;
+            if commit.time().seconds() <= cutoff.timestamp() {
+                return Ok(Some(commit));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Check if a branch exists locally or on a remote
+    pub fn has_branch(&self, branch: &str) -> Result<bool> {
+        Ok(self.branch_tip(branch)?.is_some())
+    }
+
+    fn branch_tree(&self, branch: &str) -> Result<git2::Tree<'_>> {
+        Ok(self.branch_commit(branch)?.tree()?)
+    }
+
     /// Create a branch for a host
     fn create_host_branch(&self, hostname: &str) -> Result<()> {
         let branch_name = format!("host/{}", hostname);
@@ -132,6 +643,386 @@ impl Repository {
         Ok(())
     }
 
+    /// List the hosts in the repository config, sorted by name
+    pub fn list_hosts(&self) -> Result<Vec<HostInfo>> {
+        let config = RepoConfig::load(&self.path)?;
+        let mut hosts = config
+            .hosts
+            .list
+            .into_iter()
+            .map(|(name, entry)| self.host_info(name, entry))
+            .collect::<Result<Vec<_>>>()?;
+        hosts.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(hosts)
+    }
+
+    /// Get a host from the repository config
+    pub fn host(&self, name: &str) -> Result<HostInfo> {
+        let config = RepoConfig::load(&self.path)?;
+        let entry = config
+            .hosts
+            .list
+            .get(name)
+            .ok_or_else(|| ConfectError::HostNotFound(name.to_string()))?;
+
+        self.host_info(name.to_string(), entry.clone())
+    }
+
+    /// Get the groups a host inherits files from, most specific first
+    ///
+    /// Hosts missing from the repository config have no groups.
+    pub fn host_groups(&self, name: &str) -> Result<Vec<String>> {
+        let config = RepoConfig::load(&self.path)?;
+        Ok(config
+            .hosts
+            .list
+            .get(name)
+            .map(|entry| entry.groups.clone())
+            .unwrap_or_default())
+    }
+
+    fn host_info(&self, name: String, entry: HostEntry) -> Result<HostInfo> {
+        let HostEntry { branch, groups } = entry;
+        let mut remotes = Vec::new();
+        for (remote, _) in self.list_remotes()? {
+            let refname = format!("refs/remotes/{}/{}", remote, branch);
+            if self.git.refname_to_id(&refname).is_ok() {
+                remotes.push(remote);
+            }
+        }
+
+        let tip = match self.strategy {
+            HostStrategy::Branch => self.branch_tip(&branch)?,
+            HostStrategy::Directory => self.last_commit_touching(&branch, &self.host_dir(&name))?,
+        };
+        let last_commit = tip
+            .as_ref()
+            .and_then(|commit| Utc.timestamp_opt(commit.time().seconds(), 0).single());
+        let last_message = tip
+            .as_ref()
+            .and_then(|commit| commit.summary().map(str::to_string));
+
+        Ok(HostInfo {
+            name,
+            branch,
+            last_commit,
+            last_message,
+            remotes,
+            groups,
+        })
+    }
+
+    /// Find the newest commit on a branch that changed a path
+    fn last_commit_touching(&self, branch: &str, path: &Path) -> Result<Option<Commit<'_>>> {
+        let Some(tip) = self.branch_tip(branch)? else {
+            return Ok(None);
+        };
+
+        let entry_id = |commit: &Commit| -> Result<Option<Oid>> {
+            Ok(commit.tree()?.get_path(path).ok().map(|entry| entry.id()))
+        };
+
+        let mut revwalk = self.git.revwalk()?;
+        revwalk.push(tip.id())?;
+        for id in revwalk {
+            let commit = self.git.find_commit(id?)?;
+            let current = entry_id(&commit)?;
+            let previous = match commit.parents().next() {
+                Some(parent) => entry_id(&parent)?,
+                None => None,
+            };
+            if current != previous {
+                return Ok(Some(commit));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// List the commits of the checked-out branch that change files under
+    /// `paths`, newest first
+    ///
+    /// `paths` are relative to the repository root; without any, every commit
+    /// is listed. Merges are then listed without files, and skipped when
+    /// filtering by path.
+    pub fn log(
+        &self,
+        paths: &[PathBuf],
+        patch: bool,
+        limit: Option<usize>,
+    ) -> Result<Vec<LogEntry>> {
+        let mut branches = Vec::new();
+        for branch in self.git.branches(Some(BranchType::Local))? {
+            let (branch, _) = branch?;
+            if let (Some(name), Some(id)) = (branch.name()?, branch.get().target()) {
+                branches.push((name.to_string(), id));
+            }
+        }
+
+        let mut revwalk = self.git.revwalk()?;
+        revwalk.push_head()?;
+        revwalk.set_sorting(git2::Sort::TIME)?;
+
+        let mut entries = Vec::new();
+        for id in revwalk {
+            if limit.is_some_and(|limit| entries.len() >= limit) {
+                break;
+            }
+
+            let commit = self.git.find_commit(id?)?;
+            let merge = commit.parent_count() > 1;
+            if merge && !paths.is_empty() {
+                continue;
+            }
+
+            let files = if merge {
+                Vec::new()
+            } else {
+                let parent_tree = match commit.parents().next() {
+                    Some(parent) => Some(parent.tree()?),
+                    None => None,
+                };
+                let mut options = DiffOptions::new();
+                for path in paths {
+                    options.pathspec(path);
+                }
+                let diff = self.git.diff_tree_to_tree(
+                    parent_tree.as_ref(),
+                    Some(&commit.tree()?),
+                    Some(&mut options),
+                )?;
+                file_changes(&diff, patch)?
+            };
+            if files.is_empty() && !paths.is_empty() {
+                continue;
+            }
+
+            let branch = match self.strategy {
+                HostStrategy::Branch => self.commit_branch(commit.id(), &branches)?,
+                HostStrategy::Directory => files
+                    .first()
+                    .map_or_else(|| BASE_BRANCH.to_string(), |file| layer_dir(&file.path)),
+            };
+            entries.push(LogEntry {
+                id: commit.id(),
+                time: Utc
+                    .timestamp_opt(commit.time().seconds(), 0)
+                    .single()
+                    .unwrap_or_default(),
+                branch,
+                summary: commit.summary().unwrap_or_default().to_string(),
+                files,
+            });
+        }
+
+        Ok(entries)
+    }
+
+    /// Name the branch a commit was made on
+    ///
+    /// Commits `main` or a group contains are shared there; others belong to
+    /// the host branches that contain them.
+    fn commit_branch(&self, id: Oid, branches: &[(String, Oid)]) -> Result<String> {
+        let mut containing = Vec::new();
+        for (name, tip) in branches {
+            if *tip == id || self.git.graph_descendant_of(*tip, id)? {
+                containing.push(name.as_str());
+            }
+        }
+
+        let layer = containing
+            .iter()
+            .find(|name| **name == BASE_BRANCH)
+            .or_else(|| containing.iter().find(|name| name.starts_with("group/")));
+        Ok(match layer {
+            Some(name) => name.to_string(),
+            None if containing.is_empty() => self.current_branch()?,
+            None => containing.join(", "),
+        })
+    }
+
+    /// Get the tip of a branch, falling back to the first remote that has it
+    fn branch_tip(&self, branch: &str) -> Result<Option<Commit<'_>>> {
+        if let Ok(local) = self.git.refname_to_id(&format!("refs/heads/{}", branch)) {
+            return Ok(Some(self.git.find_commit(local)?));
+        }
+
+        for (remote, _) in self.list_remotes()? {
+            let refname = format!("refs/remotes/{}/{}", remote, branch);
+            if let Ok(id) = self.git.refname_to_id(&refname) {
+                return Ok(Some(self.git.find_commit(id)?));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Get the tip of a branch, failing if it does not exist anywhere
+    fn branch_commit(&self, branch: &str) -> Result<Commit<'_>> {
+        self.branch_tip(branch)?.ok_or_else(|| {
+            ConfectError::Other(format!(
+                "Branch '{}' not found locally or on any remote",
+                branch
+            ))
+        })
+    }
+
+    /// Check out another host's branch and act as that host
+    ///
+    /// The branch is created from a remote if it only exists there. The
+    /// repository must not have uncommitted changes. With the directory
+    /// strategy nothing needs to be checked out.
+    pub fn switch_host(&mut self, name: &str) -> Result<()> {
+        let branch = self.host(name)?.branch;
+        if self.strategy == HostStrategy::Directory {
+            // All hosts share the checked-out branch
+            self.hostname = name.to_string();
+            return Ok(());
+        }
+        if self.has_changes()? {
+            return Err(ConfectError::Other(
+                "Repository has uncommitted changes, run 'confect sync' first".to_string(),
+            ));
+        }
+
+        self.checkout_branch(&branch)?;
+        self.hostname = name.to_string();
+
+        Ok(())
+    }
+
+    /// Check out a branch, creating it from a remote if it only exists there
+    fn checkout_branch(&self, branch: &str) -> Result<()> {
+        let refname = format!("refs/heads/{}", branch);
+        if self.git.refname_to_id(&refname).is_err() {
+            let commit = self.branch_commit(branch)?;
+            self.git.branch(branch, &commit, false)?;
+        }
+
+        let obj = self.git.revparse_single(&refname)?;
+        self.git.checkout_tree(&obj, None)?;
+        self.git.set_head(&refname)?;
+
+        Ok(())
+    }
+
+    /// Create a branch from the tip of another, local or on a remote, and
+    /// check it out
+    fn create_branch_from(&self, branch: &str, from: &str) -> Result<()> {
+        let commit = self.branch_commit(from)?;
+        self.git.branch(branch, &commit, false)?;
+        self.checkout_branch(branch)
+    }
+
+    /// Rename a host, moving its branch to `host/<new>`
+    ///
+    /// With the directory strategy its directory is moved instead. The
+    /// changes are committed to the checked-out branch. Branches on remotes
+    /// are left alone.
+    pub fn rename_host(&mut self, old: &str, new: &str) -> Result<()> {
+        let mut config = RepoConfig::load(&self.path)?;
+        if config.hosts.list.contains_key(new) {
+            return Err(ConfectError::Other(format!(
+                "Host '{}' already exists",
+                new
+            )));
+        }
+        let entry = config
+            .hosts
+            .list
+            .remove(old)
+            .ok_or_else(|| ConfectError::HostNotFound(old.to_string()))?;
+
+        let branch = host_branch(self.strategy, new);
+        let (old_dir, new_dir) = (self.host_dir(old), self.host_dir(new));
+        match self.strategy {
+            HostStrategy::Branch => {
+                if let Ok(mut local) = self.git.find_branch(&entry.branch, BranchType::Local) {
+                    local.rename(&branch, false)?;
+                } else if let Some(commit) = self.branch_tip(&entry.branch)? {
+                    self.git.branch(&branch, &commit, false)?;
+                }
+            }
+            HostStrategy::Directory => {
+                if self.path.join(&old_dir).exists() {
+                    fs::create_dir_all(self.path.join(&new_dir).parent().unwrap_or(&self.path))?;
+                    fs::rename(self.path.join(&old_dir), self.path.join(&new_dir))?;
+                }
+            }
+        }
+
+        config.hosts.list.insert(
+            new.to_string(),
+            HostEntry {
+                branch,
+                groups: entry.groups,
+            },
+        );
+        config.save(&self.path)?;
+
+        let mut paths = vec![Path::new(".confect/config.toml")];
+        if self.strategy == HostStrategy::Directory {
+            paths.extend([old_dir.as_path(), new_dir.as_path()]);
+        }
+        self.commit_paths(&paths, &format!("Rename host {} to {}", old, new))?;
+
+        if self.hostname == old {
+            self.hostname = new.to_string();
+        }
+
+        Ok(())
+    }
+
+    /// Remove a host from the config and delete its local branch
+    ///
+    /// With the directory strategy its directory is deleted instead. The
+    /// current host cannot be removed. Branches on remotes are left alone.
+    pub fn remove_host(&self, name: &str) -> Result<()> {
+        if name == self.hostname {
+            return Err(ConfectError::Other(
+                "Cannot remove the current host, switch to another host first".to_string(),
+            ));
+        }
+
+        let mut config = RepoConfig::load(&self.path)?;
+        let entry = config
+            .hosts
+            .list
+            .remove(name)
+            .ok_or_else(|| ConfectError::HostNotFound(name.to_string()))?;
+
+        let host_dir = self.host_dir(name);
+        match self.strategy {
+            HostStrategy::Branch => {
+                if let Ok(mut local) = self.git.find_branch(&entry.branch, BranchType::Local) {
+                    if local.is_head() {
+                        return Err(ConfectError::Other(format!(
+                            "Branch '{}' is checked out, switch to another host first",
+                            entry.branch
+                        )));
+                    }
+                    local.delete()?;
+                }
+            }
+            HostStrategy::Directory => {
+                if self.path.join(&host_dir).exists() {
+                    fs::remove_dir_all(self.path.join(&host_dir))?;
+                }
+            }
+        }
+
+        config.save(&self.path)?;
+
+        let mut paths = vec![Path::new(".confect/config.toml")];
+        if self.strategy == HostStrategy::Directory {
+            paths.push(host_dir.as_path());
+        }
+        self.commit_paths(&paths, &format!("Remove host {}", name))?;
+
+        Ok(())
+    }
+
     /// Add a remote
     pub fn add_remote(&self, name: &str, url: &str) -> Result<()> {
         self.git.remote(name, url)?;
@@ -166,6 +1057,115 @@ impl Repository {
         index.add_all(["."].iter(), git2::IndexAddOption::DEFAULT, None)?;
         index.write()?;
 
+        self.commit_index(&mut index, message)
+    }
+
+    /// Stage only the given paths, relative to the repository root, and commit
+    ///
+    /// Directories are staged recursively and deleted files are removed.
+    pub fn commit_paths(&self, paths: &[&Path], message: &str) -> Result<()> {
+        let mut index = self.git.index()?;
+        index.add_all(paths, git2::IndexAddOption::DEFAULT, None)?;
+        index.update_all(paths, None)?;
+        index.write()?;
+
+        self.commit_index(&mut index, message)
+    }
+
+    /// Commit files to a branch that is not checked out
+    ///
+    /// Paths are relative to the repository root. The working tree is left
+    /// untouched.
+    pub fn commit_to_branch(
+        &self,
+        branch: &str,
+        files: &[(PathBuf, BranchFile)],
+        message: &str,
+    ) -> Result<()> {
+        let refname = format!("refs/heads/{}", branch);
+        if self.git.head()?.name() == Some(refname.as_str()) {
+            return Err(ConfectError::Other(format!(
+                "Branch '{}' is checked out",
+                branch
+            )));
+        }
+
+        let parent = self.branch_commit(branch)?;
+        let mut index = Index::new()?;
+        index.read_tree(&parent.tree()?)?;
+
+        for (path, file) in files {
+            let (content, mode) = match file {
+                BranchFile::Blob {
+                    content,
+                    executable,
+                } => (
+                    content.as_slice(),
+                    if *executable { 0o100755 } else { 0o100644 },
+                ),
+                BranchFile::Symlink(target) => (target.as_os_str().as_encoded_bytes(), 0o120000),
+            };
+            index.add(&IndexEntry {
+                ctime: IndexTime::new(0, 0),
+                mtime: IndexTime::new(0, 0),
+                dev: 0,
+                ino: 0,
+                mode,
+                uid: 0,
+                gid: 0,
+                file_size: content.len() as u32,
+                id: self.git.blob(content)?,
+                flags: 0,
+                flags_extended: 0,
+                path: path.as_os_str().as_encoded_bytes().to_vec(),
+            })?;
+        }
+
+        let tree = self.git.find_tree(index.write_tree_to(&self.git)?)?;
+        let sig = self.signature()?;
+        self.git
+            .commit(Some(&refname), &sig, &sig, message, &tree, &[&parent])?;
+
+        Ok(())
+    }
+
+    /// Merge a branch into the checked-out branch and commit the result
+    ///
+    /// On conflicts the merge is left in progress with markers in the working
+    /// tree, for `confect resolve` to finish like a conflicting pull. The
+    /// repository must not have uncommitted changes.
+    pub fn merge_branch(&self, branch: &str) -> Result<MergeOutcome> {
+        if self.has_changes()? {
+            return Err(ConfectError::Other(
+                "Repository has uncommitted changes, run 'confect sync' first".to_string(),
+            ));
+        }
+
+        let theirs = self.branch_commit(branch)?;
+        let annotated = self.git.find_annotated_commit(theirs.id())?;
+        let (analysis, _) = self.git.merge_analysis(&[&annotated])?;
+
+        if analysis.is_up_to_date() {
+            return Ok(MergeOutcome::UpToDate);
+        }
+
+        let head = self.git.head()?;
+        if analysis.is_fast_forward() {
+            let refname = head
+                .name()
+                .ok_or_else(|| ConfectError::Other("Could not get branch name".to_string()))?;
+            self.git
+                .reference(refname, theirs.id(), true, "confect: fast-forward")?;
+            self.git
+                .checkout_head(Some(git2::build::CheckoutBuilder::default().force()))?;
+            return Ok(MergeOutcome::FastForward);
+        }
+
+        self.merge_in_worktree(&annotated, branch, head.shorthand().unwrap_or("HEAD"))
+    }
+
+    /// Commit the staged index on HEAD
+    fn commit_index(&self, index: &mut Index, message: &str) -> Result<()> {
         let tree_id = index.write_tree()?;
         let tree = self.git.find_tree(tree_id)?;
 
@@ -188,15 +1188,43 @@ impl Repository {
         Ok(())
     }
 
-    /// Push to remote
+    /// Push the checked-out branch and the host's snapshot tags to a remote
     pub fn push(&self, remote_name: &str) -> Result<()> {
-        let mut remote = self.git.find_remote(remote_name)?;
-
         let head = self.git.head()?;
         let branch_name = head
             .shorthand()
             .ok_or_else(|| ConfectError::Other("Could not get branch name".to_string()))?;
 
+        let mut refspecs = vec![format!(
+            "refs/heads/{}:refs/heads/{}",
+            branch_name, branch_name
+        )];
+
+        // Snapshots of this host go along with its branch
+        let prefix = snapshot_tag(self.current_host()?, "");
+        for tag in self
+            .git
+            .tag_names(Some(&format!("{}*", prefix)))?
+            .iter()
+            .flatten()
+        {
+            refspecs.push(format!("refs/tags/{}:refs/tags/{}", tag, tag));
+        }
+
+        let mut remote = self.git.find_remote(remote_name)?;
+        let callbacks = create_credentials_callback();
+        let mut push_options = PushOptions::new();
+        push_options.remote_callbacks(callbacks);
+
+        remote.push(&refspecs, Some(&mut push_options))?;
+
+        Ok(())
+    }
+
+    /// Push a local branch to the branch of the same name on a remote
+    pub fn push_branch(&self, remote_name: &str, branch_name: &str) -> Result<()> {
+        let mut remote = self.git.find_remote(remote_name)?;
+
         let refspec = format!("refs/heads/{}:refs/heads/{}", branch_name, branch_name);
 
         let callbacks = create_credentials_callback();
@@ -208,8 +1236,8 @@ impl Repository {
         Ok(())
     }
 
-    /// Pull from remote
-    pub fn pull(&self, remote_name: &str) -> Result<()> {
+    /// Fetch all branches from a remote
+    fn fetch(&self, remote_name: &str) -> Result<()> {
         let mut remote = self.git.find_remote(remote_name)?;
 
         // Fetch with credentials
@@ -219,22 +1247,422 @@ impl Repository {
 
         remote.fetch(&[] as &[&str], Some(&mut fetch_options), None)?;
 
+        Ok(())
+    }
+
+    /// Fetch and push `main` and every host branch in the repository config
+    ///
+    /// Local branches are fast-forwarded to the remotes where possible and
+    /// pushed to every remote that is behind, so all remotes end up with the
+    /// same branches. Diverged branches are left alone and reported, as are
+    /// remotes that cannot be fetched.
+    pub fn sync_all_hosts(&self) -> Result<HostsSync> {
+        let mut result = HostsSync::default();
+        let mut remotes = Vec::new();
+        for (remote, _) in self.list_remotes()? {
+            match self.fetch(&remote) {
+                Ok(()) => remotes.push(remote),
+                Err(e) => result.unreachable.push((remote, e)),
+            }
+        }
+
+        let config = RepoConfig::load(&self.path)?;
+        let mut branches: Vec<String> = config
+            .hosts
+            .list
+            .values()
+            .map(|host| host.branch.clone())
+            .collect();
+        for host in config.hosts.list.values() {
+            for group in &host.groups {
+                let branch = group_branch(group);
+                if self.has_branch(&branch)? {
+                    branches.push(branch);
+                }
+            }
+        }
+        branches.retain(|branch| branch != BASE_BRANCH);
+        branches.sort();
+        branches.dedup();
+
+        let main_exists = self
+            .git
+            .refname_to_id(&format!("refs/heads/{}", BASE_BRANCH))
+            .is_ok()
+            || remotes.iter().any(|remote| {
+                self.git
+                    .refname_to_id(&format!("refs/remotes/{}/{}", remote, BASE_BRANCH))
+                    .is_ok()
+            });
+        if main_exists {
+            branches.insert(0, BASE_BRANCH.to_string());
+        }
+
+        result.branches = branches
+            .into_iter()
+            .map(|branch| {
+                let mut result = BranchSync {
+                    branch,
+                    ..Default::default()
+                };
+                if let Err(e) = self.sync_branch(&remotes, &mut result) {
+                    result.error = Some(e);
+                }
+                result
+            })
+            .collect();
+        Ok(result)
+    }
+
+    /// Bring a branch up to date with the remotes, see [`Self::sync_all_hosts`]
+    fn sync_branch(&self, remotes: &[String], result: &mut BranchSync) -> Result<()> {
+        let refname = format!("refs/heads/{}", result.branch);
+        let local = self.git.refname_to_id(&refname).ok();
+        let tips: Vec<(&String, Option<Oid>)> = remotes
+            .iter()
+            .map(|remote| {
+                let tip = self
+                    .git
+                    .refname_to_id(&format!("refs/remotes/{}/{}", remote, result.branch))
+                    .ok();
+                (remote, tip)
+            })
+            .collect();
+
+        // Find the newest commit that all other tips can be fast-forwarded to
+        let mut newest = local;
+        let mut source = None;
+        for (remote, tip) in &tips {
+            let Some(tip) = *tip else {
+                continue;
+            };
+            let is_newer = match newest {
+                Some(current) => current != tip && self.git.graph_descendant_of(tip, current)?,
+                None => true,
+            };
+            if is_newer {
+                newest = Some(tip);
+                source = Some(remote.to_string());
+            }
+        }
+
+        let Some(newest) = newest else {
+            return Err(ConfectError::Other(
+                "Branch not found locally or on any remote".to_string(),
+            ));
+        };
+
+        if local != Some(newest) {
+            self.git
+                .reference(&refname, newest, true, "confect: fast-forward")?;
+            if self.git.head()?.name() == Some(refname.as_str()) {
+                self.git
+                    .checkout_head(Some(git2::build::CheckoutBuilder::default().force()))?;
+            }
+            result.fast_forwarded = source;
+        }
+
+        for (remote, tip) in tips {
+            match tip {
+                Some(tip) if tip == newest => {}
+                Some(tip) if !self.git.graph_descendant_of(newest, tip)? => {
+                    result.diverged.push(remote.clone());
+                }
+                _ => {
+                    self.push_branch(remote, &result.branch)?;
+                    result.pushed.push(remote.clone());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Pull the checked-out branch from a remote
+    ///
+    /// A branch that diverged from the remote is merged, or rebased onto it
+    /// with [`PullStrategy::Rebase`]. Conflicts are written to the working
+    /// tree with markers and the merge or rebase is left in progress.
+    pub fn pull(&self, remote_name: &str, strategy: PullStrategy) -> Result<MergeOutcome> {
+        self.ensure_resolved()?;
+        self.fetch(remote_name)?;
+
         // Get current branch
         let head = self.git.head()?;
-        let branch_name = head.shorthand().unwrap_or("main");
+        let branch_name = head.shorthand().unwrap_or(BASE_BRANCH).to_string();
 
-        // Merge (fast-forward only)
-        let fetch_head = self.git.find_reference("FETCH_HEAD")?;
-        let fetch_commit = self.git.reference_to_annotated_commit(&fetch_head)?;
+        // Keep the shared base layers current as well
+        if branch_name != BASE_BRANCH {
+            for group in self.host_groups(&self.hostname)? {
+                self.fast_forward_from(&group_branch(&group), remote_name)?;
+            }
+            self.fast_forward_from(BASE_BRANCH, remote_name)?;
+        }
 
-        let (analysis, _) = self.git.merge_analysis(&[&fetch_commit])?;
+        let upstream = format!("{}/{}", remote_name, branch_name);
+        let Ok(theirs) = self
+            .git
+            .refname_to_id(&format!("refs/remotes/{}", upstream))
+        else {
+            // Not pushed yet, nothing to pull
+            return Ok(MergeOutcome::UpToDate);
+        };
+        let theirs = self.git.find_annotated_commit(theirs)?;
+
+        let (analysis, _) = self.git.merge_analysis(&[&theirs])?;
+        if analysis.is_up_to_date() {
+            return Ok(MergeOutcome::UpToDate);
+        }
 
         if analysis.is_fast_forward() {
             let refname = format!("refs/heads/{}", branch_name);
             let mut reference = self.git.find_reference(&refname)?;
-            reference.set_target(fetch_commit.id(), "Fast-forward")?;
+            reference.set_target(theirs.id(), "Fast-forward")?;
             self.git
                 .checkout_head(Some(git2::build::CheckoutBuilder::default().force()))?;
+            return Ok(MergeOutcome::FastForward);
+        }
+
+        if self.has_changes()? {
+            return Err(ConfectError::Other(
+                "Repository has uncommitted changes, run 'confect sync' first".to_string(),
+            ));
+        }
+
+        match strategy {
+            PullStrategy::Merge => self.merge_in_worktree(&theirs, &upstream, &branch_name),
+            PullStrategy::Rebase => self.rebase_onto(&theirs),
+        }
+    }
+
+    /// Merge a commit into HEAD, leaving conflicts in the working tree
+    fn merge_in_worktree(
+        &self,
+        theirs: &AnnotatedCommit,
+        name: &str,
+        branch: &str,
+    ) -> Result<MergeOutcome> {
+        let mut checkout = git2::build::CheckoutBuilder::new();
+        checkout.allow_conflicts(true).conflict_style_merge(true);
+        self.git.merge(&[theirs], None, Some(&mut checkout))?;
+
+        let message = format!("Merge {} into {}", name, branch);
+        let index = self.git.index()?;
+        if index.has_conflicts() {
+            // Kept for the commit `finish_merge` records once resolved
+            fs::write(self.git.path().join("MERGE_MSG"), &message)?;
+            return Ok(MergeOutcome::Conflicts(conflict_paths(&index)?));
+        }
+
+        self.commit_merge(&message)?;
+        Ok(MergeOutcome::Merged)
+    }
+
+    /// Commit the index with HEAD and MERGE_HEAD as parents
+    fn commit_merge(&self, message: &str) -> Result<()> {
+        let ours = self.git.head()?.peel_to_commit()?;
+        let theirs = self
+            .git
+            .find_commit(self.git.refname_to_id("MERGE_HEAD")?)?;
+
+        let tree = self.git.find_tree(self.git.index()?.write_tree()?)?;
+        let sig = self.signature()?;
+        self.git
+            .commit(Some("HEAD"), &sig, &sig, message, &tree, &[&ours, &theirs])?;
+        self.git.cleanup_state()?;
+
+        Ok(())
+    }
+
+    /// Replay local commits onto a commit, stopping at the first conflict
+    fn rebase_onto(&self, upstream: &AnnotatedCommit) -> Result<MergeOutcome> {
+        let mut rebase = self.git.rebase(None, Some(upstream), None, None)?;
+        self.continue_rebase(&mut rebase, false)
+    }
+
+    /// Apply the remaining commits of a rebase, committing the current one
+    /// first when `resume` is set
+    fn continue_rebase(&self, rebase: &mut Rebase, resume: bool) -> Result<MergeOutcome> {
+        let sig = self.signature()?;
+        let commit = |rebase: &mut Rebase| match rebase.commit(None, &sig, None) {
+            // A commit whose changes are already upstream becomes empty
+            Err(e) if e.code() != ErrorCode::Applied => Err(e),
+            _ => Ok(()),
+        };
+
+        if resume {
+            commit(rebase)?;
+        }
+        while let Some(operation) = rebase.next() {
+            operation?;
+            let index = self.git.index()?;
+            if index.has_conflicts() {
+                return Ok(MergeOutcome::Conflicts(conflict_paths(&index)?));
+            }
+            commit(rebase)?;
+        }
+        rebase.finish(Some(&sig))?;
+
+        Ok(MergeOutcome::Rebased)
+    }
+
+    /// Finish the merge or rebase `pull` stopped once all conflicts are resolved
+    ///
+    /// A rebase carries on with the remaining commits and may stop again.
+    pub fn finish_merge(&self) -> Result<MergeOutcome> {
+        let conflicts = self.conflicts()?;
+        if !conflicts.is_empty() {
+            return Err(ConfectError::UnresolvedConflicts(conflicts.len()));
+        }
+
+        // Resolving may also have touched the vault or path indexes
+        let mut index = self.git.index()?;
+        index.add_all(["."].iter(), git2::IndexAddOption::DEFAULT, None)?;
+        index.update_all(["."].iter(), None)?;
+        index.write()?;
+
+        match self.git.state() {
+            RepositoryState::Merge => {
+                let message = self
+                    .git
+                    .message()
+                    .unwrap_or_else(|_| "Merge remote changes".to_string());
+                self.commit_merge(message.trim())?;
+                Ok(MergeOutcome::Merged)
+            }
+            RepositoryState::RebaseMerge => {
+                let mut rebase = self.git.open_rebase(None)?;
+                self.continue_rebase(&mut rebase, true)
+            }
+            _ => Err(ConfectError::Other(
+                "No merge or rebase in progress".to_string(),
+            )),
+        }
+    }
+
+    /// Read a file as one side of the merge or rebase in progress has it
+    ///
+    /// During a rebase the local commit being replayed is [`ConflictSide::Mine`]
+    /// and the remote history it is replayed onto is [`ConflictSide::Theirs`].
+    pub fn read_conflict_file(
+        &self,
+        side: ConflictSide,
+        path: &Path,
+    ) -> Result<Option<BranchFile>> {
+        let head = self.git.head()?.peel_to_commit()?;
+        let (mine, theirs) = match self.git.state() {
+            RepositoryState::Merge => {
+                let merge_head = self.git.refname_to_id("MERGE_HEAD")?;
+                (head, self.git.find_commit(merge_head)?)
+            }
+            RepositoryState::RebaseMerge => {
+                let mut rebase = self.git.open_rebase(None)?;
+                let current = rebase
+                    .operation_current()
+                    .and_then(|i| rebase.nth(i))
+                    .ok_or_else(|| ConfectError::Other("No rebase step in progress".to_string()))?
+                    .id();
+                (self.git.find_commit(current)?, head)
+            }
+            _ => {
+                return Err(ConfectError::Other(
+                    "No merge or rebase in progress".to_string(),
+                ))
+            }
+        };
+
+        let commit = match side {
+            ConflictSide::Mine => mine,
+            ConflictSide::Theirs => theirs,
+            ConflictSide::Base => {
+                let base = self.git.merge_base(mine.id(), theirs.id())?;
+                self.git.find_commit(base)?
+            }
+        };
+
+        read_tree_file(&self.git, &commit.tree()?, path)
+    }
+
+    /// Replace a file in the working tree, removing it for `None`
+    ///
+    /// `path` is relative to the repository root.
+    pub fn write_file(&self, path: &Path, file: Option<&BranchFile>) -> Result<()> {
+        let full_path = self.path.join(path);
+        if full_path.exists() || full_path.is_symlink() {
+            fs::remove_file(&full_path)?;
+        }
+        let Some(file) = file else {
+            return Ok(());
+        };
+
+        if let Some(parent) = full_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        match file {
+            BranchFile::Blob {
+                content,
+                executable,
+            } => {
+                fs::write(&full_path, content)?;
+                let mode = if *executable { 0o755 } else { 0o644 };
+                fs::set_permissions(&full_path, fs::Permissions::from_mode(mode))?;
+            }
+            BranchFile::Symlink(target) => std::os::unix::fs::symlink(target, &full_path)?,
+        }
+
+        Ok(())
+    }
+
+    /// Stage a file as it is in the working tree, clearing its conflict
+    pub fn mark_resolved(&self, path: &Path) -> Result<()> {
+        let full_path = self.path.join(path);
+        let mut index = self.git.index()?;
+        if full_path.exists() || full_path.is_symlink() {
+            index.add_path(path)?;
+        } else {
+            index.remove_path(path)?;
+        }
+        index.write()?;
+        Ok(())
+    }
+
+    /// List files with unresolved conflicts, relative to the repository root
+    pub fn conflicts(&self) -> Result<Vec<PathBuf>> {
+        conflict_paths(&self.git.index()?)
+    }
+
+    /// Fail while a merge or rebase is waiting for conflicts to be resolved
+    pub fn ensure_resolved(&self) -> Result<()> {
+        let conflicts = self.conflicts()?;
+        if !conflicts.is_empty() {
+            return Err(ConfectError::UnresolvedConflicts(conflicts.len()));
+        }
+        if self.git.state() != RepositoryState::Clean {
+            return Err(ConfectError::Other(format!(
+                "A merge or rebase is in progress in {}, run 'confect resolve' to finish it",
+                self.path.display()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Fast-forward a branch that is not checked out to its remote-tracking branch
+    fn fast_forward_from(&self, branch: &str, remote_name: &str) -> Result<()> {
+        let refname = format!("refs/heads/{}", branch);
+        let Ok(remote) = self
+            .git
+            .refname_to_id(&format!("refs/remotes/{}/{}", remote_name, branch))
+        else {
+            return Ok(());
+        };
+
+        let is_ahead = match self.git.refname_to_id(&refname) {
+            Ok(local) => local != remote && self.git.graph_descendant_of(remote, local)?,
+            Err(_) => true,
+        };
+        if is_ahead {
+            self.git
+                .reference(&refname, remote, true, "confect: fast-forward")?;
         }
 
         Ok(())
@@ -279,6 +1707,175 @@ impl Repository {
     }
 }
 
+/// Get the tag name of a host's snapshot
+fn snapshot_tag(host: &str, name: &str) -> String {
+    format!("snapshot/{}/{}", host, name)
+}
+
+/// Get the branch a host's files are committed to
+fn host_branch(strategy: HostStrategy, host: &str) -> String {
+    match strategy {
+        HostStrategy::Branch => format!("host/{}", host),
+        HostStrategy::Directory => BASE_BRANCH.to_string(),
+    }
+}
+
+/// List the files under a directory of a tree, relative to the tree's root
+fn list_tree_files(git: &Git2Repo, tree: &git2::Tree, dir: &Path) -> Result<Vec<PathBuf>> {
+    let Ok(entry) = tree.get_path(dir) else {
+        return Ok(Vec::new());
+    };
+    let Ok(subtree) = entry.to_object(git)?.into_tree() else {
+        return Ok(Vec::new());
+    };
+
+    let mut files = Vec::new();
+    subtree.walk(TreeWalkMode::PreOrder, |root, entry| {
+        if entry.kind() == Some(ObjectType::Blob) {
+            if let Some(name) = entry.name() {
+                files.push(dir.join(root).join(name));
+            }
+        }
+        TreeWalkResult::Ok
+    })?;
+
+    Ok(files)
+}
+
+/// Parse a date or time in local time, a bare day meaning its end
+fn parse_date(spec: &str) -> Option<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(spec) {
+        return Some(time.with_timezone(&Utc));
+    }
+
+    let naive = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(spec, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(spec, "%Y-%m-%d")
+                .ok()
+                .and_then(|day| day.and_hms_opt(23, 59, 59))
+        })?;
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(|time| time.with_timezone(&Utc))
+}
+
+/// Read a file from a tree, `None` if it does not exist there
+fn read_tree_file(git: &Git2Repo, tree: &git2::Tree, path: &Path) -> Result<Option<BranchFile>> {
+    let Ok(entry) = tree.get_path(path) else {
+        return Ok(None);
+    };
+    if entry.kind() != Some(ObjectType::Blob) {
+        return Ok(None);
+    }
+
+    let blob = git.find_blob(entry.id())?;
+    let file = match entry.filemode() {
+        0o120000 => BranchFile::Symlink(PathBuf::from(
+            String::from_utf8_lossy(blob.content()).into_owned(),
+        )),
+        mode => BranchFile::Blob {
+            content: blob.content().to_vec(),
+            executable: mode == 0o100755,
+        },
+    };
+
+    Ok(Some(file))
+}
+
+/// Collect the files a diff changes, with line counts and optional patches
+fn file_changes(diff: &git2::Diff, patch: bool) -> Result<Vec<FileChange>> {
+    let mut changes = Vec::new();
+    for i in 0..diff.deltas().len() {
+        let Some(mut file_patch) = git2::Patch::from_diff(diff, i)? else {
+            continue;
+        };
+        let delta = file_patch.delta();
+        let Some(path) = delta.new_file().path().or(delta.old_file().path()) else {
+            continue;
+        };
+        let path = path.to_path_buf();
+        let binary = delta.flags().is_binary();
+
+        let (_, insertions, deletions) = file_patch.line_stats()?;
+        let patch = if patch && !binary {
+            Some(String::from_utf8_lossy(&file_patch.to_buf()?).into_owned())
+        } else {
+            None
+        };
+        changes.push(FileChange {
+            path,
+            insertions,
+            deletions,
+            binary,
+            patch,
+        });
+    }
+
+    Ok(changes)
+}
+
+/// Name the layer directory holding a path with the directory strategy,
+/// such as `hosts/web1`, `groups/prod` or `common`
+fn layer_dir(path: &Path) -> String {
+    let depth = match path.components().next() {
+        Some(first) if first.as_os_str() == COMMON_DIR => 1,
+        Some(first) if first.as_os_str() == "hosts" || first.as_os_str() == GROUPS_DIR => 2,
+        _ => return BASE_BRANCH.to_string(),
+    };
+    path.components()
+        .take(depth)
+        .collect::<PathBuf>()
+        .display()
+        .to_string()
+}
+
+/// Paths of the conflicting entries in an index, sorted
+fn conflict_paths(index: &Index) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for conflict in index.conflicts()? {
+        let conflict = conflict?;
+        if let Some(entry) = conflict.our.or(conflict.their).or(conflict.ancestor) {
+            paths.push(PathBuf::from(
+                String::from_utf8_lossy(&entry.path).into_owned(),
+            ));
+        }
+    }
+    paths.sort();
+    paths.dedup();
+    Ok(paths)
+}
+
+/// Copy a directory tree, keeping symlinks and permissions
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    if !from.exists() {
+        return Ok(());
+    }
+
+    for entry in WalkDir::new(from).follow_links(false) {
+        let entry = entry?;
+        let target = to.join(entry.path().strip_prefix(from).unwrap_or(entry.path()));
+        let file_type = entry.file_type();
+
+        if file_type.is_dir() {
+            fs::create_dir_all(&target)?;
+        } else if file_type.is_symlink() {
+            std::os::unix::fs::symlink(fs::read_link(entry.path())?, &target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Get the branch a group's files are committed to with the branch strategy
+pub fn group_branch(group: &str) -> String {
+    format!("group/{}", group)
+}
+
 /// Create RemoteCallbacks with authentication support
 fn create_credentials_callback<'a>() -> RemoteCallbacks<'a> {
     let mut callbacks = RemoteCallbacks::new();
@@ -307,3 +1904,520 @@ fn create_credentials_callback<'a>() -> RemoteCallbacks<'a> {
 
     callbacks
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn bootstrap_clones_the_repository_and_adds_a_host() -> Result<()> {
+        let temp = tempdir()?;
+        let origin = temp.path().join("origin.git");
+        Git2Repo::init_bare(&origin)?;
+        let origin_url = origin.to_string_lossy().to_string();
+        let main = HostBase::Branch(BASE_BRANCH.to_string());
+
+        // An empty remote is left for `init` to start a new history in
+        let empty_dir = temp.path().join("empty");
+        assert!(Repository::bootstrap(&origin_url, &empty_dir, "alpha", &main)?.is_none());
+        assert!(!empty_dir.exists());
+
+        let alpha = Repository::init(&temp.path().join("alpha"), "alpha")?;
+        alpha.add_remote("origin", &origin_url)?;
+        let mut config = RepoConfig::load(alpha.path())?;
+        config
+            .hosts
+            .list
+            .get_mut("alpha")
+            .expect("host exists")
+            .groups = vec!["web".to_string()];
+        config.save(alpha.path())?;
+        fs::create_dir_all(alpha.path().join("shell"))?;
+        fs::write(alpha.path().join("shell/bashrc"), "alias ll='ls -l'")?;
+        alpha.commit_all("Add bashrc")?;
+        alpha.push_branch("origin", BASE_BRANCH)?;
+        alpha.push("origin")?;
+
+        let beta_dir = temp.path().join("beta");
+        let beta = Repository::bootstrap(
+            &origin_url,
+            &beta_dir,
+            "beta",
+            &HostBase::Host("alpha".to_string()),
+        )?
+        .expect("remote has branches");
+        assert_eq!(beta.current_branch()?, "host/beta");
+        assert!(beta.git.refname_to_id("refs/heads/main").is_ok());
+        assert_eq!(
+            fs::read_to_string(beta_dir.join("shell/bashrc"))?,
+            "alias ll='ls -l'"
+        );
+        assert!(!beta.has_changes()?);
+        let host = beta.host("beta")?;
+        assert_eq!(host.branch, "host/beta");
+        assert_eq!(host.groups, vec!["web".to_string()]);
+
+        // Hosts are not added twice, and failed attempts leave nothing behind
+        let again_dir = temp.path().join("again");
+        assert!(Repository::bootstrap(&origin_url, &again_dir, "alpha", &main).is_err());
+        assert!(!again_dir.exists());
+
+        // An existing directory is kept, without the checked out files
+        fs::create_dir_all(&again_dir)?;
+        assert!(Repository::bootstrap(&origin_url, &again_dir, "alpha", &main).is_err());
+        assert!(again_dir.exists());
+        assert_eq!(fs::read_dir(&again_dir)?.count(), 0);
+
+        let gamma = Repository::bootstrap(&origin_url, &temp.path().join("gamma"), "gamma", &main)?
+            .expect("remote has branches");
+        assert_eq!(gamma.current_branch()?, "host/gamma");
+        assert!(!gamma.path().join("shell").exists());
+        assert!(gamma.host("gamma")?.groups.is_empty());
+
+        Ok(())
+    }
+
+    /// Clone a second working copy of a host's branch, as on a reinstalled machine
+    fn clone_host(url: &str, path: &Path, host: &str) -> Result<Repository> {
+        let git = RepoBuilder::new()
+            .branch(&format!("host/{}", host))
+            .clone(url, path)?;
+        let mut config = git.config()?;
+        config.set_str("user.name", "test")?;
+        config.set_str("user.email", "test@localhost")?;
+        Ok(Repository {
+            git,
+            path: path.to_path_buf(),
+            hostname: host.to_string(),
+            strategy: HostStrategy::Branch,
+        })
+    }
+
+    #[test]
+    fn pull_merges_or_rebases_diverged_branches() -> Result<()> {
+        let temp = tempdir()?;
+        let origin = temp.path().join("origin.git");
+        Git2Repo::init_bare(&origin)?;
+        let origin_url = origin.to_string_lossy().to_string();
+
+        let alpha = Repository::init(&temp.path().join("alpha"), "alpha")?;
+        alpha.add_remote("origin", &origin_url)?;
+        fs::create_dir_all(alpha.path().join("shell"))?;
+        fs::write(alpha.path().join("shell/bashrc"), "one\ntwo\nthree\n")?;
+        alpha.commit_all("Add bashrc")?;
+        alpha.push_branch("origin", BASE_BRANCH)?;
+        alpha.push("origin")?;
+        let other = clone_host(&origin_url, &temp.path().join("other"), "alpha")?;
+
+        // Changes to different files are merged with a merge commit
+        fs::write(other.path().join("shell/profile"), "export A=1\n")?;
+        other.commit_all("Add profile")?;
+        other.push("origin")?;
+        fs::write(alpha.path().join("shell/bashrc"), "ONE\ntwo\nthree\n")?;
+        alpha.commit_all("Edit bashrc")?;
+        assert_eq!(
+            alpha.pull("origin", PullStrategy::Merge)?,
+            MergeOutcome::Merged
+        );
+        let head = alpha.git.head()?.peel_to_commit()?;
+        assert_eq!(head.parent_count(), 2);
+        assert!(alpha.path().join("shell/profile").exists());
+        assert_eq!(
+            alpha.pull("origin", PullStrategy::Merge)?,
+            MergeOutcome::UpToDate
+        );
+        alpha.push("origin")?;
+        assert_eq!(
+            other.pull("origin", PullStrategy::Merge)?,
+            MergeOutcome::FastForward
+        );
+
+        // With rebase, local commits end up on top of the remote ones
+        fs::write(other.path().join("shell/profile"), "export A=2\n")?;
+        other.commit_all("Edit profile")?;
+        other.push("origin")?;
+        fs::write(alpha.path().join("shell/bashrc"), "ONE\ntwo\nTHREE\n")?;
+        alpha.commit_all("Edit bashrc again")?;
+        assert_eq!(
+            alpha.pull("origin", PullStrategy::Rebase)?,
+            MergeOutcome::Rebased
+        );
+        let head = alpha.git.head()?.peel_to_commit()?;
+        assert_eq!(head.parent_count(), 1);
+        assert_eq!(head.summary(), Some("Edit bashrc again"));
+        assert_eq!(
+            fs::read_to_string(alpha.path().join("shell/profile"))?,
+            "export A=2\n"
+        );
+        alpha.push("origin")?;
+        other.pull("origin", PullStrategy::Merge)?;
+
+        // Conflicting edits are left in the working tree with markers
+        for (repo, line) in [(&other, "remote"), (&alpha, "local")] {
+            fs::write(repo.path().join("shell/bashrc"), format!("{}\n", line))?;
+            repo.commit_all("Conflicting edit")?;
+        }
+        other.push("origin")?;
+        let conflicts = vec![PathBuf::from("shell/bashrc")];
+        assert_eq!(
+            alpha.pull("origin", PullStrategy::Merge)?,
+            MergeOutcome::Conflicts(conflicts.clone())
+        );
+        let content = fs::read_to_string(alpha.path().join("shell/bashrc"))?;
+        assert!(content.contains("<<<<<<<") && content.contains("remote"));
+        assert_eq!(alpha.conflicts()?, conflicts);
+        assert!(matches!(
+            alpha.ensure_resolved(),
+            Err(ConfectError::UnresolvedConflicts(1))
+        ));
+        assert!(alpha.pull("origin", PullStrategy::Merge).is_err());
+
+        // A rebase stops at the conflicting commit the same way
+        alpha.git.cleanup_state()?;
+        alpha
+            .git
+            .reset(head.as_object(), git2::ResetType::Hard, None)?;
+        fs::write(alpha.path().join("shell/bashrc"), "local\n")?;
+        alpha.commit_all("Conflicting edit")?;
+        assert_eq!(
+            alpha.pull("origin", PullStrategy::Rebase)?,
+            MergeOutcome::Conflicts(conflicts)
+        );
+        let content = fs::read_to_string(alpha.path().join("shell/bashrc"))?;
+        assert!(content.contains("<<<<<<<") && content.contains("local"));
+        assert!(alpha.ensure_resolved().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn resolved_conflicts_finish_the_merge_or_rebase() -> Result<()> {
+        let temp = tempdir()?;
+        let origin = temp.path().join("origin.git");
+        Git2Repo::init_bare(&origin)?;
+        let origin_url = origin.to_string_lossy().to_string();
+
+        let alpha = Repository::init(&temp.path().join("alpha"), "alpha")?;
+        alpha.add_remote("origin", &origin_url)?;
+        fs::create_dir_all(alpha.path().join("shell"))?;
+        fs::write(alpha.path().join("shell/bashrc"), "base\n")?;
+        alpha.commit_all("Add bashrc")?;
+        alpha.push("origin")?;
+        let other = clone_host(&origin_url, &temp.path().join("other"), "alpha")?;
+        let bashrc = Path::new("shell/bashrc");
+
+        for strategy in [PullStrategy::Merge, PullStrategy::Rebase] {
+            for (repo, line) in [(&other, "remote"), (&alpha, "local")] {
+                fs::write(
+                    repo.path().join(bashrc),
+                    format!("{} {:?}\n", line, strategy),
+                )?;
+                repo.commit_all("Conflicting edit")?;
+            }
+            other.push("origin")?;
+            let blob = |line: &str| BranchFile::Blob {
+                content: format!("{} {:?}\n", line, strategy).into_bytes(),
+                executable: false,
+            };
+            assert!(matches!(
+                alpha.pull("origin", strategy)?,
+                MergeOutcome::Conflicts(_)
+            ));
+
+            // Sides are named from this machine's point of view either way
+            assert_eq!(
+                alpha.read_conflict_file(ConflictSide::Mine, bashrc)?,
+                Some(blob("local"))
+            );
+            assert_eq!(
+                alpha.read_conflict_file(ConflictSide::Theirs, bashrc)?,
+                Some(blob("remote"))
+            );
+            assert!(alpha.finish_merge().is_err());
+
+            let theirs = alpha.read_conflict_file(ConflictSide::Theirs, bashrc)?;
+            alpha.write_file(bashrc, theirs.as_ref())?;
+            alpha.mark_resolved(bashrc)?;
+            let expected = match strategy {
+                PullStrategy::Merge => MergeOutcome::Merged,
+                PullStrategy::Rebase => MergeOutcome::Rebased,
+            };
+            assert_eq!(alpha.finish_merge()?, expected);
+            alpha.ensure_resolved()?;
+            assert!(!alpha.has_changes()?);
+            assert_eq!(
+                alpha.read_branch_file("host/alpha", bashrc)?,
+                Some(blob("remote"))
+            );
+
+            alpha.push("origin")?;
+            other.pull("origin", PullStrategy::Merge)?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn log_lists_commits_changing_paths_with_their_branch() -> Result<()> {
+        let temp = tempdir()?;
+        let repo = Repository::init(&temp.path().join("repo"), "alpha")?;
+        let bashrc = PathBuf::from("shell/home/user/.bashrc");
+        fs::create_dir_all(repo.path().join("shell/home/user"))?;
+        fs::create_dir_all(repo.path().join("nginx"))?;
+
+        fs::write(repo.path().join(&bashrc), "one\ntwo\n")?;
+        repo.commit_all("Add bashrc")?;
+        fs::write(repo.path().join("nginx/nginx.conf"), "worker 1;\n")?;
+        repo.commit_all("Add nginx")?;
+        fs::write(repo.path().join(&bashrc), "one\nTWO\nthree\n")?;
+        repo.commit_all("Edit bashrc")?;
+
+        let entries = repo.log(&[PathBuf::from("shell")], true, None)?;
+        let summaries: Vec<_> = entries.iter().map(|e| e.summary.as_str()).collect();
+        assert_eq!(summaries, vec!["Edit bashrc", "Add bashrc"]);
+        assert!(entries.iter().all(|e| e.branch == "host/alpha"));
+
+        let change = &entries[0].files[0];
+        assert_eq!(change.path, bashrc);
+        assert_eq!((change.insertions, change.deletions), (2, 1));
+        assert!(!change.binary);
+        assert!(change
+            .patch
+            .as_deref()
+            .is_some_and(|patch| patch.contains("+TWO")));
+
+        assert_eq!(repo.log(&[], false, Some(2))?.len(), 2);
+        assert!(repo.log(&[bashrc], false, None)?[0].files[0]
+            .patch
+            .is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn snapshots_are_tagged_listed_and_pushed_with_the_branch() -> Result<()> {
+        let temp = tempdir()?;
+        let origin = temp.path().join("origin.git");
+        let origin_git = Git2Repo::init_bare(&origin)?;
+
+        let alpha = Repository::init(&temp.path().join("alpha"), "alpha")?;
+        alpha.add_remote("origin", &origin.to_string_lossy())?;
+        fs::create_dir_all(alpha.path().join("shell"))?;
+        fs::write(alpha.path().join("shell/bashrc"), "one\n")?;
+        alpha.commit_all("Add bashrc")?;
+        let first = alpha.create_snapshot("before-upgrade", "Before the upgrade")?;
+        assert_eq!(first.tag, "snapshot/alpha/before-upgrade");
+        assert_eq!(first.message, "Before the upgrade");
+        assert!(alpha.create_snapshot("before-upgrade", "Again").is_err());
+        assert!(alpha.create_snapshot("bad name", "Invalid").is_err());
+
+        fs::write(alpha.path().join("shell/bashrc"), "two\n")?;
+        alpha.commit_all("Edit bashrc")?;
+        let second = alpha.create_snapshot("after-upgrade", "After the upgrade")?;
+        assert_ne!(first.id, second.id);
+
+        // Both are taken within the same second here, so only compare names
+        let mut names: Vec<_> = alpha
+            .list_snapshots()?
+            .into_iter()
+            .map(|snapshot| snapshot.name)
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["after-upgrade", "before-upgrade"]);
+        assert_eq!(alpha.snapshot("before-upgrade")?.id, first.id);
+        assert!(alpha.snapshot("missing").is_err());
+
+        // Other hosts' snapshots are not listed as this host's
+        let beta = Repository::init(&temp.path().join("beta"), "beta")?;
+        assert!(beta.list_snapshots()?.is_empty());
+
+        alpha.push("origin")?;
+        assert_eq!(
+            origin_git
+                .find_reference("refs/tags/snapshot/alpha/before-upgrade")?
+                .peel_to_commit()?
+                .id(),
+            first.id
+        );
+        assert!(origin_git
+            .find_reference("refs/tags/snapshot/alpha/after-upgrade")
+            .is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn sync_all_hosts_mirrors_host_branches_across_remotes() -> Result<()> {
+        let temp = tempdir()?;
+        let origin = temp.path().join("origin.git");
+        let mirror = temp.path().join("mirror.git");
+        Git2Repo::init_bare(&origin)?;
+        Git2Repo::init_bare(&mirror)?;
+        let origin_url = origin.to_string_lossy().to_string();
+
+        // Another host has pushed its branch
+        let beta = Repository::init(&temp.path().join("beta"), "beta")?;
+        beta.add_remote("origin", &origin_url)?;
+        beta.push("origin")?;
+
+        let alpha = Repository::init(&temp.path().join("alpha"), "alpha")?;
+        alpha.add_remote("origin", &origin_url)?;
+        let mut config = RepoConfig::load(alpha.path())?;
+        config.hosts.list.insert(
+            "beta".to_string(),
+            HostEntry {
+                branch: "host/beta".to_string(),
+                groups: Vec::new(),
+            },
+        );
+        config.save(alpha.path())?;
+        alpha.commit_all("Add beta")?;
+
+        let results = alpha.sync_all_hosts()?.branches;
+        let branches: Vec<_> = results.iter().map(|r| r.branch.as_str()).collect();
+        assert_eq!(branches, vec!["main", "host/alpha", "host/beta"]);
+        assert_eq!(results[1].pushed, vec!["origin".to_string()]);
+        assert_eq!(results[2].fast_forwarded.as_deref(), Some("origin"));
+        assert!(results[2].pushed.is_empty());
+
+        // Fast-forward local copies of branches other hosts pushed to
+        fs::write(beta.path().join("new.txt"), "new")?;
+        beta.commit_all("Change beta")?;
+        beta.push("origin")?;
+
+        // A remote that cannot be reached is reported, the others still synced
+        alpha.add_remote("mirror", &mirror.to_string_lossy())?;
+        alpha.add_remote("dead", "unsupported://nowhere/repo.git")?;
+        let results = alpha.sync_all_hosts()?;
+        assert_eq!(results.unreachable.len(), 1);
+        assert_eq!(results.unreachable[0].0, "dead");
+        let results = results.branches;
+        assert!(results[1].fast_forwarded.is_none());
+        assert_eq!(results[1].pushed, vec!["mirror".to_string()]);
+        assert_eq!(results[2].fast_forwarded.as_deref(), Some("origin"));
+        assert_eq!(results[2].pushed, vec!["mirror".to_string()]);
+
+        let beta_head = beta.git.refname_to_id("refs/heads/host/beta")?;
+        let mirror = Git2Repo::open_bare(&mirror)?;
+        assert_eq!(mirror.refname_to_id("refs/heads/host/beta")?, beta_head);
+        assert!(alpha
+            .sync_all_hosts()?
+            .branches
+            .iter()
+            .all(BranchSync::is_up_to_date));
+
+        Ok(())
+    }
+
+    #[test]
+    fn hosts_can_be_switched_renamed_and_removed() -> Result<()> {
+        let temp = tempdir()?;
+        let mut repo = Repository::init(&temp.path().join("repo"), "alpha")?;
+
+        let mut config = RepoConfig::load(repo.path())?;
+        config.hosts.list.insert(
+            "beta".to_string(),
+            HostEntry {
+                branch: "host/beta".to_string(),
+                groups: Vec::new(),
+            },
+        );
+        config.save(repo.path())?;
+        repo.commit_all("Add beta")?;
+        {
+            let head = repo.git.head()?.peel_to_commit()?;
+            repo.git.branch("host/beta", &head, false)?;
+        }
+
+        let hosts = repo.list_hosts()?;
+        let names: Vec<_> = hosts.iter().map(|h| h.name.as_str()).collect();
+        assert_eq!(names, vec!["alpha", "beta"]);
+        assert_eq!(hosts[1].last_message.as_deref(), Some("Add beta"));
+        assert!(hosts[1].last_commit.is_some());
+
+        repo.switch_host("beta")?;
+        assert_eq!(repo.current_host()?, "beta");
+        assert_eq!(repo.git.head()?.shorthand(), Some("host/beta"));
+        assert!(matches!(
+            repo.switch_host("gamma"),
+            Err(ConfectError::HostNotFound(_))
+        ));
+
+        repo.rename_host("alpha", "gamma")?;
+        assert!(repo.git.refname_to_id("refs/heads/host/alpha").is_err());
+        assert!(repo.git.refname_to_id("refs/heads/host/gamma").is_ok());
+        assert_eq!(repo.host("gamma")?.branch, "host/gamma");
+        assert!(!repo.has_changes()?);
+
+        assert!(repo.remove_host("beta").is_err());
+        repo.remove_host("gamma")?;
+        assert!(repo.git.refname_to_id("refs/heads/host/gamma").is_err());
+        let names: Vec<_> = repo.list_hosts()?.into_iter().map(|h| h.name).collect();
+        assert_eq!(names, vec!["beta".to_string()]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn main_is_merged_into_the_host_branch_leaving_conflicts_to_resolve() -> Result<()> {
+        let temp = tempdir()?;
+        let repo = Repository::init(&temp.path().join("repo"), "alpha")?;
+        let blob = |content: &str| BranchFile::Blob {
+            content: content.as_bytes().to_vec(),
+            executable: false,
+        };
+
+        fs::write(repo.path().join("host.txt"), "host")?;
+        repo.commit_all("Host file")?;
+        repo.commit_to_branch(
+            BASE_BRANCH,
+            &[(PathBuf::from("shared.txt"), blob("shared"))],
+            "Shared file",
+        )?;
+        assert!(!repo.path().join("shared.txt").exists());
+
+        assert_eq!(repo.merge_branch(BASE_BRANCH)?, MergeOutcome::Merged);
+        assert_eq!(
+            fs::read_to_string(repo.path().join("shared.txt"))?,
+            "shared"
+        );
+        assert_eq!(repo.merge_branch(BASE_BRANCH)?, MergeOutcome::UpToDate);
+
+        fs::write(repo.path().join("shared.txt"), "ours")?;
+        repo.commit_all("Change shared file")?;
+        repo.commit_to_branch(
+            BASE_BRANCH,
+            &[(PathBuf::from("shared.txt"), blob("theirs"))],
+            "Change shared file on main",
+        )?;
+
+        let shared = PathBuf::from("shared.txt");
+        assert_eq!(
+            repo.merge_branch(BASE_BRANCH)?,
+            MergeOutcome::Conflicts(vec![shared.clone()])
+        );
+        assert!(fs::read_to_string(repo.path().join(&shared))?.contains("<<<<<<<"));
+        assert!(matches!(
+            repo.ensure_resolved(),
+            Err(ConfectError::UnresolvedConflicts(1))
+        ));
+
+        // Resolved like a conflicting pull
+        repo.write_file(
+            &shared,
+            repo.read_conflict_file(ConflictSide::Theirs, &shared)?
+                .as_ref(),
+        )?;
+        repo.mark_resolved(&shared)?;
+        assert_eq!(repo.finish_merge()?, MergeOutcome::Merged);
+        let head = repo.git.head()?.peel_to_commit()?;
+        assert_eq!(head.parent_count(), 2);
+        assert_eq!(head.summary(), Some("Merge main into host/alpha"));
+        assert_eq!(fs::read_to_string(repo.path().join(&shared))?, "theirs");
+        assert!(!repo.has_changes()?);
+        repo.ensure_resolved()?;
+
+        Ok(())
+    }
+}
@@ -1,3 +1,5 @@
 mod age;
+mod passphrase;
 
 pub use age::AgeEncryption;
+pub use passphrase::{read_passphrase, PASSPHRASE_ENV, PASSPHRASE_FD_ENV};
@@ -0,0 +1,50 @@
+use std::env;
+use std::fs;
+
+use age::secrecy::SecretString;
+use console::Term;
+use dialoguer::Password;
+
+use crate::error::{ConfectError, Result};
+
+/// Environment variable holding the passphrase
+pub const PASSPHRASE_ENV: &str = "CONFECT_PASSPHRASE";
+
+/// Environment variable naming a file descriptor to read the passphrase from
+pub const PASSPHRASE_FD_ENV: &str = "CONFECT_PASSPHRASE_FD";
+
+/// Read a passphrase for unattended or interactive use
+///
+/// Sources are tried in order: `CONFECT_PASSPHRASE`, the first line read from
+/// the descriptor in `CONFECT_PASSPHRASE_FD`, then a terminal prompt.
+pub fn read_passphrase(prompt: &str, confirm: bool) -> Result<SecretString> {
+    if let Ok(passphrase) = env::var(PASSPHRASE_ENV) {
+        return Ok(SecretString::new(passphrase));
+    }
+
+    if let Ok(fd) = env::var(PASSPHRASE_FD_ENV) {
+        let fd: u32 = fd.trim().parse().map_err(|_| {
+            ConfectError::Config(format!(
+                "{} must be a file descriptor number",
+                PASSPHRASE_FD_ENV
+            ))
+        })?;
+        let content = fs::read_to_string(format!("/dev/fd/{}", fd))?;
+        let passphrase = content.lines().next().unwrap_or_default().to_string();
+        return Ok(SecretString::new(passphrase));
+    }
+
+    if !Term::stderr().is_term() {
+        return Err(ConfectError::Other(format!(
+            "{} requires a passphrase (set {} or {})",
+            prompt, PASSPHRASE_ENV, PASSPHRASE_FD_ENV
+        )));
+    }
+
+    let mut input = Password::new().with_prompt(prompt);
+    if confirm {
+        input = input.with_confirmation("Confirm passphrase", "Passphrases do not match");
+    }
+
+    Ok(SecretString::new(input.interact()?))
+}
@@ -74,6 +74,12 @@ pub enum ConfectError {
     #[error("Permission denied: {0}")]
     PermissionDenied(PathBuf),
 
+    #[error("Possible secrets found in {0} unencrypted file(s)")]
+    SecretsDetected(usize),
+
+    #[error("Unresolved conflicts in {0} file(s), run 'confect resolve' first")]
+    UnresolvedConflicts(usize),
+
     #[error("Host '{0}' not found in repository")]
     HostNotFound(String),
 
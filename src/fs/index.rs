@@ -0,0 +1,153 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use super::metadata::FileMetadata;
+use crate::error::Result;
+
+/// Directory inside a category holding files stored under opaque IDs
+pub const HIDDEN_DIR: &str = ".hidden";
+
+/// Index file structure
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+struct IndexFile {
+    /// Opaque ID to system path
+    #[serde(default)]
+    files: BTreeMap<String, PathBuf>,
+    /// Opaque ID to file metadata
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    metadata: BTreeMap<String, FileMetadata>,
+}
+
+/// Mapping between system paths and the opaque IDs their encrypted copies
+/// are stored under, kept age-encrypted in the repository
+#[derive(Debug, Default)]
+pub struct PathIndex {
+    ids: BTreeMap<PathBuf, String>,
+    /// Metadata of hidden files, kept here instead of the plaintext store
+    metadata: BTreeMap<PathBuf, FileMetadata>,
+    changed: bool,
+}
+
+impl PathIndex {
+    /// Get the path of a category's index in a host's state directory
+    pub fn path(state_dir: &Path, category: &str) -> PathBuf {
+        state_dir.join("index").join(format!("{}.age", category))
+    }
+
+    /// Get the repository path of a file stored under an ID
+    pub fn repo_path_for(category_dir: &Path, id: &str) -> PathBuf {
+        category_dir.join(HIDDEN_DIR).join(id)
+    }
+
+    /// Parse decrypted index content
+    pub fn from_toml(content: &str) -> Result<Self> {
+        let file: IndexFile = toml::from_str(content)?;
+        let metadata = file
+            .metadata
+            .into_iter()
+            .filter_map(|(id, meta)| Some((file.files.get(&id)?.clone(), meta)))
+            .collect();
+        Ok(Self {
+            ids: file
+                .files
+                .into_iter()
+                .map(|(id, path)| (path, id))
+                .collect(),
+            metadata,
+            changed: false,
+        })
+    }
+
+    /// Serialize the index for encryption
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string_pretty(&IndexFile {
+            files: self
+                .ids
+                .iter()
+                .map(|(path, id)| (id.clone(), path.clone()))
+                .collect(),
+            metadata: self
+                .metadata
+                .iter()
+                .filter_map(|(path, meta)| Some((self.ids.get(path)?.clone(), meta.clone())))
+                .collect(),
+        })?)
+    }
+
+    /// Get the ID a system path is stored under
+    pub fn id(&self, system_path: &Path) -> Option<&str> {
+        self.ids.get(system_path).map(String::as_str)
+    }
+
+    /// Get the system path stored under an ID
+    pub fn system_path(&self, id: &str) -> Option<&Path> {
+        self.ids
+            .iter()
+            .find(|(_, stored)| stored.as_str() == id)
+            .map(|(path, _)| path.as_path())
+    }
+
+    /// Get the ID for a system path, assigning a new random one if needed
+    pub fn assign(&mut self, system_path: &Path) -> Result<String> {
+        if let Some(id) = self.id(system_path) {
+            return Ok(id.to_string());
+        }
+
+        let id = random_id()?;
+        self.ids.insert(system_path.to_path_buf(), id.clone());
+        self.changed = true;
+        Ok(id)
+    }
+
+    /// Forget a system path, returning the ID it was stored under
+    pub fn remove(&mut self, system_path: &Path) -> Option<String> {
+        let id = self.ids.remove(system_path);
+        self.metadata.remove(system_path);
+        self.changed |= id.is_some();
+        id
+    }
+
+    /// Get the metadata recorded for a hidden file
+    pub fn metadata(&self, system_path: &Path) -> Option<&FileMetadata> {
+        self.metadata.get(system_path)
+    }
+
+    /// Record the metadata of a hidden file
+    ///
+    /// Returns `false`, recording nothing, if the path is not hidden.
+    pub fn set_metadata(&mut self, system_path: &Path, meta: FileMetadata) -> bool {
+        if self.id(system_path).is_none() {
+            return false;
+        }
+        if self.metadata.get(system_path) != Some(&meta) {
+            self.metadata.insert(system_path.to_path_buf(), meta);
+            self.changed = true;
+        }
+        true
+    }
+
+    /// Check if the index is empty
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Check if the index has unsaved changes
+    pub fn is_changed(&self) -> bool {
+        self.changed
+    }
+
+    /// Mark the index as saved
+    pub fn mark_saved(&mut self) {
+        self.changed = false;
+    }
+}
+
+/// Generate an ID that reveals nothing about the path or content
+fn random_id() -> Result<String> {
+    let mut bytes = [0u8; 16];
+    File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
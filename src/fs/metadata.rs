@@ -1,16 +1,18 @@
 use chrono::{DateTime, Utc};
 use nix::unistd::{Gid, Group, Uid, User};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
-use std::fs;
+use std::fs::{self, File};
+use std::io::{self, Read};
 use std::os::unix::fs::{MetadataExt, PermissionsExt};
 use std::path::{Path, PathBuf};
 
-use crate::core::Repository;
+use crate::core::{BranchFile, HostStrategy, Repository, COMMON_DIR};
 use crate::error::Result;
 
 /// Metadata for a single file
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct FileMetadata {
     /// File mode (permissions)
     pub mode: u32,
@@ -28,6 +30,9 @@ pub struct FileMetadata {
     /// Symlink target (if this is a symlink)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub symlink_target: Option<PathBuf>,
+    /// Hash of the plaintext of an encrypted file (see [`content_hash`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
 }
 
 impl FileMetadata {
@@ -66,9 +71,22 @@ impl FileMetadata {
             group,
             mtime,
             symlink_target,
+            content_hash: None,
         })
     }
 
+    /// Collect metadata from a file about to be synced
+    ///
+    /// For encrypted files the plaintext hash is recorded too, so changes can
+    /// be detected without an identity.
+    pub fn from_system(path: &Path, encrypted: bool) -> Result<Self> {
+        let mut meta = Self::from_path(path)?;
+        if encrypted && meta.symlink_target.is_none() {
+            meta.content_hash = Some(content_hash(path, File::open(path)?)?);
+        }
+        Ok(meta)
+    }
+
     /// Apply metadata to a file
     pub fn apply_to(&self, path: &Path) -> Result<()> {
         // Handle symlinks specially
@@ -113,37 +131,114 @@ struct MetadataFile {
 /// Store for file metadata
 pub struct MetadataStore {
     entries: HashMap<PathBuf, FileMetadata>,
-    repo_path: PathBuf,
+    dir: PathBuf,
 }
 
 impl MetadataStore {
     /// Load metadata from repository
     pub fn load(repo: &Repository) -> Result<Self> {
-        let repo_path = repo.path().to_path_buf();
-        let metadata_file = repo_path.join(".confect").join("metadata.toml");
+        Self::load_dir(&repo.state_dir())
+    }
 
-        let entries = if metadata_file.exists() {
-            let content = fs::read_to_string(&metadata_file)?;
-            let file: MetadataFile = toml::from_str(&content)?;
+    /// Load the metadata of the files shared by all hosts
+    ///
+    /// `None` when there is no shared layer under the current host.
+    pub fn load_base(repo: &Repository) -> Result<Option<Self>> {
+        match repo.strategy() {
+            HostStrategy::Branch => match repo.base_branch() {
+                Some(branch) => Ok(Some(Self::load_branch(repo, branch)?)),
+                None => Ok(None),
+            },
+            HostStrategy::Directory => Ok(Some(Self::load_dir(
+                &repo.path().join(COMMON_DIR).join(".confect"),
+            )?)),
+        }
+    }
+
+    /// Load metadata from a state directory
+    pub fn load_dir(dir: &Path) -> Result<Self> {
+        let metadata_file = dir.join("metadata.toml");
 
-            file.files
-                .into_iter()
-                .map(|(path, meta)| (PathBuf::from(path), meta))
-                .collect()
+        let entries = if metadata_file.exists() {
+            parse_entries(&fs::read_to_string(&metadata_file)?)?
         } else {
             HashMap::new()
         };
 
-        Ok(Self { entries, repo_path })
+        Ok(Self {
+            entries,
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    /// Load the metadata another branch stores, without checking it out
+    pub fn load_branch(repo: &Repository, branch: &str) -> Result<Self> {
+        let entries = match repo.read_branch_file(branch, Path::new(".confect/metadata.toml"))? {
+            Some(BranchFile::Blob { content, .. }) => {
+                parse_entries(&String::from_utf8_lossy(&content))?
+            }
+            _ => HashMap::new(),
+        };
+
+        Ok(Self {
+            entries,
+            dir: repo.path().join(".confect"),
+        })
+    }
+
+    /// Parse the content of a metadata file kept in `dir`
+    pub fn from_toml(dir: &Path, content: &str) -> Result<Self> {
+        Ok(Self {
+            entries: parse_entries(content)?,
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    /// Merge the metadata files of both sides of a conflict
+    ///
+    /// Each entry takes the version of the side that changed it since
+    /// `base`; where both did, the local one wins. Missing files are empty.
+    pub fn merged(
+        dir: &Path,
+        base: Option<&str>,
+        mine: Option<&str>,
+        theirs: Option<&str>,
+    ) -> Result<Self> {
+        let parse =
+            |content: Option<&str>| content.map_or_else(|| Ok(HashMap::new()), parse_entries);
+        let base = parse(base)?;
+        let mine = parse(mine)?;
+        let theirs = parse(theirs)?;
+
+        let mut entries = HashMap::new();
+        for path in mine.keys().chain(theirs.keys()) {
+            let side = if mine.get(path) == base.get(path) {
+                &theirs
+            } else {
+                &mine
+            };
+            if let Some(meta) = side.get(path) {
+                entries.insert(path.clone(), meta.clone());
+            }
+        }
+
+        Ok(Self {
+            entries,
+            dir: dir.to_path_buf(),
+        })
     }
 
     /// Save metadata to repository
     pub fn save(&self) -> Result<()> {
-        let confect_dir = self.repo_path.join(".confect");
-        fs::create_dir_all(&confect_dir)?;
+        fs::create_dir_all(&self.dir)?;
 
-        let metadata_file = confect_dir.join("metadata.toml");
+        let metadata_file = self.dir.join("metadata.toml");
+        fs::write(&metadata_file, self.to_toml()?)?;
+        Ok(())
+    }
 
+    /// Serialize the metadata file
+    pub fn to_toml(&self) -> Result<String> {
         let file = MetadataFile {
             files: self
                 .entries
@@ -152,15 +247,15 @@ impl MetadataStore {
                 .collect(),
         };
 
-        let content = toml::to_string_pretty(&file)?;
-        fs::write(&metadata_file, content)?;
-        Ok(())
+        Ok(toml::to_string_pretty(&file)?)
     }
 
     /// Update metadata for a file from the current system state
-    pub fn update_from_system(&mut self, path: &Path) -> Result<()> {
-        let meta = FileMetadata::from_path(path)?;
-        self.entries.insert(path.to_path_buf(), meta);
+    pub fn update_from_system(&mut self, path: &Path, encrypted: bool) -> Result<()> {
+        self.entries.insert(
+            path.to_path_buf(),
+            FileMetadata::from_system(path, encrypted)?,
+        );
         Ok(())
     }
 
@@ -187,8 +282,37 @@ impl MetadataStore {
         Ok(())
     }
 
+    /// Set the metadata for a file
+    pub fn set(&mut self, path: &Path, meta: FileMetadata) {
+        self.entries.insert(path.to_path_buf(), meta);
+    }
+
     /// Remove metadata for a file
     pub fn remove(&mut self, path: &Path) {
         self.entries.remove(path);
     }
 }
+
+/// Parse the entries of a metadata file
+fn parse_entries(content: &str) -> Result<HashMap<PathBuf, FileMetadata>> {
+    let file: MetadataFile = toml::from_str(content)?;
+
+    Ok(file
+        .files
+        .into_iter()
+        .map(|(path, meta)| (PathBuf::from(path), meta))
+        .collect())
+}
+
+/// Hash file content for change detection
+///
+/// The path is mixed in so identical secrets in different files do not share
+/// a hash. The content is hashed as it is read.
+pub fn content_hash(path: &Path, mut content: impl Read) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    hasher.update(b"confect-content-hash\0");
+    hasher.update(path.as_os_str().as_encoded_bytes());
+    hasher.update(b"\0");
+    io::copy(&mut content, &mut hasher)?;
+    Ok(format!("sha256:{:x}", hasher.finalize()))
+}
@@ -1,5 +1,9 @@
+mod index;
 mod metadata;
+mod placeholders;
+mod scanner;
 mod tracker;
 
-pub use metadata::MetadataStore;
-pub use tracker::FileTracker;
+pub use metadata::{FileMetadata, MetadataStore};
+pub use scanner::SecretFinding;
+pub use tracker::{FileTracker, LayerCopy};
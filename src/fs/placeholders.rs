@@ -0,0 +1,277 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::core::PlaceholderRule;
+use crate::error::{ConfectError, Result};
+
+/// Matches `{{ secret "name" }}` placeholders
+fn placeholder_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r#"\{\{\s*secret\s+"([^"]+)"\s*\}\}"#).expect("placeholder regex is valid")
+    })
+}
+
+/// Format the placeholder for a secret
+pub fn placeholder(name: &str) -> String {
+    format!("{{{{ secret \"{}\" }}}}", name)
+}
+
+/// Check if content contains secret placeholders
+pub fn has_placeholders(content: &str) -> bool {
+    placeholder_regex().is_match(content)
+}
+
+/// Replace the values matched by placeholder rules with placeholders
+///
+/// Returns the templated content and the extracted secret values.
+pub fn extract(
+    content: &str,
+    rules: &[&PlaceholderRule],
+) -> Result<(String, BTreeMap<String, String>)> {
+    let mut templated = content.to_string();
+    let mut values = BTreeMap::new();
+
+    for rule in rules {
+        let re = Regex::new(&format!("(?m){}", rule.pattern)).map_err(|e| {
+            ConfectError::Config(format!(
+                "Invalid placeholder pattern '{}': {}",
+                rule.pattern, e
+            ))
+        })?;
+
+        let mut result = String::with_capacity(templated.len());
+        let mut last = 0;
+
+        for caps in re.captures_iter(&templated) {
+            let Some(value) = caps.get(1) else {
+                return Err(ConfectError::Config(format!(
+                    "Placeholder pattern '{}' needs a capture group for the secret",
+                    rule.pattern
+                )));
+            };
+
+            // Already templated, e.g. when the rule matches its own placeholder
+            if has_placeholders(value.as_str()) {
+                continue;
+            }
+
+            match values.get(&rule.name) {
+                Some(existing) if existing != value.as_str() => {
+                    return Err(ConfectError::Config(format!(
+                        "Placeholder '{}' matches different values",
+                        rule.name
+                    )));
+                }
+                _ => {
+                    values.insert(rule.name.clone(), value.as_str().to_string());
+                }
+            }
+
+            result.push_str(&templated[last..value.start()]);
+            result.push_str(&placeholder(&rule.name));
+            last = value.end();
+        }
+
+        result.push_str(&templated[last..]);
+        templated = result;
+    }
+
+    Ok((templated, values))
+}
+
+/// Substitute placeholders with the values stored for a file
+pub fn render(content: &str, vault: &SecretVault, system_path: &Path) -> Result<String> {
+    let mut missing = None;
+
+    let rendered = placeholder_regex().replace_all(content, |caps: &regex::Captures| {
+        match vault.get(system_path, &caps[1]) {
+            Some(value) => value.to_string(),
+            None => {
+                missing.get_or_insert_with(|| caps[1].to_string());
+                String::new()
+            }
+        }
+    });
+
+    match missing {
+        Some(name) => Err(ConfectError::Other(format!(
+            "Secret '{}' not found in the vault",
+            name
+        ))),
+        None => Ok(rendered.into_owned()),
+    }
+}
+
+/// Check if content matches a template, whatever the placeholder values are
+pub fn matches_template(content: &str, template: &str) -> bool {
+    let mut pattern = String::from("(?s)^");
+    let mut last = 0;
+
+    for m in placeholder_regex().find_iter(template) {
+        pattern.push_str(&regex::escape(&template[last..m.start()]));
+        pattern.push_str(".*?");
+        last = m.end();
+    }
+    pattern.push_str(&regex::escape(&template[last..]));
+    pattern.push('$');
+
+    Regex::new(&pattern)
+        .map(|re| re.is_match(content))
+        .unwrap_or(false)
+}
+
+/// Names of the placeholders in content
+pub fn names(content: &str) -> Vec<String> {
+    let mut names: Vec<_> = placeholder_regex()
+        .captures_iter(content)
+        .map(|caps| caps[1].to_string())
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Vault file structure
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+struct VaultFile {
+    /// Values stored before they were kept per file, used as a fallback
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    secrets: BTreeMap<String, String>,
+    /// Values by system path, then placeholder name
+    #[serde(default)]
+    files: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+/// Secret values for placeholders, stored age-encrypted in the repository
+///
+/// Values are kept per file, so files may use the same placeholder name for
+/// different secrets.
+#[derive(Debug, Default)]
+pub struct SecretVault {
+    secrets: BTreeMap<String, String>,
+    files: BTreeMap<String, BTreeMap<String, String>>,
+    changed: bool,
+}
+
+impl SecretVault {
+    /// Get the path of the vault in a host's state directory
+    pub fn path(state_dir: &Path) -> PathBuf {
+        state_dir.join("secrets.age")
+    }
+
+    /// Parse decrypted vault content
+    pub fn from_toml(content: &str) -> Result<Self> {
+        let file: VaultFile = toml::from_str(content)?;
+        Ok(Self {
+            secrets: file.secrets,
+            files: file.files,
+            changed: false,
+        })
+    }
+
+    /// Serialize the vault for encryption
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string_pretty(&VaultFile {
+            secrets: self.secrets.clone(),
+            files: self.files.clone(),
+        })?)
+    }
+
+    /// Get a secret value of a file
+    pub fn get(&self, system_path: &Path, name: &str) -> Option<&str> {
+        self.files
+            .get(system_path.to_string_lossy().as_ref())
+            .and_then(|values| values.get(name))
+            .or_else(|| self.secrets.get(name))
+            .map(String::as_str)
+    }
+
+    /// Set a secret value of a file
+    pub fn set(&mut self, system_path: &Path, name: String, value: String) {
+        let values = self
+            .files
+            .entry(system_path.to_string_lossy().into_owned())
+            .or_default();
+        if values.get(&name) != Some(&value) {
+            values.insert(name, value);
+            self.changed = true;
+        }
+    }
+
+    /// Check if the vault has unsaved changes
+    pub fn is_changed(&self) -> bool {
+        self.changed
+    }
+
+    /// Mark the vault as saved
+    pub fn mark_saved(&mut self) {
+        self.changed = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(pattern: &str, name: &str) -> PlaceholderRule {
+        PlaceholderRule {
+            path: "/etc/app/.env".to_string(),
+            pattern: pattern.to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn extracted_values_render_back_to_the_original() -> Result<()> {
+        let content = "HOST=db.internal\nDB_PASSWORD=hunter2\nproxy_set_header X-Api-Key abc123;\n";
+        let db = rule("^DB_PASSWORD=(.*)$", "db-password");
+        let api = rule(r"X-Api-Key (\S+);", "api-key");
+
+        let (templated, values) = extract(content, &[&db, &api])?;
+        assert_eq!(
+            templated,
+            "HOST=db.internal\nDB_PASSWORD={{ secret \"db-password\" }}\n\
+             proxy_set_header X-Api-Key {{ secret \"api-key\" }};\n"
+        );
+        assert_eq!(
+            values.get("db-password").map(String::as_str),
+            Some("hunter2")
+        );
+
+        let env = Path::new("/etc/app/.env");
+        let mut vault = SecretVault::default();
+        for (name, value) in values {
+            vault.set(env, name, value);
+        }
+        assert_eq!(render(&templated, &vault, env)?, content);
+
+        // Names are scoped to their file
+        let other = Path::new("/etc/other/.env");
+        vault.set(other, "db-password".to_string(), "other".to_string());
+        assert_eq!(vault.get(env, "db-password"), Some("hunter2"));
+        assert_eq!(vault.get(other, "db-password"), Some("other"));
+        assert!(render(&templated, &vault, other).is_err());
+
+        // Values stored before they were scoped apply to every file
+        let legacy = SecretVault::from_toml("[secrets]\napi-key = \"abc123\"\n")?;
+        assert_eq!(legacy.get(other, "api-key"), Some("abc123"));
+        let saved = SecretVault::from_toml(&vault.to_toml()?)?;
+        assert_eq!(saved.get(other, "db-password"), Some("other"));
+
+        assert!(matches_template(content, &templated));
+        assert!(matches_template(
+            &content.replace("hunter2", "other"),
+            &templated
+        ));
+        assert!(!matches_template(
+            &content.replace("db.internal", "db.example"),
+            &templated
+        ));
+
+        Ok(())
+    }
+}
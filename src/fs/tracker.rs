@@ -1,15 +1,46 @@
-use std::collections::{HashMap, HashSet};
-use std::fs;
+use age::secrecy::SecretString;
+use git2::Oid;
+use std::cell::{OnceCell, RefCell};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
 use std::path::{Path, PathBuf};
 use walkdir::{DirEntry, WalkDir};
 
 use crate::cli::commands::FileStatus;
-use crate::core::{CategoryManager, Repository};
+use crate::core::{
+    group_branch, BranchFile, Category, CategoryManager, Config, EncryptionConfig, HostStrategy,
+    LogEntry, Repository, BASE_BRANCH, COMMON_DIR, GROUPS_DIR,
+};
+use crate::crypto::{read_passphrase, AgeEncryption};
 use crate::error::{ConfectError, Result};
 
+use super::index::{PathIndex, HIDDEN_DIR};
+use super::metadata::{content_hash, FileMetadata, MetadataStore};
+use super::placeholders::{self, SecretVault};
+use super::scanner::{self, SecretFinding};
+
+/// Bytes compared at a time when checking files for changes
+const CHUNK_SIZE: usize = 64 * 1024;
+
 /// Tracks files between the system and the repository
 pub struct FileTracker<'a> {
     repo: &'a Repository,
+    encryption: EncryptionConfig,
+    /// Decryption identity, loaded on first use (`None` if loading failed)
+    identity: OnceCell<Option<Box<dyn age::Identity>>>,
+    /// Passphrase for passphrase-encrypted files, read on first use
+    passphrase: OnceCell<Option<SecretString>>,
+    /// Stored file metadata, loaded on first use
+    metadata: OnceCell<MetadataStore>,
+    /// Values for secret placeholders, decrypted on first use
+    vault: OnceCell<RefCell<SecretVault>>,
+    /// Opaque IDs of hidden files per category, decrypted on first use
+    indexes: RefCell<HashMap<String, PathIndex>>,
+    /// Layers the host inherits files from, resolved on first use
+    layers: OnceCell<Vec<Layer>>,
 }
 
 /// Files changed while refreshing the repository from the system.
@@ -17,6 +48,8 @@ pub struct FileTracker<'a> {
 pub struct RefreshResult {
     pub updated: Vec<PathBuf>,
     pub deleted: Vec<PathBuf>,
+    /// Changed files left out because they look like they contain secrets
+    pub blocked: Vec<(PathBuf, Vec<SecretFinding>)>,
 }
 
 impl RefreshResult {
@@ -33,466 +66,3642 @@ impl RefreshResult {
     }
 }
 
-impl<'a> FileTracker<'a> {
-    pub fn new(repo: &'a Repository) -> Self {
-        Self { repo }
-    }
+/// Files processed while re-encrypting the repository.
+#[derive(Debug, Default)]
+pub struct RekeyResult {
+    pub rekeyed: Vec<PathBuf>,
+    pub failed: Vec<(PathBuf, ConfectError)>,
+}
 
-    /// Add a file or directory to the repository
-    pub fn add(&self, path: &Path, category: &str, _encrypt: bool) -> Result<Vec<PathBuf>> {
-        let repo_base = self.repo.path();
-        let category_dir = repo_base.join(category);
+/// A copy of a tracked file in one of the layers it is resolved through.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayerCopy {
+    /// Branch or directory of the layer
+    pub layer: String,
+    /// Path of the copy, relative to the layer
+    pub path: PathBuf,
+}
 
-        let mut added_files = Vec::new();
+/// A tree of stored files that can be read without checking it out
+enum Layer {
+    /// The tip of a branch
+    Branch(String),
+    /// A directory of the working tree, relative to the repository root
+    Dir(PathBuf),
+}
 
-        if path.is_dir() {
-            // Walk directory
-            for entry in WalkDir::new(path).follow_links(false) {
-                let entry = entry?;
-                let file_path = entry.path();
+impl Layer {
+    /// List the files and symlinks under `dir`, relative to the layer
+    fn list(&self, repo: &Repository, dir: &Path) -> Result<Vec<PathBuf>> {
+        let base = match self {
+            Layer::Branch(branch) => return repo.list_branch_files(branch, dir),
+            Layer::Dir(base) => repo.path().join(base),
+        };
 
-                // Include regular files and all symlinks
-                if entry.file_type().is_file() || entry.file_type().is_symlink() {
-                    self.copy_to_repo(file_path, &category_dir)?;
-                    added_files.push(file_path.to_path_buf());
+        let mut files = Vec::new();
+        if !base.join(dir).exists() {
+            return Ok(files);
+        }
+        for entry in WalkDir::new(base.join(dir)).follow_links(false) {
+            let entry = entry?;
+            if is_trackable_entry(&entry) {
+                if let Ok(relative) = entry.path().strip_prefix(&base) {
+                    files.push(relative.to_path_buf());
                 }
             }
-        } else if path.is_file() || path.is_symlink() {
-            self.copy_to_repo(path, &category_dir)?;
-            added_files.push(path.to_path_buf());
         }
+        Ok(files)
+    }
 
-        Ok(added_files)
+    /// Read a file, relative to the layer, `None` if it is missing
+    fn read(&self, repo: &Repository, path: &Path) -> Result<Option<BranchFile>> {
+        match self {
+            Layer::Branch(branch) => repo.read_branch_file(branch, path),
+            Layer::Dir(base) => system_file(&repo.path().join(base).join(path)),
+        }
     }
 
-    /// Copy a file to the repository
-    fn copy_to_repo(&self, system_path: &Path, category_dir: &Path) -> Result<()> {
-        let path_str = system_path.to_string_lossy();
-        let relative = path_str.trim_start_matches('/');
-        let repo_path = category_dir.join(relative);
+    /// Load the metadata of the files in the layer
+    fn metadata(&self, repo: &Repository) -> Result<MetadataStore> {
+        match self {
+            Layer::Branch(branch) => MetadataStore::load_branch(repo, branch),
+            Layer::Dir(base) => MetadataStore::load_dir(&repo.path().join(base).join(".confect")),
+        }
+    }
+}
 
-        // Create parent directories
-        if let Some(parent) = repo_path.parent() {
-            fs::create_dir_all(parent)?;
+impl fmt::Display for Layer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Layer::Branch(branch) => write!(f, "{}", branch),
+            Layer::Dir(dir) => write!(f, "{}", dir.display()),
         }
+    }
+}
 
-        // Remove existing file/symlink if exists
-        if repo_path.exists() || repo_path.is_symlink() {
-            let _ = fs::remove_file(&repo_path);
+impl<'a> FileTracker<'a> {
+    pub fn new(repo: &'a Repository) -> Result<Self> {
+        let config = Config::load_global()?;
+        Ok(Self::with_encryption(repo, config.encryption))
+    }
+
+    /// Create a tracker with explicit encryption settings
+    pub fn with_encryption(repo: &'a Repository, encryption: EncryptionConfig) -> Self {
+        Self {
+            repo,
+            encryption,
+            identity: OnceCell::new(),
+            passphrase: OnceCell::new(),
+            metadata: OnceCell::new(),
+            vault: OnceCell::new(),
+            layers: OnceCell::new(),
+            indexes: RefCell::new(HashMap::new()),
         }
+    }
 
-        // Handle symlinks - preserve them as symlinks
-        let meta = fs::symlink_metadata(system_path)?;
-        if meta.file_type().is_symlink() {
-            let target = fs::read_link(system_path)?;
-            #[cfg(unix)]
-            std::os::unix::fs::symlink(&target, &repo_path)?;
-        } else if meta.is_file() {
-            fs::copy(system_path, &repo_path)?;
+    /// Load the local identity used to decrypt repository files
+    ///
+    /// A failure is remembered, so the user is not asked again for every file.
+    fn identity(&self) -> Result<&dyn age::Identity> {
+        if let Some(identity) = self.identity.get() {
+            return identity.as_deref().ok_or_else(|| {
+                ConfectError::Decryption("No usable identity for decryption".to_string())
+            });
         }
-        // Skip non-regular files (sockets, devices, etc.)
 
-        Ok(())
+        let path = self.encryption.decryption_identity_path()?;
+        match AgeEncryption::load_decryption_identity(&path) {
+            Ok(identity) => Ok(self
+                .identity
+                .get_or_init(|| Some(identity))
+                .as_deref()
+                .expect("identity was just stored")),
+            Err(e) => {
+                let _ = self.identity.set(None);
+                Err(e)
+            }
+        }
     }
 
-    /// Remove a file from tracking
-    pub fn remove(&self, path: &Path, delete_from_repo: bool) -> Result<Vec<PathBuf>> {
-        let categories = CategoryManager::load(self.repo)?;
-        let mut removed = Vec::new();
-
-        // Find the file in the repo
-        if let Some(cat) = categories.find_for_path(path) {
-            let repo_path = self.repo.path().join(cat.repo_path_for(path));
+    /// Read the passphrase for passphrase-encrypted files
+    ///
+    /// Only asked once per run; `confirm` repeats the prompt when it is
+    /// interactive, which guards against typos before encrypting.
+    fn passphrase(&self, confirm: bool) -> Result<&SecretString> {
+        if let Some(passphrase) = self.passphrase.get() {
+            return passphrase
+                .as_ref()
+                .ok_or_else(|| ConfectError::Decryption("No passphrase available".to_string()));
+        }
 
-            if repo_path.exists() {
-                if delete_from_repo {
-                    fs::remove_file(&repo_path)?;
-                }
-                removed.push(path.to_path_buf());
+        match read_passphrase("Passphrase for encrypted files", confirm) {
+            Ok(passphrase) => Ok(self
+                .passphrase
+                .get_or_init(|| Some(passphrase))
+                .as_ref()
+                .expect("passphrase was just stored")),
+            Err(e) => {
+                let _ = self.passphrase.set(None);
+                Err(e)
             }
         }
+    }
 
-        Ok(removed)
+    /// Load the stored file metadata
+    fn metadata(&self) -> Result<&MetadataStore> {
+        if let Some(metadata) = self.metadata.get() {
+            return Ok(metadata);
+        }
+
+        let metadata = MetadataStore::load(self.repo)?;
+        Ok(self.metadata.get_or_init(|| metadata))
     }
 
-    /// Get the status of tracked files
-    pub fn status(&self, category: Option<&str>) -> Result<HashMap<PathBuf, FileStatus>> {
-        let categories = CategoryManager::load(self.repo)?;
-        let mut result = HashMap::new();
+    /// Load the secret vault, decrypting it on first use
+    fn vault(&self) -> Result<&RefCell<SecretVault>> {
+        if let Some(vault) = self.vault.get() {
+            return Ok(vault);
+        }
 
-        let cats_to_check: Vec<_> = if let Some(name) = category {
-            vec![categories.get(name)?]
+        let path = SecretVault::path(&self.repo.state_dir());
+        let vault = if path.exists() {
+            let plaintext = self.decrypt(&fs::read(&path)?)?;
+            SecretVault::from_toml(&String::from_utf8_lossy(&plaintext))?
         } else {
-            categories.list()
+            SecretVault::default()
         };
 
-        for cat in cats_to_check {
-            let category_dir = self.repo.path().join(&cat.name);
-            if !category_dir.exists() {
+        Ok(self.vault.get_or_init(|| RefCell::new(vault)))
+    }
+
+    /// Build the encryption used for the secret vault
+    fn vault_encryptor(&self) -> Result<AgeEncryption> {
+        AgeEncryption::for_category(&self.encryption, self.repo.path(), None)
+    }
+
+    /// Write the secret vault back if placeholders changed it
+    fn save_vault(&self) -> Result<()> {
+        let Some(vault) = self.vault.get() else {
+            return Ok(());
+        };
+
+        let mut vault = vault.borrow_mut();
+        if vault.is_changed() {
+            let ciphertext = self
+                .vault_encryptor()?
+                .encrypt(vault.to_toml()?.as_bytes())?;
+            fs::write(SecretVault::path(&self.repo.state_dir()), ciphertext)?;
+            vault.mark_saved();
+        }
+
+        Ok(())
+    }
+
+    /// Use a category's path index, decrypting it on first use
+    fn with_index<T>(
+        &self,
+        cat: &Category,
+        f: impl FnOnce(&mut PathIndex) -> Result<T>,
+    ) -> Result<T> {
+        let mut indexes = self.indexes.borrow_mut();
+
+        if !indexes.contains_key(&cat.name) {
+            let path = PathIndex::path(&self.repo.state_dir(), &cat.name);
+            let index = if path.exists() {
+                let plaintext = self.decrypt(&fs::read(&path)?)?;
+                PathIndex::from_toml(&String::from_utf8_lossy(&plaintext))?
+            } else {
+                PathIndex::default()
+            };
+            indexes.insert(cat.name.clone(), index);
+        }
+
+        f(indexes.get_mut(&cat.name).expect("index was just loaded"))
+    }
+
+    /// Write back path indexes changed by adding, moving or removing files
+    ///
+    /// Each index is encrypted like the files of its category.
+    fn save_indexes(&self, categories: &CategoryManager) -> Result<()> {
+        for (name, index) in self.indexes.borrow_mut().iter_mut() {
+            if !index.is_changed() {
                 continue;
             }
 
-            // Walk repository files
-            for entry in WalkDir::new(&category_dir).follow_links(false) {
-                let entry = entry?;
-                if !is_trackable_entry(&entry) {
-                    continue;
+            let path = PathIndex::path(&self.repo.state_dir(), name);
+            if index.is_empty() {
+                if path.exists() {
+                    fs::remove_file(&path)?;
                 }
-
-                let repo_file = entry.path();
-
-                // Get system path
-                if let Some(system_path) = cat.system_path_for(
-                    repo_file
-                        .strip_prefix(self.repo.path())
-                        .unwrap_or(repo_file),
-                ) {
-                    let status = self.compare_files(&system_path, repo_file)?;
-                    if status != FileStatus::Modified
-                        || !self.files_equal(&system_path, repo_file)?
-                    {
-                        result.insert(system_path, status);
-                    }
+            } else {
+                let ciphertext = self
+                    .encryptor(categories.get(name).ok())?
+                    .encrypt(index.to_toml()?.as_bytes())?;
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
                 }
+                fs::write(&path, ciphertext)?;
             }
+            index.mark_saved();
         }
 
-        Ok(result)
+        Ok(())
     }
 
-    /// Compare a system file with its repository copy
-    fn compare_files(&self, system_path: &Path, repo_path: &Path) -> Result<FileStatus> {
-        let system_exists = system_path.exists();
-        let repo_exists = repo_path.exists();
+    /// Get where a file is stored in the repository
+    fn repo_path(&self, cat: &Category, system_path: &Path) -> Result<PathBuf> {
+        let id = self.with_index(cat, |index| Ok(index.id(system_path).map(str::to_string)))?;
 
-        match (system_exists, repo_exists) {
-            (true, true) => Ok(FileStatus::Modified), // Filtered later if equal
-            (true, false) => Ok(FileStatus::Added),
-            (false, true) => Ok(FileStatus::Deleted),
-            (false, false) => Ok(FileStatus::Missing),
-        }
+        Ok(self.repo.path().join(match id {
+            Some(id) => PathIndex::repo_path_for(&cat.dir(), &id),
+            None => cat.repo_path_for(system_path),
+        }))
     }
 
-    /// Check if two files or symlinks have the same content
-    fn files_equal(&self, path1: &Path, path2: &Path) -> Result<bool> {
-        let meta1 = fs::symlink_metadata(path1)?;
-        let meta2 = fs::symlink_metadata(path2)?;
-
-        if meta1.file_type().is_symlink() || meta2.file_type().is_symlink() {
-            return Ok(meta1.file_type().is_symlink()
-                && meta2.file_type().is_symlink()
-                && fs::read_link(path1)? == fs::read_link(path2)?);
+    /// Choose where to store a file, moving it between its real path and
+    /// an opaque ID when the category setting or its encryption changed
+    fn store_path(&self, cat: &Category, system_path: &Path, hide: bool) -> Result<PathBuf> {
+        let plain_path = self.repo.path().join(cat.repo_path_for(system_path));
+
+        if hide {
+            let id = self.with_index(cat, |index| index.assign(system_path))?;
+            remove_stored(&plain_path)?;
+            return Ok(self
+                .repo
+                .path()
+                .join(PathIndex::repo_path_for(&cat.dir(), &id)));
         }
 
-        if !meta1.is_file() || !meta2.is_file() {
-            return Ok(false);
+        if let Some(id) = self.with_index(cat, |index| Ok(index.remove(system_path)))? {
+            remove_stored(
+                &self
+                    .repo
+                    .path()
+                    .join(PathIndex::repo_path_for(&cat.dir(), &id)),
+            )?;
         }
-
-        let content1 = fs::read(path1)?;
-        let content2 = fs::read(path2)?;
-        Ok(content1 == content2)
+        Ok(plain_path)
     }
 
-    /// Get diff between system file and repo file
-    pub fn diff_file(&self, system_path: &Path) -> Result<String> {
-        let categories = CategoryManager::load(self.repo)?;
+    /// List the files stored for a category with their system paths
+    ///
+    /// Files under opaque IDs are resolved through the category's index.
+    fn stored_files(&self, cat: &Category) -> Result<Vec<(PathBuf, PathBuf)>> {
+        let category_dir = self.repo.path().join(cat.dir());
+        let hidden_dir = category_dir.join(HIDDEN_DIR);
+        let mut files = Vec::new();
 
-        if let Some(cat) = categories.find_for_path(system_path) {
-            let repo_path = self.repo.path().join(cat.repo_path_for(system_path));
+        if !category_dir.exists() {
+            return Ok(files);
+        }
 
-            if !repo_path.exists() {
-                return Ok(format!(
-                    "File only exists in system: {}",
-                    system_path.display()
-                ));
-            }
-            if !system_path.exists() {
-                return Ok(format!("File only exists in repo: {}", repo_path.display()));
+        for entry in WalkDir::new(&category_dir).follow_links(false) {
+            let entry = entry?;
+            if !is_trackable_entry(&entry) {
+                continue;
             }
 
-            // Simple line-by-line diff
-            let system_content = fs::read_to_string(system_path).unwrap_or_default();
-            let repo_content = fs::read_to_string(&repo_path).unwrap_or_default();
+            let repo_file = entry.path();
+            let system_path = match repo_file.strip_prefix(&hidden_dir) {
+                Ok(id) => self.with_index(cat, |index| {
+                    Ok(index
+                        .system_path(&id.to_string_lossy())
+                        .map(Path::to_path_buf))
+                })?,
+                Err(_) => cat.system_path_for(
+                    repo_file
+                        .strip_prefix(self.repo.path())
+                        .unwrap_or(repo_file),
+                ),
+            };
 
-            if system_content == repo_content {
-                return Ok(String::new());
+            if let Some(system_path) = system_path {
+                files.push((repo_file.to_path_buf(), system_path));
             }
+        }
 
-            let mut diff = String::new();
-            diff.push_str(&format!("--- a/{}\n", system_path.display()));
-            diff.push_str(&format!("+++ b/{}\n", repo_path.display()));
+        Ok(files)
+    }
 
-            // Very basic diff - show changed lines
-            let system_lines: Vec<_> = system_content.lines().collect();
-            let repo_lines: Vec<_> = repo_content.lines().collect();
+    /// Replace secret values with placeholders for the repository copy
+    ///
+    /// Returns `None` when no placeholder rule matches the file.
+    fn templated_content(
+        &self,
+        category: Option<&Category>,
+        path: &Path,
+    ) -> Result<Option<(String, BTreeMap<String, String>)>> {
+        let Some(cat) = category else {
+            return Ok(None);
+        };
 
-            for (i, (s, r)) in system_lines.iter().zip(repo_lines.iter()).enumerate() {
-                if s != r {
-                    diff.push_str(&format!("@@ -{},{} +{},{} @@\n", i + 1, 1, i + 1, 1));
-                    diff.push_str(&format!("-{}\n", r));
-                    diff.push_str(&format!("+{}\n", s));
-                }
-            }
+        let rules = cat.placeholder_rules(path);
+        if rules.is_empty() || !is_regular_file(path) {
+            return Ok(None);
+        }
 
-            // Handle length differences
-            if system_lines.len() > repo_lines.len() {
-                for line in &system_lines[repo_lines.len()..] {
-                    diff.push_str(&format!("+{}\n", line));
-                }
-            } else if repo_lines.len() > system_lines.len() {
-                for line in &repo_lines[system_lines.len()..] {
-                    diff.push_str(&format!("-{}\n", line));
-                }
-            }
+        let Ok(content) = String::from_utf8(fs::read(path)?) else {
+            return Ok(None);
+        };
 
-            Ok(diff)
-        } else {
-            Err(ConfectError::PathNotTracked(system_path.to_path_buf()))
+        let (templated, values) = placeholders::extract(&content, &rules)?;
+        Ok((!values.is_empty()).then_some((templated, values)))
+    }
+
+    /// Scan the content that would be stored unencrypted for a file
+    fn scan_stored(&self, category: Option<&Category>, path: &Path) -> Result<Vec<SecretFinding>> {
+        match self.templated_content(category, path)? {
+            Some((templated, _)) => Ok(scanner::scan(templated.as_bytes())),
+            None => Ok(scanner::scan_reader(BufReader::new(File::open(path)?))?),
         }
     }
 
-    /// Refresh all tracked files from system to repository
-    pub fn refresh_all(&self) -> Result<RefreshResult> {
-        let categories = CategoryManager::load(self.repo)?;
-        let mut result = RefreshResult::default();
+    /// Build the encryption used for files in a category
+    fn encryptor(&self, category: Option<&Category>) -> Result<AgeEncryption> {
+        match category.filter(|c| c.passphrase) {
+            Some(_) => Ok(AgeEncryption::with_passphrase(
+                self.passphrase(true)?.clone(),
+            )),
+            None => AgeEncryption::for_category(&self.encryption, self.repo.path(), category),
+        }
+    }
 
-        for cat in categories.list() {
-            let category_dir = self.repo.path().join(&cat.name);
-            let mut seen_system_paths = HashSet::new();
+    /// Decrypt data with the identity or passphrase it needs
+    fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        AgeEncryption::decrypt_with(ciphertext, || self.identity(), || self.passphrase(false))
+    }
 
-            // Expand paths in category. Exact directory paths are tracked recursively.
-            for pattern in &cat.paths {
-                self.refresh_pattern(
-                    cat,
-                    pattern,
-                    &category_dir,
-                    &mut seen_system_paths,
-                    &mut result.updated,
-                )?;
-            }
+    /// Open a stored file for streaming decryption
+    fn decrypt_file(&self, path: &Path) -> Result<impl Read> {
+        self.decrypt_reader(File::open(path)?)
+    }
 
-            if !category_dir.exists() {
-                continue;
-            }
+    /// Open a reader over the plaintext of encrypted content
+    fn decrypt_reader<R: Read>(&self, ciphertext: R) -> Result<impl Read> {
+        AgeEncryption::decrypt_stream_with(
+            ciphertext,
+            || self.identity(),
+            || self.passphrase(false),
+        )
+    }
 
-            let mut repo_files = Vec::new();
-            for entry in WalkDir::new(&category_dir).follow_links(false) {
-                let entry = entry?;
-                if is_trackable_entry(&entry) {
-                    repo_files.push(entry.path().to_path_buf());
-                }
-            }
+    /// Get the layers this host inherits files from, most specific first
+    fn base_layers(&self) -> Result<&[Layer]> {
+        if let Some(layers) = self.layers.get() {
+            return Ok(layers);
+        }
 
-            for repo_file in repo_files {
-                if let Some(system_path) = cat.system_path_for(
-                    repo_file
-                        .strip_prefix(self.repo.path())
-                        .unwrap_or(&repo_file),
-                ) {
-                    if !seen_system_paths.contains(&system_path) && cat.matches(&system_path) {
-                        fs::remove_file(&repo_file)?;
-                        result.deleted.push(system_path);
+        let base = match self.repo.strategy() {
+            HostStrategy::Branch => self
+                .repo
+                .base_branch()
+                .map(|branch| Layer::Branch(branch.to_string())),
+            HostStrategy::Directory => Some(Layer::Dir(PathBuf::from(COMMON_DIR))),
+        };
+        let layers = self.inherited_layers(self.repo.current_host()?, base)?;
+        Ok(self.layers.get_or_init(|| layers))
+    }
+
+    /// Get the layers a host inherits files from: its groups in order, then
+    /// `base`, the layer shared by every host
+    ///
+    /// Group branches that exist neither locally nor on a remote are skipped.
+    fn inherited_layers(&self, host: &str, base: Option<Layer>) -> Result<Vec<Layer>> {
+        let mut layers = Vec::new();
+        for group in self.repo.host_groups(host)? {
+            match self.repo.strategy() {
+                HostStrategy::Branch => {
+                    let branch = group_branch(&group);
+                    if self.repo.has_branch(&branch)? {
+                        layers.push(Layer::Branch(branch));
                     }
                 }
+                HostStrategy::Directory => {
+                    layers.push(Layer::Dir(Path::new(GROUPS_DIR).join(group)));
+                }
             }
-
-            remove_empty_dirs(&category_dir)?;
         }
+        layers.extend(base);
 
-        Ok(result)
+        Ok(layers)
     }
 
-    fn refresh_pattern(
+    /// Find the layer a file is inherited from, unless the host stores its
+    /// own copy or the category excludes it
+    fn inherited_file(
         &self,
-        cat: &crate::core::Category,
-        pattern: &str,
-        category_dir: &Path,
-        seen_system_paths: &mut HashSet<PathBuf>,
-        updated: &mut Vec<PathBuf>,
-    ) -> Result<()> {
-        for path in glob::glob(pattern)? {
-            let path = path?;
-            if !cat.matches(&path) {
-                continue;
+        cat: &Category,
+        system_path: &Path,
+    ) -> Result<Option<(&Layer, BranchFile)>> {
+        let layers = self.base_layers()?;
+        if layers.is_empty() {
+            return Ok(None);
+        }
+
+        let repo_path = self.repo_path(cat, system_path)?;
+        if repo_path.exists() || repo_path.is_symlink() || !cat.matches(system_path) {
+            return Ok(None);
+        }
+
+        let layer_path = cat.layer_path_for(system_path);
+        for layer in layers {
+            if let Some(file) = layer.read(self.repo, &layer_path)? {
+                return Ok(Some((layer, file)));
             }
+        }
 
-            if path.is_dir() {
-                let mut entries = WalkDir::new(&path).follow_links(false).into_iter();
-                while let Some(entry) = entries.next() {
-                    let entry = entry?;
-                    let entry_path = entry.path();
+        Ok(None)
+    }
 
-                    if entry.file_type().is_dir() && !cat.matches(entry_path) {
-                        entries.skip_current_dir();
-                        continue;
-                    }
+    /// Read a file from the layers the host inherits, unless the host stores
+    /// its own copy or the category excludes it
+    fn base_file(&self, cat: &Category, system_path: &Path) -> Result<Option<BranchFile>> {
+        Ok(self.inherited_file(cat, system_path)?.map(|(_, file)| file))
+    }
 
-                    if is_trackable_entry(&entry) {
-                        self.refresh_file(entry_path, category_dir, seen_system_paths, updated)?;
-                    }
+    /// List the files a category takes from the layers the host inherits
+    ///
+    /// Files the host stores itself override these and are left out.
+    fn base_files(&self, cat: &Category) -> Result<Vec<(PathBuf, BranchFile)>> {
+        let hidden_dir = Path::new(&cat.name).join(HIDDEN_DIR);
+        let mut system_paths = BTreeSet::new();
+
+        for layer in self.base_layers()? {
+            for repo_file in layer.list(self.repo, Path::new(&cat.name))? {
+                if repo_file.starts_with(&hidden_dir) {
+                    continue;
+                }
+                if let Some(system_path) = cat.system_path_for(&repo_file) {
+                    system_paths.insert(system_path);
                 }
-            } else if is_trackable_path(&path) {
-                self.refresh_file(&path, category_dir, seen_system_paths, updated)?;
             }
         }
 
-        Ok(())
+        let mut files = Vec::new();
+        for system_path in system_paths {
+            if let Some(file) = self.base_file(cat, &system_path)? {
+                files.push((system_path, file));
+            }
+        }
+
+        Ok(files)
     }
 
-    fn refresh_file(
-        &self,
-        path: &Path,
-        category_dir: &Path,
-        seen_system_paths: &mut HashSet<PathBuf>,
-        updated: &mut Vec<PathBuf>,
-    ) -> Result<()> {
-        seen_system_paths.insert(path.to_path_buf());
+    /// List the layers storing a copy of a tracked file, in the order they
+    /// are resolved: the host, its groups, then `main` (or `common`)
+    ///
+    /// The first entry supplies the effective version. Inherited layers are
+    /// only listed where the category covers the path.
+    pub fn which(&self, system_path: &Path) -> Result<Vec<LayerCopy>> {
+        let categories = CategoryManager::load(self.repo)?;
+        let cat = categories
+            .find_for_path(system_path)
+            .ok_or_else(|| ConfectError::PathNotTracked(system_path.to_path_buf()))?;
 
-        let repo_path = category_dir.join(path.to_string_lossy().trim_start_matches('/'));
+        let mut copies = Vec::new();
+        let repo_path = self.repo_path(cat, system_path)?;
+        if repo_path.exists() || repo_path.is_symlink() {
+            let host_dir = self.repo.host_dir(self.repo.current_host()?);
+            let layer = match self.repo.strategy() {
+                HostStrategy::Branch => self.repo.current_branch()?,
+                HostStrategy::Directory => host_dir.display().to_string(),
+            };
+            let relative = repo_path
+                .strip_prefix(self.repo.path().join(&host_dir))
+                .unwrap_or(&repo_path);
+            copies.push(LayerCopy {
+                layer,
+                path: relative.to_path_buf(),
+            });
+        }
 
-        if !repo_path.exists() || !self.files_equal(path, &repo_path)? {
-            self.copy_to_repo(path, category_dir)?;
-            updated.push(path.to_path_buf());
+        if cat.matches(system_path) {
+            let layer_path = cat.layer_path_for(system_path);
+            for layer in self.base_layers()? {
+                if layer.read(self.repo, &layer_path)?.is_some() {
+                    copies.push(LayerCopy {
+                        layer: layer.to_string(),
+                        path: layer_path.clone(),
+                    });
+                }
+            }
         }
 
-        Ok(())
+        Ok(copies)
     }
 
-    /// Restore a file from repository to system
-    pub fn restore_file(&self, system_path: &Path) -> Result<()> {
+    /// Get the system path of a file in the repository
+    ///
+    /// `repo_path` is relative to the repository root and may point into this
+    /// host's categories or, with the directory strategy, into `common/`.
+    /// `None` for files confect keeps for itself or other hosts.
+    pub fn system_path_of(&self, repo_path: &Path) -> Result<Option<PathBuf>> {
         let categories = CategoryManager::load(self.repo)?;
-
-        if let Some(cat) = categories.find_for_path(system_path) {
-            let repo_path = self.repo.path().join(cat.repo_path_for(system_path));
-
-            // Check if exists (file or symlink)
-            if !repo_path.exists() && !repo_path.is_symlink() {
-                return Err(ConfectError::FileNotFound(repo_path));
+        for cat in categories.list() {
+            if let Ok(relative) = repo_path.strip_prefix(cat.dir()) {
+                let Ok(id) = relative.strip_prefix(HIDDEN_DIR) else {
+                    return Ok(cat.system_path_for(repo_path));
+                };
+                let id = id.to_string_lossy();
+                return self.with_index(cat, |index| {
+                    Ok(index.system_path(&id).map(Path::to_path_buf))
+                });
             }
 
-            // Create parent directories
-            if let Some(parent) = system_path.parent() {
-                fs::create_dir_all(parent)?;
+            if self.repo.strategy() == HostStrategy::Directory {
+                let shared = repo_path.strip_prefix(COMMON_DIR);
+                if let Some(layer_path) = shared.ok().filter(|p| p.starts_with(&cat.name)) {
+                    return Ok(cat.system_path_for(layer_path));
+                }
             }
+        }
 
-            // Remove existing file/symlink
-            if system_path.exists() || system_path.is_symlink() {
-                let _ = fs::remove_file(system_path);
+        Ok(None)
+    }
+
+    /// List the commits that changed a tracked file, a category, or with
+    /// neither, anything in the repository
+    ///
+    /// Copies in shared directories are followed too with the directory
+    /// strategy. Shared branches are not walked unless merged.
+    pub fn log(
+        &self,
+        system_path: Option<&Path>,
+        category: Option<&str>,
+        patch: bool,
+        limit: Option<usize>,
+    ) -> Result<Vec<LogEntry>> {
+        let categories = CategoryManager::load(self.repo)?;
+        let mut paths = Vec::new();
+
+        if let Some(system_path) = system_path {
+            let cat = categories
+                .find_for_path(system_path)
+                .ok_or_else(|| ConfectError::PathNotTracked(system_path.to_path_buf()))?;
+            paths.push(cat.repo_path_for(system_path));
+            if let Some(id) =
+                self.with_index(cat, |index| Ok(index.id(system_path).map(str::to_string)))?
+            {
+                paths.push(PathIndex::repo_path_for(&cat.dir(), &id));
+            }
+            let layer_path = cat.layer_path_for(system_path);
+            for layer in self.base_layers()? {
+                if let Layer::Dir(dir) = layer {
+                    paths.push(dir.join(&layer_path));
+                }
+            }
+        } else if let Some(name) = category {
+            let cat = categories.get(name)?;
+            paths.push(cat.dir());
+            for layer in self.base_layers()? {
+                if let Layer::Dir(dir) = layer {
+                    paths.push(dir.join(&cat.name));
+                }
             }
+        }
 
-            // Restore - handle symlinks
-            let meta = fs::symlink_metadata(&repo_path)?;
-            if meta.file_type().is_symlink() {
-                let target = fs::read_link(&repo_path)?;
-                #[cfg(unix)]
-                std::os::unix::fs::symlink(&target, system_path)?;
-            } else {
-                fs::copy(&repo_path, system_path)?;
+        self.repo.log(&paths, patch, limit)
+    }
+
+    /// Get the metadata stored for a file at the last sync
+    ///
+    /// Files stored under hidden paths keep theirs in the encrypted index.
+    pub fn stored_metadata(&self, system_path: &Path) -> Result<Option<FileMetadata>> {
+        let categories = CategoryManager::load(self.repo)?;
+        if let Some(cat) = categories.find_for_path(system_path) {
+            if let Some(meta) =
+                self.with_index(cat, |index| Ok(index.metadata(system_path).cloned()))?
+            {
+                return Ok(Some(meta));
             }
+        }
 
-            Ok(())
-        } else {
-            Err(ConfectError::PathNotTracked(system_path.to_path_buf()))
+        Ok(self.metadata()?.get(system_path).cloned())
+    }
+
+    /// Record the metadata of synced files and forget that of deleted ones
+    ///
+    /// Hidden files get theirs recorded in the encrypted index instead of the
+    /// plaintext metadata, which would name their paths.
+    pub fn update_metadata(&self, result: &RefreshResult) -> Result<()> {
+        let categories = CategoryManager::load(self.repo)?;
+        let mut store = MetadataStore::load(self.repo)?;
+
+        for path in &result.updated {
+            let meta = FileMetadata::from_system(path, self.is_stored_encrypted(path))?;
+            let hidden = match categories.find_for_path(path) {
+                Some(cat) => {
+                    self.with_index(cat, |index| Ok(index.set_metadata(path, meta.clone())))?
+                }
+                None => false,
+            };
+            if hidden {
+                store.remove(path);
+            } else {
+                store.set(path, meta);
+            }
         }
+        for path in &result.deleted {
+            store.remove(path);
+        }
+
+        store.save()?;
+        self.save_indexes(&categories)
     }
 
-    /// List all files in a category
-    pub fn list_files_in_category(&self, category_name: &str) -> Result<Vec<PathBuf>> {
+    /// Get the metadata of a file inherited from a group or shared layer
+    ///
+    /// `None` when the host stores its own copy or no layer has one.
+    pub fn inherited_metadata(&self, system_path: &Path) -> Result<Option<FileMetadata>> {
         let categories = CategoryManager::load(self.repo)?;
-        let cat = categories.get(category_name)?;
+        let Some(cat) = categories.find_for_path(system_path) else {
+            return Ok(None);
+        };
+        let Some((layer, _)) = self.inherited_file(cat, system_path)? else {
+            return Ok(None);
+        };
 
-        let category_dir = self.repo.path().join(&cat.name);
-        let mut files = Vec::new();
+        Ok(layer.metadata(self.repo)?.get(system_path).cloned())
+    }
+
+    /// Add a file or directory to the repository
+    ///
+    /// Files are encrypted when `encrypt` is set, when the category's
+    /// encryption patterns match them, or when the sensitive-path policy
+    /// requires it. Sensitive files are rejected up front when they cannot be
+    /// encrypted.
+    pub fn add(&self, path: &Path, category: &str, encrypt: bool) -> Result<Vec<PathBuf>> {
+        let category_dir = self
+            .repo
+            .path()
+            .join(self.repo.host_dir(self.repo.current_host()?))
+            .join(category);
+
+        let categories = CategoryManager::load(self.repo)?;
+        let cat = categories.get(category).ok();
+        let should_encrypt = |p: &Path| {
+            encrypt
+                || cat.map_or_else(
+                    || categories.policy().requires_encryption(p),
+                    |c| c.should_encrypt(p),
+                )
+        };
 
-        if category_dir.exists() {
-            for entry in WalkDir::new(&category_dir).follow_links(false) {
+        let mut files = Vec::new();
+        if path.is_dir() {
+            // Walk directory
+            for entry in WalkDir::new(path).follow_links(false) {
                 let entry = entry?;
-                if is_trackable_entry(&entry) {
-                    let repo_path = entry.path();
-                    if let Some(system_path) = cat.system_path_for(
-                        repo_path
-                            .strip_prefix(self.repo.path())
-                            .unwrap_or(repo_path),
-                    ) {
-                        files.push(system_path);
-                    }
+
+                // Include regular files and all symlinks
+                if entry.file_type().is_file() || entry.file_type().is_symlink() {
+                    files.push(entry.path().to_path_buf());
                 }
             }
+        } else if path.is_file() || path.is_symlink() {
+            files.push(path.to_path_buf());
+        }
+
+        // Fail before copying anything if a sensitive file cannot be encrypted
+        if let Some(sensitive) = files
+            .iter()
+            .find(|f| categories.policy().requires_encryption(f) && is_regular_file(f))
+        {
+            self.encryptor(cat).map_err(|e| {
+                ConfectError::Encryption(format!(
+                    "{} is a sensitive path and must be encrypted: {}",
+                    sensitive.display(),
+                    e
+                ))
+            })?;
+        }
+
+        for file in &files {
+            self.copy_to_repo(file, &category_dir, cat, should_encrypt(file))?;
         }
+        self.save_vault()?;
+        self.save_indexes(&categories)?;
 
         Ok(files)
     }
 
-    /// List all tracked files
-    pub fn list_all_tracked_files(&self) -> Result<Vec<PathBuf>> {
+    /// Store the system copy of a tracked file in place of the repository one
+    /// at `repo_path`, relative to the repository
+    ///
+    /// A copy in a shared directory is replaced there. Unlike `sync`, the file
+    /// is stored even if it matches a shared layer. Unencrypted files with
+    /// possible secrets are refused.
+    pub fn store_system_copy(&self, system_path: &Path, repo_path: &Path) -> Result<()> {
         let categories = CategoryManager::load(self.repo)?;
-        let mut files = Vec::new();
+        let cat = categories
+            .find_for_path(system_path)
+            .ok_or_else(|| ConfectError::PathNotTracked(system_path.to_path_buf()))?;
+
+        let encrypt = cat.should_encrypt(system_path);
+        if !encrypt
+            && !cat.allows_secrets(system_path)
+            && is_regular_file(system_path)
+            && !self.scan_stored(Some(cat), system_path)?.is_empty()
+        {
+            return Err(ConfectError::SecretsDetected(1));
+        }
 
-        for cat in categories.list() {
-            files.extend(self.list_files_in_category(&cat.name)?);
+        if repo_path.starts_with(cat.dir()) {
+            let category_dir = self.repo.path().join(cat.dir());
+            self.copy_to_repo(system_path, &category_dir, Some(cat), encrypt)?;
+        } else {
+            self.copy_to(system_path, Some(cat), encrypt, |_| {
+                Ok(self.repo.path().join(repo_path))
+            })?;
         }
+        self.save_vault()?;
+        self.save_indexes(&categories)?;
 
-        Ok(files)
+        Ok(())
     }
 
-    /// Count files in a category
-    pub fn count_files_in_category(&self, category_name: &str) -> Result<usize> {
-        Ok(self.list_files_in_category(category_name)?.len())
+    /// Scan the files `add` would store unencrypted for possible secrets
+    ///
+    /// Files covered by the category's `allow_secrets` patterns are skipped.
+    pub fn scan(
+        &self,
+        path: &Path,
+        category: &str,
+        encrypt: bool,
+    ) -> Result<Vec<(PathBuf, Vec<SecretFinding>)>> {
+        if encrypt {
+            return Ok(Vec::new());
+        }
+
+        let categories = CategoryManager::load(self.repo)?;
+        let cat = categories.get(category).ok();
+        let mut flagged = Vec::new();
+
+        for entry in WalkDir::new(path).follow_links(false) {
+            let entry = entry?;
+            let file_path = entry.path();
+
+            if !entry.file_type().is_file()
+                || categories.policy().requires_encryption(file_path)
+                || cat.is_some_and(|c| c.should_encrypt(file_path) || c.allows_secrets(file_path))
+            {
+                continue;
+            }
+
+            let findings = self.scan_stored(cat, file_path)?;
+            if !findings.is_empty() {
+                flagged.push((file_path.to_path_buf(), findings));
+            }
+        }
+
+        Ok(flagged)
     }
 
-    /// Count all tracked files
-    pub fn count_all_files(&self) -> Result<usize> {
-        Ok(self.list_all_tracked_files()?.len())
+    /// Copy a file to the repository, encrypting regular files if requested
+    fn copy_to_repo(
+        &self,
+        system_path: &Path,
+        category_dir: &Path,
+        category: Option<&Category>,
+        encrypt: bool,
+    ) -> Result<()> {
+        self.copy_to(system_path, category, encrypt, |encrypted| match category {
+            Some(cat) => self.store_path(cat, system_path, cat.hide_paths && encrypted),
+            None => Ok(category_dir.join(system_path.to_string_lossy().trim_start_matches('/'))),
+        })
     }
 
-    /// Get category name for a path
-    pub fn get_category(&self, path: &Path) -> Result<String> {
+    /// Copy a file to the repository path `destination` picks, given whether
+    /// the copy is encrypted
+    fn copy_to(
+        &self,
+        system_path: &Path,
+        category: Option<&Category>,
+        encrypt: bool,
+        destination: impl FnOnce(bool) -> Result<PathBuf>,
+    ) -> Result<()> {
+        let meta = fs::symlink_metadata(system_path)?;
+
+        // Resolve recipients before touching the repository copy, so a
+        // misconfiguration never leaves a plaintext or missing file behind
+        let encryption = if encrypt && meta.is_file() {
+            Some(self.encryptor(category)?)
+        } else {
+            None
+        };
+
+        // Move secret values into the vault, leaving placeholders behind
+        let templated = if encryption.is_none() {
+            self.templated_content(category, system_path)?
+        } else {
+            None
+        };
+        if let Some((_, values)) = &templated {
+            self.vault_encryptor()?;
+            let mut vault = self.vault()?.borrow_mut();
+            for (name, value) in values {
+                vault.set(system_path, name.clone(), value.clone());
+            }
+        }
+
+        let repo_path = destination(encryption.is_some())?;
+
+        // Create parent directories
+        if let Some(parent) = repo_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // Remove existing file/symlink if exists
+        if repo_path.exists() || repo_path.is_symlink() {
+            let _ = fs::remove_file(&repo_path);
+        }
+
+        // Handle symlinks - preserve them as symlinks
+        if meta.file_type().is_symlink() {
+            let target = fs::read_link(system_path)?;
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(&target, &repo_path)?;
+        } else if let Some(encryption) = encryption {
+            encryption.encrypt_file(system_path, &repo_path)?;
+        } else if let Some((content, _)) = templated {
+            fs::write(&repo_path, content)?;
+        } else if meta.is_file() {
+            fs::copy(system_path, &repo_path)?;
+        }
+        // Skip non-regular files (sockets, devices, etc.)
+
+        Ok(())
+    }
+
+    /// Remove a file from tracking
+    pub fn remove(&self, path: &Path, delete_from_repo: bool) -> Result<Vec<PathBuf>> {
         let categories = CategoryManager::load(self.repo)?;
+        let mut removed = Vec::new();
 
-        categories
-            .find_for_path(path)
-            .map(|c| c.name.clone())
-            .ok_or_else(|| ConfectError::PathNotTracked(path.to_path_buf()))
+        // Find the file in the repo
+        if let Some(cat) = categories.find_for_path(path) {
+            let repo_path = self.repo_path(cat, path)?;
+
+            if repo_path.exists() {
+                if delete_from_repo {
+                    fs::remove_file(&repo_path)?;
+                    self.with_index(cat, |index| Ok(index.remove(path)))?;
+                }
+                removed.push(path.to_path_buf());
+            }
+        }
+        self.save_indexes(&categories)?;
+
+        Ok(removed)
     }
-}
 
-fn is_trackable_entry(entry: &DirEntry) -> bool {
-    let file_type = entry.file_type();
-    file_type.is_file() || file_type.is_symlink()
-}
+    /// Get the status of tracked files
+    pub fn status(&self, category: Option<&str>) -> Result<HashMap<PathBuf, FileStatus>> {
+        let categories = CategoryManager::load(self.repo)?;
+        let mut result = HashMap::new();
 
-fn is_trackable_path(path: &Path) -> bool {
-    fs::symlink_metadata(path)
-        .map(|meta| meta.is_file() || meta.file_type().is_symlink())
-        .unwrap_or(false)
-}
+        let cats_to_check: Vec<_> = if let Some(name) = category {
+            vec![categories.get(name)?]
+        } else {
+            categories.list()
+        };
 
-fn remove_empty_dirs(root: &Path) -> Result<()> {
-    let mut dirs = Vec::new();
+        for cat in cats_to_check {
+            // Walk repository files
+            for (repo_file, system_path) in self.stored_files(cat)? {
+                let status = self.compare_files(&system_path, &repo_file)?;
+                if status != FileStatus::Modified
+                    || !self.files_equal(cat, &system_path, &repo_file)?
+                {
+                    result.insert(system_path, status);
+                }
+            }
 
-    for entry in WalkDir::new(root).min_depth(1).follow_links(false) {
-        let entry = entry?;
-        if entry.file_type().is_dir() {
-            dirs.push(entry.path().to_path_buf());
+            // Shared files from the base branch the host doesn't override
+            for (system_path, file) in self.base_files(cat)? {
+                if !system_path.exists() && !system_path.is_symlink() {
+                    result.insert(system_path, FileStatus::Deleted);
+                } else if !self.base_equal(cat, &system_path, &file)? {
+                    result.insert(system_path, FileStatus::Modified);
+                }
+            }
         }
+
+        Ok(result)
     }
 
-    dirs.sort_by_key(|path| std::cmp::Reverse(path.components().count()));
+    /// Compare a system file with its repository copy
+    fn compare_files(&self, system_path: &Path, repo_path: &Path) -> Result<FileStatus> {
+        let system_exists = system_path.exists();
+        let repo_exists = repo_path.exists();
 
-    for dir in dirs {
-        if fs::read_dir(&dir)?.next().is_none() {
-            fs::remove_dir(&dir)?;
+        match (system_exists, repo_exists) {
+            (true, true) => Ok(FileStatus::Modified), // Filtered later if equal
+            (true, false) => Ok(FileStatus::Added),
+            (false, true) => Ok(FileStatus::Deleted),
+            (false, false) => Ok(FileStatus::Missing),
         }
     }
 
-    Ok(())
-}
+    /// Check if a system file or symlink has the same content as its
+    /// repository copy
+    ///
+    /// Files are compared in chunks, so memory use does not grow with their size.
+    fn files_equal(&self, cat: &Category, path1: &Path, path2: &Path) -> Result<bool> {
+        let meta1 = fs::symlink_metadata(path1)?;
+        let meta2 = fs::symlink_metadata(path2)?;
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::core::{CategoryManager, Repository};
-    use tempfile::tempdir;
+        if meta1.file_type().is_symlink() || meta2.file_type().is_symlink() {
+            return Ok(meta1.file_type().is_symlink()
+                && meta2.file_type().is_symlink()
+                && fs::read_link(path1)? == fs::read_link(path2)?);
+        }
+
+        if !meta1.is_file() || !meta2.is_file() {
+            return Ok(false);
+        }
+
+        self.content_equal(cat, path1, meta2.len(), || Ok(File::open(path2)?))
+    }
+
+    /// Check if a system file or symlink matches a file on the base branch
+    fn base_equal(&self, cat: &Category, system_path: &Path, file: &BranchFile) -> Result<bool> {
+        let meta = fs::symlink_metadata(system_path)?;
+
+        match file {
+            BranchFile::Symlink(target) => {
+                Ok(meta.file_type().is_symlink() && &fs::read_link(system_path)? == target)
+            }
+            BranchFile::Blob { content, .. } => Ok(meta.is_file()
+                && self.content_equal(cat, system_path, content.len() as u64, || {
+                    Ok(content.as_slice())
+                })?),
+        }
+    }
+
+    /// Check if a regular system file has the same content as a stored copy
+    ///
+    /// `open` opens the stored copy again for each pass over it.
+    fn content_equal<R: Read>(
+        &self,
+        cat: &Category,
+        system_path: &Path,
+        stored_len: u64,
+        open: impl Fn() -> Result<R>,
+    ) -> Result<bool> {
+        if fs::metadata(system_path)?.len() == stored_len
+            && readers_equal(File::open(system_path)?, open()?)?
+        {
+            return Ok(true);
+        }
+
+        let mut header = [0u8; 64];
+        let len = read_chunk(&mut open()?, &mut header)?;
+        if AgeEncryption::is_encrypted_data(&header[..len]) {
+            return self.plaintext_equal(system_path, open()?);
+        }
+
+        // Files with placeholders are small text configs, read them whole
+        if cat.placeholder_rules(system_path).is_empty() {
+            return Ok(false);
+        }
+        let mut template = String::new();
+        if open()?.read_to_string(&mut template).is_err()
+            || !placeholders::has_placeholders(&template)
+        {
+            return Ok(false);
+        }
+
+        Ok(self.template_equal(
+            system_path,
+            &String::from_utf8_lossy(&fs::read(system_path)?),
+            &template,
+        ))
+    }
+
+    /// Check if a system file matches a repository copy with placeholders
+    ///
+    /// Placeholders are substituted from the vault. Without a usable vault
+    /// only the text around the placeholders is compared.
+    fn template_equal(&self, system_path: &Path, content: &str, template: &str) -> bool {
+        match self.vault() {
+            Ok(vault) => placeholders::render(template, &vault.borrow(), system_path)
+                .is_ok_and(|rendered| rendered == content),
+            Err(_) => placeholders::matches_template(content, template),
+        }
+    }
+
+    /// Check if a system file matches the plaintext of its encrypted copy
+    ///
+    /// The copy is decrypted as it is compared. Without a usable identity the
+    /// hash recorded in the metadata at the last sync is compared instead.
+    fn plaintext_equal(&self, system_path: &Path, ciphertext: impl Read) -> Result<bool> {
+        if let Ok(plaintext) = self.decrypt_reader(ciphertext) {
+            if let Ok(equal) = readers_equal(File::open(system_path)?, plaintext) {
+                return Ok(equal);
+            }
+        }
+
+        let Some(hash) = self
+            .stored_metadata(system_path)?
+            .and_then(|meta| meta.content_hash)
+        else {
+            return Ok(false);
+        };
+
+        Ok(hash == content_hash(system_path, File::open(system_path)?)?)
+    }
+
+    /// Check if a tracked file is, or will be, stored encrypted
+    pub fn is_stored_encrypted(&self, system_path: &Path) -> bool {
+        let Ok(categories) = CategoryManager::load(self.repo) else {
+            return false;
+        };
+
+        categories.find_for_path(system_path).is_some_and(|cat| {
+            cat.should_encrypt(system_path)
+                || self
+                    .repo_path(cat, system_path)
+                    .is_ok_and(|repo_path| AgeEncryption::is_encrypted(&repo_path))
+                || matches!(
+                    self.base_file(cat, system_path),
+                    Ok(Some(BranchFile::Blob { content, .. }))
+                        if AgeEncryption::is_encrypted_data(&content)
+                )
+        })
+    }
+
+    /// Get diff between system file and repo file
+    pub fn diff_file(&self, system_path: &Path) -> Result<String> {
+        let categories = CategoryManager::load(self.repo)?;
+
+        if let Some(cat) = categories.find_for_path(system_path) {
+            let repo_path = self.repo_path(cat, system_path)?;
+            let base = self.inherited_file(cat, system_path)?;
+            let label = match &base {
+                Some((Layer::Branch(branch), _)) => {
+                    format!("{}:{}", branch, cat.layer_path_for(system_path).display())
+                }
+                Some((Layer::Dir(dir), _)) => self
+                    .repo
+                    .path()
+                    .join(dir)
+                    .join(cat.layer_path_for(system_path))
+                    .display()
+                    .to_string(),
+                None => repo_path.display().to_string(),
+            };
+            let base = base.map(|(_, file)| file);
+
+            if !repo_path.exists() && base.is_none() {
+                return Ok(format!(
+                    "File only exists in system: {}",
+                    system_path.display()
+                ));
+            }
+            if !system_path.exists() {
+                return Ok(format!("File only exists in repo: {}", label));
+            }
+
+            let equal = match &base {
+                Some(file) => self.base_equal(cat, system_path, file)?,
+                None => self.files_equal(cat, system_path, &repo_path)?,
+            };
+            if equal {
+                return Ok(String::new());
+            }
+
+            // Compare against the plaintext of encrypted files
+            let repo_content = match base {
+                Some(BranchFile::Blob { content, .. }) => content,
+                Some(BranchFile::Symlink(target)) => {
+                    return Ok(format!(
+                        "Symlink differs from {} -> {}",
+                        label,
+                        target.display()
+                    ))
+                }
+                None => fs::read(&repo_path).unwrap_or_default(),
+            };
+            let repo_content = if AgeEncryption::is_encrypted_data(&repo_content) {
+                match self.decrypt(&repo_content) {
+                    Ok(plaintext) => plaintext,
+                    Err(_) => {
+                        return Ok(format!(
+                            "Encrypted file differs (cannot decrypt {} to show changes)",
+                            label
+                        ))
+                    }
+                }
+            } else {
+                repo_content
+            };
+
+            // Simple line-by-line diff
+            let system_content = fs::read_to_string(system_path).unwrap_or_default();
+            let repo_content = String::from_utf8(repo_content).unwrap_or_default();
+
+            // Never show secret values: diff against the templated content
+            let system_content = if placeholders::has_placeholders(&repo_content) {
+                let rules = cat.placeholder_rules(system_path);
+                let (templated, _) = placeholders::extract(&system_content, &rules)?;
+                if templated == repo_content {
+                    return Ok(format!(
+                        "Secret values differ: {}",
+                        placeholders::names(&repo_content).join(", ")
+                    ));
+                }
+                templated
+            } else {
+                system_content
+            };
+
+            Ok(line_diff(
+                &format!("a/{}", system_path.display()),
+                &format!("b/{}", label),
+                &repo_content,
+                &system_content,
+            ))
+        } else {
+            Err(ConfectError::PathNotTracked(system_path.to_path_buf()))
+        }
+    }
+
+    /// Copy this host's stored files into `main` so every host shares them
+    ///
+    /// `path` may be a file or a directory of tracked files. Metadata entries
+    /// move along. The host copies are removed afterwards so the host follows
+    /// `main` too; their removal and the host's metadata file are left for the
+    /// caller to commit.
+    /// With the directory strategy the files are moved into `common` instead,
+    /// and that directory is left for the caller to commit as well.
+    ///
+    /// Returns each shared system path with the repository path, relative to
+    /// the repository root, its host copy was removed from.
+    pub fn share(&self, path: &Path) -> Result<Vec<(PathBuf, PathBuf)>> {
+        let Some(mut base_metadata) = MetadataStore::load_base(self.repo)? else {
+            return Err(ConfectError::Other(format!(
+                "Branch '{}' does not exist or is checked out",
+                BASE_BRANCH
+            )));
+        };
+
+        let mut host_metadata = MetadataStore::load(self.repo)?;
+        let categories = CategoryManager::load(self.repo)?;
+        let mut files = Vec::new();
+        let mut shared = Vec::new();
+
+        for cat in categories.list() {
+            let hidden_dir = self.repo.path().join(cat.dir()).join(HIDDEN_DIR);
+
+            for (repo_file, system_path) in self.stored_files(cat)? {
+                if !system_path.starts_with(path) {
+                    continue;
+                }
+                if repo_file.starts_with(&hidden_dir) {
+                    return Err(ConfectError::Other(format!(
+                        "{} is stored under a hidden path and cannot be shared",
+                        system_path.display()
+                    )));
+                }
+                let Some(file) = system_file(&repo_file)? else {
+                    continue;
+                };
+
+                // The entry moves too, or it would override the shared one
+                if let Some(meta) = host_metadata.get(&system_path).cloned() {
+                    base_metadata.set(&system_path, meta);
+                    host_metadata.remove(&system_path);
+                }
+                files.push((cat.layer_path_for(&system_path), file));
+                shared.push((system_path.clone(), cat.repo_path_for(&system_path)));
+            }
+        }
+
+        if shared.is_empty() {
+            return Err(ConfectError::PathNotTracked(path.to_path_buf()));
+        }
+
+        if self.repo.strategy() == HostStrategy::Directory {
+            let common_dir = self.repo.path().join(COMMON_DIR);
+            for ((layer_path, _), (_, relative)) in files.iter().zip(&shared) {
+                let common_path = common_dir.join(layer_path);
+                if let Some(parent) = common_path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::rename(self.repo.path().join(relative), common_path)?;
+            }
+            base_metadata.save()?;
+            host_metadata.save()?;
+            self.remove_empty_category_dirs(&categories)?;
+            return Ok(shared);
+        }
+
+        let host = self.repo.current_host()?;
+        let message = match shared.as_slice() {
+            [(system_path, _)] => format!(
+                "Share {} from {}",
+                system_path
+                    .file_name()
+                    .unwrap_or(system_path.as_os_str())
+                    .to_string_lossy(),
+                host
+            ),
+            _ => format!("Share {} files from {}", shared.len(), host),
+        };
+        files.push((
+            PathBuf::from(".confect/metadata.toml"),
+            BranchFile::Blob {
+                content: base_metadata.to_toml()?.into_bytes(),
+                executable: false,
+            },
+        ));
+        self.repo.commit_to_branch(BASE_BRANCH, &files, &message)?;
+
+        host_metadata.save()?;
+        for (_, relative) in &shared {
+            remove_stored(&self.repo.path().join(relative))?;
+        }
+        self.remove_empty_category_dirs(&categories)?;
+
+        Ok(shared)
+    }
+
+    /// Prune the directories emptied in this host's categories
+    fn remove_empty_category_dirs(&self, categories: &CategoryManager) -> Result<()> {
+        for cat in categories.list() {
+            let category_dir = self.repo.path().join(cat.dir());
+            if category_dir.exists() {
+                remove_empty_dirs(&category_dir)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Diff the files on this system against another host's branch
+    ///
+    /// Covers the files tracked here and those the host tracks, overlaid on
+    /// `main`. Only files that differ are returned, keyed by system path.
+    pub fn diff_host(
+        &self,
+        host: &str,
+        category: Option<&str>,
+        filter: Option<&Path>,
+    ) -> Result<Vec<(PathBuf, String)>> {
+        let (host_categories, host_files) = self.host_files(host, category)?;
+        let categories = CategoryManager::load(self.repo)?;
+
+        let mut paths: BTreeSet<PathBuf> = host_files.keys().cloned().collect();
+        match category {
+            Some(name) if categories.exists(name) => {
+                paths.extend(self.list_files_in_category(name)?)
+            }
+            Some(_) => {}
+            None => paths.extend(self.list_all_tracked_files()?),
+        }
+
+        let mut diffs = Vec::new();
+        for path in paths {
+            if filter.is_some_and(|filter| !path.starts_with(filter)) {
+                continue;
+            }
+
+            let cat = categories
+                .find_for_path(&path)
+                .or_else(|| host_categories.find_for_path(&path));
+            let local = system_file(&path)?;
+            let diff = self.diff_branch_files(
+                cat,
+                &path,
+                (host, host_files.get(&path)),
+                ("local", local.as_ref()),
+            )?;
+            if !diff.is_empty() {
+                diffs.push((path, diff));
+            }
+        }
+
+        Ok(diffs)
+    }
+
+    /// Diff the files two hosts track, reading both from their branches
+    pub fn diff_between(
+        &self,
+        from: &str,
+        to: &str,
+        category: Option<&str>,
+        filter: Option<&Path>,
+    ) -> Result<Vec<(PathBuf, String)>> {
+        let (from_categories, from_files) = self.host_files(from, category)?;
+        let (to_categories, to_files) = self.host_files(to, category)?;
+
+        let paths: BTreeSet<&PathBuf> = from_files.keys().chain(to_files.keys()).collect();
+
+        let mut diffs = Vec::new();
+        for path in paths {
+            if filter.is_some_and(|filter| !path.starts_with(filter)) {
+                continue;
+            }
+
+            let cat = from_categories
+                .find_for_path(path)
+                .or_else(|| to_categories.find_for_path(path));
+            let diff = self.diff_branch_files(
+                cat,
+                path,
+                (from, from_files.get(path)),
+                (to, to_files.get(path)),
+            )?;
+            if !diff.is_empty() {
+                diffs.push((path.clone(), diff));
+            }
+        }
+
+        Ok(diffs)
+    }
+
+    /// Read the categories and tracked files of a host from its branch, or
+    /// its directory with the directory strategy
+    ///
+    /// Files from the host's groups and `main` (or `common`) are included
+    /// where the host's categories cover them and it has no copy of its own.
+    /// Files stored under hidden paths are left out.
+    fn host_files(
+        &self,
+        host: &str,
+        category: Option<&str>,
+    ) -> Result<(CategoryManager, BTreeMap<PathBuf, BranchFile>)> {
+        let (categories, layers) = match self.repo.strategy() {
+            HostStrategy::Branch => {
+                let branch = self.repo.host(host)?.branch;
+                let categories = CategoryManager::load_branch(self.repo, &branch)?;
+                let base = if branch != BASE_BRANCH && self.repo.has_branch(BASE_BRANCH)? {
+                    Some(Layer::Branch(BASE_BRANCH.to_string()))
+                } else {
+                    None
+                };
+                let mut layers = vec![Layer::Branch(branch)];
+                layers.extend(self.inherited_layers(host, base)?);
+                (categories, layers)
+            }
+            HostStrategy::Directory => {
+                self.repo.host(host)?;
+                let categories = CategoryManager::load_for_host(self.repo, host)?;
+                let mut layers = vec![Layer::Dir(self.repo.host_dir(host))];
+                layers.extend(
+                    self.inherited_layers(host, Some(Layer::Dir(PathBuf::from(COMMON_DIR))))?,
+                );
+                (categories, layers)
+            }
+        };
+
+        let mut files = BTreeMap::new();
+        for cat in categories.list() {
+            if category.is_some_and(|name| name != cat.name) {
+                continue;
+            }
+
+            let hidden_dir = Path::new(&cat.name).join(HIDDEN_DIR);
+            for (index, layer) in layers.iter().enumerate() {
+                for repo_file in layer.list(self.repo, Path::new(&cat.name))? {
+                    if repo_file.starts_with(&hidden_dir) {
+                        continue;
+                    }
+                    let Some(system_path) = cat.system_path_for(&repo_file) else {
+                        continue;
+                    };
+                    if files.contains_key(&system_path) || (index > 0 && !cat.matches(&system_path))
+                    {
+                        continue;
+                    }
+                    if let Some(file) = layer.read(self.repo, &repo_file)? {
+                        files.insert(system_path, file);
+                    }
+                }
+            }
+        }
+
+        Ok((categories, files))
+    }
+
+    /// Diff two copies of a file, each named after the host it comes from
+    fn diff_branch_files(
+        &self,
+        cat: Option<&Category>,
+        system_path: &Path,
+        (from, old): (&str, Option<&BranchFile>),
+        (to, new): (&str, Option<&BranchFile>),
+    ) -> Result<String> {
+        let (old, new) = match (old, new) {
+            (Some(old), Some(new)) if old == new => return Ok(String::new()),
+            (Some(old), Some(new)) => (old, new),
+            (Some(_), None) => return Ok(format!("Only in {}", from)),
+            (None, Some(_)) => return Ok(format!("Only in {}", to)),
+            (None, None) => return Ok(String::new()),
+        };
+
+        let (old, new, mode_differs) = match (old, new) {
+            (
+                BranchFile::Blob {
+                    content: old,
+                    executable: old_executable,
+                },
+                BranchFile::Blob {
+                    content: new,
+                    executable: new_executable,
+                },
+            ) => (old, new, old_executable != new_executable),
+            (BranchFile::Symlink(old), BranchFile::Symlink(new)) => {
+                return Ok(format!(
+                    "Symlink differs: {} -> {}, {} -> {}",
+                    from,
+                    old.display(),
+                    to,
+                    new.display()
+                ))
+            }
+            (BranchFile::Symlink(_), _) => {
+                return Ok(format!("Symlink in {}, regular file in {}", from, to))
+            }
+            (_, BranchFile::Symlink(_)) => {
+                return Ok(format!("Regular file in {}, symlink in {}", from, to))
+            }
+        };
+
+        // Encrypted copies of the same content still differ as ciphertext
+        let Some(old) = self.plaintext(old) else {
+            return Ok(format!(
+                "Encrypted file differs (cannot decrypt {} copy to show changes)",
+                from
+            ));
+        };
+        let Some(new) = self.plaintext(new) else {
+            return Ok(format!(
+                "Encrypted file differs (cannot decrypt {} copy to show changes)",
+                to
+            ));
+        };
+        if old == new {
+            return Ok(if mode_differs {
+                format!("Executable bit differs between {} and {}", from, to)
+            } else {
+                String::new()
+            });
+        }
+
+        // Never show secret values: template the side that still has them
+        let old = String::from_utf8_lossy(&old).into_owned();
+        let new = String::from_utf8_lossy(&new).into_owned();
+        let rules = cat
+            .map(|cat| cat.placeholder_rules(system_path))
+            .unwrap_or_default();
+        let (old, new) = match (
+            placeholders::has_placeholders(&old),
+            placeholders::has_placeholders(&new),
+        ) {
+            (true, false) => {
+                let new = placeholders::extract(&new, &rules)?.0;
+                (old, new)
+            }
+            (false, true) => (placeholders::extract(&old, &rules)?.0, new),
+            _ => (old, new),
+        };
+        if old == new {
+            return Ok(format!(
+                "Secret values differ: {}",
+                placeholders::names(&old).join(", ")
+            ));
+        }
+
+        Ok(line_diff(
+            &format!("{}:{}", from, system_path.display()),
+            &format!("{}:{}", to, system_path.display()),
+            &old,
+            &new,
+        ))
+    }
+
+    /// Decrypt stored content if it is encrypted, `None` if that fails
+    fn plaintext(&self, content: &[u8]) -> Option<Vec<u8>> {
+        if AgeEncryption::is_encrypted_data(content) {
+            self.decrypt(content).ok()
+        } else {
+            Some(content.to_vec())
+        }
+    }
+
+    /// Refresh all tracked files from system to repository
+    pub fn refresh_all(&self) -> Result<RefreshResult> {
+        let categories = CategoryManager::load(self.repo)?;
+        let mut result = RefreshResult::default();
+
+        for cat in categories.list() {
+            let category_dir = self.repo.path().join(cat.dir());
+            let mut seen_system_paths = HashSet::new();
+
+            // Expand paths in category. Exact directory paths are tracked recursively.
+            for pattern in &cat.paths {
+                self.refresh_pattern(
+                    cat,
+                    pattern,
+                    &category_dir,
+                    &mut seen_system_paths,
+                    &mut result,
+                )?;
+            }
+
+            if !category_dir.exists() {
+                continue;
+            }
+
+            for (repo_file, system_path) in self.stored_files(cat)? {
+                if !seen_system_paths.contains(&system_path) && cat.matches(&system_path) {
+                    fs::remove_file(&repo_file)?;
+                    self.with_index(cat, |index| Ok(index.remove(&system_path)))?;
+                    result.deleted.push(system_path);
+                }
+            }
+
+            remove_empty_dirs(&category_dir)?;
+        }
+
+        self.save_vault()?;
+        self.save_indexes(&categories)?;
+
+        Ok(result)
+    }
+
+    fn refresh_pattern(
+        &self,
+        cat: &Category,
+        pattern: &str,
+        category_dir: &Path,
+        seen_system_paths: &mut HashSet<PathBuf>,
+        result: &mut RefreshResult,
+    ) -> Result<()> {
+        for path in glob::glob(pattern)? {
+            let path = path?;
+            if !cat.matches(&path) {
+                continue;
+            }
+
+            if path.is_dir() {
+                let mut entries = WalkDir::new(&path).follow_links(false).into_iter();
+                while let Some(entry) = entries.next() {
+                    let entry = entry?;
+                    let entry_path = entry.path();
+
+                    if entry.file_type().is_dir() && !cat.matches(entry_path) {
+                        entries.skip_current_dir();
+                        continue;
+                    }
+
+                    if is_trackable_entry(&entry) {
+                        self.refresh_file(
+                            cat,
+                            entry_path,
+                            category_dir,
+                            seen_system_paths,
+                            result,
+                        )?;
+                    }
+                }
+            } else if is_trackable_path(&path) {
+                self.refresh_file(cat, &path, category_dir, seen_system_paths, result)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn refresh_file(
+        &self,
+        cat: &Category,
+        path: &Path,
+        category_dir: &Path,
+        seen_system_paths: &mut HashSet<PathBuf>,
+        result: &mut RefreshResult,
+    ) -> Result<()> {
+        seen_system_paths.insert(path.to_path_buf());
+
+        let repo_path = self.repo_path(cat, path)?;
+        let encrypt = cat.should_encrypt(path);
+
+        // Files also move when they are to be stored under an opaque ID or
+        // no longer are
+        let hidden = self.with_index(cat, |index| Ok(index.id(path).is_some()))?;
+        let hide = cat.hide_paths && encrypt && is_regular_file(path);
+
+        // Files that become sensitive are re-encrypted even when unchanged
+        let stored_encrypted =
+            is_regular_file(&repo_path) && AgeEncryption::is_encrypted(&repo_path);
+        let encrypted_as_needed = !is_regular_file(path) || stored_encrypted == encrypt;
+
+        if repo_path.exists()
+            && hidden == hide
+            && encrypted_as_needed
+            && self.files_equal(cat, path, &repo_path)?
+        {
+            return Ok(());
+        }
+
+        // Shared files only get a host copy once the host changes them
+        if let Some(file) = self.base_file(cat, path)? {
+            if self.base_equal(cat, path, &file)? {
+                return Ok(());
+            }
+        }
+
+        // Never let unattended syncs store a possible secret in plaintext
+        if !encrypt && !cat.allows_secrets(path) && is_regular_file(path) {
+            let findings = self.scan_stored(Some(cat), path)?;
+            if !findings.is_empty() {
+                result.blocked.push((path.to_path_buf(), findings));
+                return Ok(());
+            }
+        }
+
+        self.copy_to_repo(path, category_dir, Some(cat), encrypt)?;
+        result.updated.push(path.to_path_buf());
+
+        Ok(())
+    }
+
+    /// Restore a file from repository to system
+    pub fn restore_file(&self, system_path: &Path) -> Result<()> {
+        let categories = CategoryManager::load(self.repo)?;
+
+        if let Some(cat) = categories.find_for_path(system_path) {
+            let repo_path = self.repo_path(cat, system_path)?;
+
+            // Fall back to the shared copy on the base branch
+            if !repo_path.exists() && !repo_path.is_symlink() {
+                return match self.base_file(cat, system_path)? {
+                    Some(file) => self.restore_base_file(cat, system_path, file),
+                    None => Err(ConfectError::FileNotFound(repo_path)),
+                };
+            }
+
+            let meta = fs::symlink_metadata(&repo_path)?;
+
+            // Create parent directories
+            if let Some(parent) = system_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            // Decrypted and rendered files are written next to the system file
+            // and moved over it, so a missing or wrong key never removes it
+            if meta.is_file() && AgeEncryption::is_encrypted(&repo_path) {
+                write_private(system_path, self.decrypt_file(&repo_path)?)?;
+                return Ok(());
+            }
+            if meta.is_file() && !cat.placeholder_rules(system_path).is_empty() {
+                if let Ok(template) = String::from_utf8(fs::read(&repo_path)?) {
+                    if placeholders::has_placeholders(&template) {
+                        let rendered =
+                            placeholders::render(&template, &self.vault()?.borrow(), system_path)?;
+                        write_private(system_path, rendered.as_bytes())?;
+                        return Ok(());
+                    }
+                }
+            }
+
+            // Remove existing file/symlink
+            if system_path.exists() || system_path.is_symlink() {
+                let _ = fs::remove_file(system_path);
+            }
+
+            // Restore - handle symlinks
+            if meta.file_type().is_symlink() {
+                let target = fs::read_link(&repo_path)?;
+                #[cfg(unix)]
+                std::os::unix::fs::symlink(&target, system_path)?;
+            } else {
+                fs::copy(&repo_path, system_path)?;
+            }
+
+            Ok(())
+        } else {
+            Err(ConfectError::PathNotTracked(system_path.to_path_buf()))
+        }
+    }
+
+    /// Restore a file from the base branch without checking it out
+    fn restore_base_file(
+        &self,
+        cat: &Category,
+        system_path: &Path,
+        file: BranchFile,
+    ) -> Result<()> {
+        self.write_stored(cat, system_path, file, |template| {
+            placeholders::render(template, &self.vault()?.borrow(), system_path)
+        })
+    }
+
+    /// List the tracked files a commit stored, optionally in one category
+    ///
+    /// Files are found through the categories configured now, and with the
+    /// directory strategy also in the shared directories of that commit.
+    pub fn list_files_at(&self, at: Oid, category: Option<&str>) -> Result<Vec<PathBuf>> {
+        let categories = CategoryManager::load(self.repo)?;
+        let cats = match category {
+            Some(name) => vec![categories.get(name)?],
+            None => categories.list(),
+        };
+
+        let mut files = BTreeSet::new();
+        for cat in cats {
+            let hidden_dir = cat.dir().join(HIDDEN_DIR);
+            let repo_files = self.repo.list_commit_files(at, &cat.dir())?;
+            let index = if repo_files.iter().any(|f| f.starts_with(&hidden_dir)) {
+                self.index_at(at, cat)?
+            } else {
+                PathIndex::default()
+            };
+            for repo_file in repo_files {
+                let system_path = match repo_file.strip_prefix(&hidden_dir) {
+                    Ok(id) => index
+                        .system_path(&id.to_string_lossy())
+                        .map(Path::to_path_buf),
+                    Err(_) => cat.system_path_for(&repo_file),
+                };
+                files.extend(system_path);
+            }
+
+            for layer in self.base_layers()? {
+                let Layer::Dir(dir) = layer else {
+                    continue;
+                };
+                for repo_file in self.repo.list_commit_files(at, &dir.join(&cat.name))? {
+                    let Ok(layer_path) = repo_file.strip_prefix(dir) else {
+                        continue;
+                    };
+                    if let Some(system_path) = cat.system_path_for(layer_path) {
+                        if cat.matches(&system_path) {
+                            files.insert(system_path);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(files.into_iter().collect())
+    }
+
+    /// Restore a file as a commit stored it, without checking the commit out
+    ///
+    /// Returns the metadata stored with the file at the time, for the caller
+    /// to apply. Placeholders are filled from that commit's vault.
+    pub fn restore_file_at(&self, system_path: &Path, at: Oid) -> Result<Option<FileMetadata>> {
+        let categories = CategoryManager::load(self.repo)?;
+        let cat = categories
+            .find_for_path(system_path)
+            .ok_or_else(|| ConfectError::PathNotTracked(system_path.to_path_buf()))?;
+
+        let Some((repo_path, metadata_dir, file)) = self.stored_file_at(cat, system_path, at)?
+        else {
+            return Err(ConfectError::FileNotFound(
+                self.repo.path().join(cat.repo_path_for(system_path)),
+            ));
+        };
+        self.write_stored(cat, system_path, file, |template| {
+            placeholders::render(template, &self.vault_at(at)?, system_path)
+        })?;
+
+        // Hidden files have their metadata in the index
+        if repo_path.starts_with(cat.dir().join(HIDDEN_DIR)) {
+            return Ok(self.index_at(at, cat)?.metadata(system_path).cloned());
+        }
+
+        let metadata_file = metadata_dir.join("metadata.toml");
+        let metadata = match self.repo.read_commit_file(at, &metadata_file)? {
+            Some(BranchFile::Blob { content, .. }) => MetadataStore::from_toml(
+                &self.repo.path().join(&metadata_dir),
+                &String::from_utf8_lossy(&content),
+            )?
+            .get(system_path)
+            .cloned(),
+            _ => None,
+        };
+        Ok(metadata)
+    }
+
+    /// Get diff between a system file and the copy a commit stored
+    pub fn diff_file_at(&self, system_path: &Path, at: Oid) -> Result<String> {
+        let categories = CategoryManager::load(self.repo)?;
+        let cat = categories
+            .find_for_path(system_path)
+            .ok_or_else(|| ConfectError::PathNotTracked(system_path.to_path_buf()))?;
+        let exists = system_path.exists() || system_path.is_symlink();
+
+        let Some((repo_path, _, file)) = self.stored_file_at(cat, system_path, at)? else {
+            return Ok(if exists {
+                format!("File only exists in system: {}", system_path.display())
+            } else {
+                String::new()
+            });
+        };
+        let label = format!("{}:{}", &at.to_string()[..7], repo_path.display());
+        if !exists {
+            return Ok(format!("File only exists in {}", label));
+        }
+
+        let content = match file {
+            BranchFile::Symlink(target) => {
+                return Ok(match fs::read_link(system_path) {
+                    Ok(current) if current == target => String::new(),
+                    _ => format!("Symlink differs from {} -> {}", label, target.display()),
+                })
+            }
+            BranchFile::Blob { content, .. } => content,
+        };
+        let content = if AgeEncryption::is_encrypted_data(&content) {
+            match self.decrypt(&content) {
+                Ok(plaintext) => plaintext,
+                Err(_) => {
+                    return Ok(format!(
+                        "Encrypted file differs (cannot decrypt {} to show changes)",
+                        label
+                    ))
+                }
+            }
+        } else {
+            content
+        };
+        if fs::read(system_path).ok().as_ref() == Some(&content) {
+            return Ok(String::new());
+        }
+
+        let system_content = fs::read_to_string(system_path).unwrap_or_default();
+        let stored_content = String::from_utf8(content).unwrap_or_default();
+
+        // Never show secret values: diff against the templated content
+        let rules = cat.placeholder_rules(system_path);
+        let system_content = if !rules.is_empty() && placeholders::has_placeholders(&stored_content)
+        {
+            let (templated, _) = placeholders::extract(&system_content, &rules)?;
+            if templated == stored_content {
+                let rendered =
+                    placeholders::render(&stored_content, &self.vault_at(at)?, system_path)?;
+                if rendered == system_content {
+                    return Ok(String::new());
+                }
+                return Ok(format!(
+                    "Secret values differ: {}",
+                    placeholders::names(&stored_content).join(", ")
+                ));
+            }
+            templated
+        } else {
+            system_content
+        };
+
+        Ok(line_diff(
+            &format!("a/{}", system_path.display()),
+            &format!("b/{}", label),
+            &stored_content,
+            &system_content,
+        ))
+    }
+
+    /// Read the copy of a file a commit stored, with the repository paths of
+    /// that copy and of the state directory holding its metadata
+    ///
+    /// The host's own copy is tried first, then the shared directories of the
+    /// same commit.
+    fn stored_file_at(
+        &self,
+        cat: &Category,
+        system_path: &Path,
+        at: Oid,
+    ) -> Result<Option<(PathBuf, PathBuf, BranchFile)>> {
+        let state_dir = self
+            .repo
+            .host_dir(self.repo.current_host()?)
+            .join(".confect");
+
+        let host_path = match self.index_at(at, cat)?.id(system_path) {
+            Some(id) => PathIndex::repo_path_for(&cat.dir(), id),
+            None => cat.repo_path_for(system_path),
+        };
+        let mut copies = vec![(host_path, state_dir)];
+        if cat.matches(system_path) {
+            for layer in self.base_layers()? {
+                if let Layer::Dir(dir) = layer {
+                    copies.push((
+                        dir.join(cat.layer_path_for(system_path)),
+                        dir.join(".confect"),
+                    ));
+                }
+            }
+        }
+
+        for (repo_path, metadata_dir) in copies {
+            if let Some(file) = self.repo.read_commit_file(at, &repo_path)? {
+                return Ok(Some((repo_path, metadata_dir, file)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Read the secret vault as a commit stored it
+    fn vault_at(&self, at: Oid) -> Result<SecretVault> {
+        let state_dir = self
+            .repo
+            .host_dir(self.repo.current_host()?)
+            .join(".confect");
+        match self
+            .repo
+            .read_commit_file(at, &SecretVault::path(&state_dir))?
+        {
+            Some(BranchFile::Blob { content, .. }) => {
+                SecretVault::from_toml(&String::from_utf8_lossy(&self.decrypt(&content)?))
+            }
+            _ => Ok(SecretVault::default()),
+        }
+    }
+
+    /// Read a category's path index as a commit stored it
+    fn index_at(&self, at: Oid, cat: &Category) -> Result<PathIndex> {
+        let state_dir = self
+            .repo
+            .host_dir(self.repo.current_host()?)
+            .join(".confect");
+        match self
+            .repo
+            .read_commit_file(at, &PathIndex::path(&state_dir, &cat.name))?
+        {
+            Some(BranchFile::Blob { content, .. }) => {
+                let plaintext = self.decrypt(&content)?;
+                PathIndex::from_toml(&String::from_utf8_lossy(&plaintext))
+            }
+            _ => Ok(PathIndex::default()),
+        }
+    }
+
+    /// Write a stored copy of a file to the system, decrypting it or
+    /// filling in its placeholders with `render`
+    fn write_stored(
+        &self,
+        cat: &Category,
+        system_path: &Path,
+        file: BranchFile,
+        render: impl FnOnce(&str) -> Result<String>,
+    ) -> Result<()> {
+        if let Some(parent) = system_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let (content, executable) = match file {
+            BranchFile::Symlink(target) => {
+                if system_path.exists() || system_path.is_symlink() {
+                    let _ = fs::remove_file(system_path);
+                }
+                std::os::unix::fs::symlink(&target, system_path)?;
+                return Ok(());
+            }
+            BranchFile::Blob {
+                content,
+                executable,
+            } => (content, executable),
+        };
+
+        if AgeEncryption::is_encrypted_data(&content) {
+            return write_private(system_path, self.decrypt_reader(content.as_slice())?);
+        }
+
+        match std::str::from_utf8(&content) {
+            Ok(template)
+                if !cat.placeholder_rules(system_path).is_empty()
+                    && placeholders::has_placeholders(template) =>
+            {
+                write_private(system_path, render(template)?.as_bytes())
+            }
+            _ => {
+                write_private(system_path, content.as_slice())?;
+                let mode = if executable { 0o755 } else { 0o644 };
+                fs::set_permissions(system_path, fs::Permissions::from_mode(mode))?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Re-encrypt stored files to the current recipients
+    ///
+    /// Files that cannot be decrypted with the local identity or passphrase
+    /// are reported in the result and left untouched. Rekeying all categories
+    /// also re-encrypts the secret vault.
+    pub fn rekey(&self, category: Option<&str>) -> Result<RekeyResult> {
+        let categories = CategoryManager::load(self.repo)?;
+        let mut result = RekeyResult::default();
+
+        let cats_to_rekey: Vec<_> = if let Some(name) = category {
+            vec![categories.get(name)?]
+        } else {
+            categories.list()
+        };
+
+        for cat in cats_to_rekey {
+            // Hidden files are only found through the index, so a category
+            // whose index cannot be decrypted is skipped as a whole
+            let index_path = PathIndex::path(&self.repo.state_dir(), &cat.name);
+            let index = if index_path.exists() {
+                match self.decrypt(&fs::read(&index_path)?) {
+                    Ok(plaintext) => Some(plaintext),
+                    Err(e) => {
+                        result.failed.push((index_path, e));
+                        continue;
+                    }
+                }
+            } else {
+                None
+            };
+
+            let encrypted_files: Vec<_> = self
+                .stored_files(cat)?
+                .into_iter()
+                .filter(|(repo_file, _)| {
+                    is_regular_file(repo_file) && AgeEncryption::is_encrypted(repo_file)
+                })
+                .collect();
+
+            if encrypted_files.is_empty() && index.is_none() {
+                continue;
+            }
+
+            let encryption = self.encryptor(Some(cat))?;
+
+            for (repo_file, system_path) in encrypted_files {
+                match self.decrypt_file(&repo_file) {
+                    Ok(plaintext) => {
+                        rewrite(&repo_file, |output| {
+                            encryption.encrypt_stream(plaintext, output)
+                        })?;
+                        result.rekeyed.push(system_path);
+                    }
+                    Err(e) => result.failed.push((system_path, e)),
+                }
+            }
+
+            // The index is encrypted like the files it lists
+            if let Some(plaintext) = index {
+                rewrite(&index_path, |output| {
+                    encryption.encrypt_stream(plaintext.as_slice(), output)
+                })?;
+                result.rekeyed.push(index_path);
+            }
+        }
+
+        // The secret vault is shared by all categories
+        let vault_path = SecretVault::path(&self.repo.state_dir());
+        if category.is_none() && vault_path.exists() {
+            match self.decrypt_file(&vault_path) {
+                Ok(plaintext) => {
+                    let encryption = self.vault_encryptor()?;
+                    rewrite(&vault_path, |output| {
+                        encryption.encrypt_stream(plaintext, output)
+                    })?;
+                    result.rekeyed.push(vault_path);
+                }
+                Err(e) => result.failed.push((vault_path, e)),
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// List all files in a category
+    pub fn list_files_in_category(&self, category_name: &str) -> Result<Vec<PathBuf>> {
+        let categories = CategoryManager::load(self.repo)?;
+        let cat = categories.get(category_name)?;
+
+        let mut files: Vec<_> = self
+            .stored_files(cat)?
+            .into_iter()
+            .map(|(_, system_path)| system_path)
+            .collect();
+        files.extend(
+            self.base_files(cat)?
+                .into_iter()
+                .map(|(system_path, _)| system_path),
+        );
+
+        Ok(files)
+    }
+
+    /// List all tracked files
+    pub fn list_all_tracked_files(&self) -> Result<Vec<PathBuf>> {
+        let categories = CategoryManager::load(self.repo)?;
+        let mut files = Vec::new();
+
+        for cat in categories.list() {
+            files.extend(self.list_files_in_category(&cat.name)?);
+        }
+
+        Ok(files)
+    }
+
+    /// Count files in a category
+    pub fn count_files_in_category(&self, category_name: &str) -> Result<usize> {
+        Ok(self.list_files_in_category(category_name)?.len())
+    }
+
+    /// Count all tracked files
+    pub fn count_all_files(&self) -> Result<usize> {
+        Ok(self.list_all_tracked_files()?.len())
+    }
+
+    /// Get category name for a path
+    pub fn get_category(&self, path: &Path) -> Result<String> {
+        let categories = CategoryManager::load(self.repo)?;
+
+        categories
+            .find_for_path(path)
+            .map(|c| c.name.clone())
+            .ok_or_else(|| ConfectError::PathNotTracked(path.to_path_buf()))
+    }
+}
+
+fn is_trackable_entry(entry: &DirEntry) -> bool {
+    let file_type = entry.file_type();
+    file_type.is_file() || file_type.is_symlink()
+}
+
+fn is_regular_file(path: &Path) -> bool {
+    fs::symlink_metadata(path)
+        .map(|meta| meta.is_file())
+        .unwrap_or(false)
+}
+
+fn is_trackable_path(path: &Path) -> bool {
+    fs::symlink_metadata(path)
+        .map(|meta| meta.is_file() || meta.file_type().is_symlink())
+        .unwrap_or(false)
+}
+
+/// Line-by-line diff of two versions of a file
+fn line_diff(from_label: &str, to_label: &str, old: &str, new: &str) -> String {
+    let mut diff = String::new();
+    diff.push_str(&format!("--- {}\n", from_label));
+    diff.push_str(&format!("+++ {}\n", to_label));
+
+    // Very basic diff - show changed lines
+    let new_lines: Vec<_> = new.lines().collect();
+    let old_lines: Vec<_> = old.lines().collect();
+
+    for (i, (n, o)) in new_lines.iter().zip(old_lines.iter()).enumerate() {
+        if n != o {
+            diff.push_str(&format!("@@ -{},{} +{},{} @@\n", i + 1, 1, i + 1, 1));
+            diff.push_str(&format!("-{}\n", o));
+            diff.push_str(&format!("+{}\n", n));
+        }
+    }
+
+    // Handle length differences
+    if new_lines.len() > old_lines.len() {
+        for line in &new_lines[old_lines.len()..] {
+            diff.push_str(&format!("+{}\n", line));
+        }
+    } else if old_lines.len() > new_lines.len() {
+        for line in &old_lines[new_lines.len()..] {
+            diff.push_str(&format!("-{}\n", line));
+        }
+    }
+
+    diff
+}
+
+/// Read a system file the way branches store it, `None` if it is missing
+fn system_file(path: &Path) -> Result<Option<BranchFile>> {
+    let meta = match fs::symlink_metadata(path) {
+        Ok(meta) => meta,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    let file = if meta.file_type().is_symlink() {
+        Some(BranchFile::Symlink(fs::read_link(path)?))
+    } else if meta.is_file() {
+        Some(BranchFile::Blob {
+            content: fs::read(path)?,
+            executable: meta.permissions().mode() & 0o100 != 0,
+        })
+    } else {
+        None
+    };
+
+    Ok(file)
+}
+
+/// Compare two streams chunk by chunk
+fn readers_equal(mut a: impl Read, mut b: impl Read) -> io::Result<bool> {
+    let mut buf_a = vec![0u8; CHUNK_SIZE];
+    let mut buf_b = vec![0u8; CHUNK_SIZE];
+
+    loop {
+        let len_a = read_chunk(&mut a, &mut buf_a)?;
+        let len_b = read_chunk(&mut b, &mut buf_b)?;
+        if buf_a[..len_a] != buf_b[..len_b] {
+            return Ok(false);
+        }
+        if len_a == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// Fill a buffer as far as the stream allows, returning the bytes read
+fn read_chunk(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Replace a file through a temporary sibling with owner-only permissions
+///
+/// The original is only replaced once `write` succeeds, so a failed
+/// decryption or encryption halfway through a file leaves it untouched.
+fn rewrite(path: &Path, write: impl FnOnce(&mut File) -> Result<()>) -> Result<()> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| ConfectError::InvalidPath(path.to_path_buf()))?;
+    let temp = path.with_file_name(format!(".{}.confect-tmp", name.to_string_lossy()));
+
+    let written = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&temp)
+        .map_err(ConfectError::from)
+        .and_then(|mut file| write(&mut file));
+
+    match written.and_then(|()| Ok(fs::rename(&temp, path)?)) {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            let _ = fs::remove_file(&temp);
+            Err(e)
+        }
+    }
+}
+
+/// Remove a stored file or symlink if it exists
+fn remove_stored(path: &Path) -> Result<()> {
+    if path.exists() || path.is_symlink() {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Write decrypted content with owner-only permissions until metadata is applied
+fn write_private(path: &Path, mut content: impl Read) -> Result<()> {
+    rewrite(path, |file| {
+        io::copy(&mut content, file)?;
+        Ok(())
+    })
+}
+
+fn remove_empty_dirs(root: &Path) -> Result<()> {
+    let mut dirs = Vec::new();
+
+    for entry in WalkDir::new(root).min_depth(1).follow_links(false) {
+        let entry = entry?;
+        if entry.file_type().is_dir() {
+            dirs.push(entry.path().to_path_buf());
+        }
+    }
+
+    dirs.sort_by_key(|path| std::cmp::Reverse(path.components().count()));
+
+    for dir in dirs {
+        if fs::read_dir(&dir)?.next().is_none() {
+            fs::remove_dir(&dir)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{CategoryManager, HostEntry, PlaceholderRule, RepoConfig, Repository};
+    use tempfile::tempdir;
+
+    #[test]
+    fn encrypted_files_are_stored_as_ciphertext_and_restored_as_plaintext() -> Result<()> {
+        let temp = tempdir()?;
+        let repo_dir = temp.path().join("repo");
+        let source_dir = temp.path().join("source");
+        fs::create_dir_all(&source_dir)?;
+
+        let secret_file = source_dir.join("secret.conf");
+        fs::write(&secret_file, "password=hunter2")?;
+
+        let identity = age::x25519::Identity::generate();
+        let identity_file = temp.path().join("age-key.txt");
+        AgeEncryption::save_identity(&identity_file, &identity, None)?;
+
+        let repo = Repository::init(&repo_dir, "test-host")?;
+        let mut categories = CategoryManager::load(&repo)?;
+        categories.create(
+            "secrets",
+            None,
+            vec![source_dir.to_string_lossy().to_string()],
+        )?;
+        categories.save()?;
+
+        let encryption = EncryptionConfig {
+            enabled: true,
+            public_key: Some(identity.to_public().to_string()),
+            identity_file: Some(identity_file),
+            ..Default::default()
+        };
+        let tracker = FileTracker::with_encryption(&repo, encryption);
+        tracker.add(&secret_file, "secrets", true)?;
+
+        let repo_file = repo_dir.join("secrets").join(
+            secret_file
+                .strip_prefix("/")
+                .expect("tempdir path is absolute"),
+        );
+        assert!(AgeEncryption::is_encrypted(&repo_file));
+        assert!(!String::from_utf8_lossy(&fs::read(&repo_file)?).contains("hunter2"));
+
+        fs::remove_file(&secret_file)?;
+        tracker.restore_file(&secret_file)?;
+        assert_eq!(fs::read_to_string(&secret_file)?, "password=hunter2");
+
+        Ok(())
+    }
+
+    #[test]
+    fn files_are_restored_as_an_earlier_commit_stored_them() -> Result<()> {
+        let temp = tempdir()?;
+        let source_dir = temp.path().join("source");
+        fs::create_dir_all(&source_dir)?;
+        let plain = source_dir.join("app.conf");
+        let secret = source_dir.join("secret.conf");
+        let extra = source_dir.join("extra.conf");
+        fs::write(&plain, "port = 1")?;
+        fs::set_permissions(&plain, fs::Permissions::from_mode(0o640))?;
+        fs::write(&secret, "password=one")?;
+
+        let identity = age::x25519::Identity::generate();
+        let identity_file = temp.path().join("age-key.txt");
+        AgeEncryption::save_identity(&identity_file, &identity, None)?;
+
+        let repo = Repository::init(&temp.path().join("repo"), "test-host")?;
+        let mut categories = CategoryManager::load(&repo)?;
+        categories.create("app", None, vec![source_dir.to_string_lossy().to_string()])?;
+        categories.get_mut("app")?.encrypt = vec![secret.to_string_lossy().to_string()];
+        categories.save()?;
+
+        let encryption = EncryptionConfig {
+            enabled: true,
+            public_key: Some(identity.to_public().to_string()),
+            identity_file: Some(identity_file),
+            ..Default::default()
+        };
+        let tracker = FileTracker::with_encryption(&repo, encryption);
+        tracker.add(&source_dir, "app", false)?;
+        let mut metadata = MetadataStore::load(&repo)?;
+        metadata.update_from_system(&plain, false)?;
+        metadata.save()?;
+        repo.commit_all("First version")?;
+        let first = repo.resolve_revision("HEAD")?;
+
+        fs::write(&plain, "port = 2")?;
+        fs::write(&secret, "password=two")?;
+        fs::write(&extra, "new")?;
+        tracker.add(&source_dir, "app", false)?;
+        repo.commit_all("Second version")?;
+        let second = repo.resolve_revision("HEAD")?;
+        assert_eq!(repo.resolve_revision("HEAD~1")?.id, first.id);
+        assert_eq!(repo.resolve_revision("2999-01-01")?.id, second.id);
+        assert!(repo.resolve_revision("2000-01-01").is_err());
+
+        // Encrypted copies are compared by their plaintext
+        assert!(tracker
+            .diff_file_at(&plain, first.id)?
+            .contains("+port = 2"));
+        assert!(tracker
+            .diff_file_at(&secret, first.id)?
+            .contains("+password=two"));
+        assert_eq!(tracker.diff_file_at(&plain, second.id)?, "");
+        assert!(tracker
+            .diff_file_at(&extra, first.id)?
+            .starts_with("File only exists in system"));
+
+        assert_eq!(
+            tracker.list_files_at(first.id, None)?,
+            vec![plain.clone(), secret.clone()]
+        );
+
+        let meta = tracker.restore_file_at(&plain, first.id)?;
+        assert_eq!(fs::read_to_string(&plain)?, "port = 1");
+        assert_eq!(meta.map(|meta| meta.mode & 0o777), Some(0o640));
+        tracker.restore_file_at(&secret, first.id)?;
+        assert_eq!(fs::read_to_string(&secret)?, "password=one");
+        assert!(tracker.restore_file_at(&extra, first.id).is_err());
+
+        // History is read from the commit, the checkout stays where it was
+        assert_eq!(repo.resolve_revision("HEAD")?.id, second.id);
+        let stored = repo
+            .path()
+            .join("app")
+            .join(plain.strip_prefix("/").expect("tempdir path is absolute"));
+        assert_eq!(fs::read_to_string(stored)?, "port = 2");
+
+        Ok(())
+    }
+
+    #[test]
+    fn large_encrypted_files_are_compared_and_restored_in_chunks() -> Result<()> {
+        let temp = tempdir()?;
+        let repo_dir = temp.path().join("repo");
+        let source_dir = temp.path().join("geoip");
+        fs::create_dir_all(&source_dir)?;
+
+        // Several age and comparison chunks, differing only at the very end
+        let content: Vec<u8> = (0..5 * CHUNK_SIZE as u32 + 123)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let db_file = source_dir.join("GeoLite2-City.mmdb");
+        fs::write(&db_file, &content)?;
+
+        let identity = age::x25519::Identity::generate();
+        let identity_file = temp.path().join("age-key.txt");
+        AgeEncryption::save_identity(&identity_file, &identity, None)?;
+
+        let repo = Repository::init(&repo_dir, "test-host")?;
+        let mut categories = CategoryManager::load(&repo)?;
+        categories.create(
+            "geoip",
+            None,
+            vec![source_dir.to_string_lossy().to_string()],
+        )?;
+        categories.save()?;
+
+        let encryption = EncryptionConfig {
+            enabled: true,
+            public_key: Some(identity.to_public().to_string()),
+            identity_file: Some(identity_file),
+            ..Default::default()
+        };
+        let tracker = FileTracker::with_encryption(&repo, encryption);
+        tracker.add(&db_file, "geoip", true)?;
+        assert!(tracker.status(None)?.is_empty());
+
+        let mut changed = content.clone();
+        *changed.last_mut().expect("content is not empty") ^= 1;
+        fs::write(&db_file, &changed)?;
+        assert_eq!(
+            tracker.status(None)?.get(&db_file),
+            Some(&FileStatus::Modified)
+        );
+
+        tracker.restore_file(&db_file)?;
+        assert_eq!(fs::read(&db_file)?, content);
+        assert_eq!(fs::read_dir(&source_dir)?.count(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn unchanged_encrypted_files_are_not_reported_or_reencrypted() -> Result<()> {
+        let temp = tempdir()?;
+        let repo_dir = temp.path().join("repo");
+        let source_dir = temp.path().join("source");
+        fs::create_dir_all(&source_dir)?;
+
+        let secret_file = source_dir.join("secret.conf");
+        fs::write(&secret_file, "password=hunter2")?;
+
+        let identity = age::x25519::Identity::generate();
+        let identity_file = temp.path().join("age-key.txt");
+        AgeEncryption::save_identity(&identity_file, &identity, None)?;
+
+        let repo = Repository::init(&repo_dir, "test-host")?;
+        let mut categories = CategoryManager::load(&repo)?;
+        let mut cat = Category::new("secrets");
+        cat.paths = vec![source_dir.to_string_lossy().to_string()];
+        cat.encrypt = vec![source_dir.to_string_lossy().to_string()];
+        categories.add(cat)?;
+        categories.save()?;
+
+        let encryption = EncryptionConfig {
+            enabled: true,
+            public_key: Some(identity.to_public().to_string()),
+            identity_file: Some(identity_file),
+            ..Default::default()
+        };
+        let tracker = FileTracker::with_encryption(&repo, encryption.clone());
+        let refreshed = tracker.refresh_all()?;
+        assert_eq!(refreshed.updated, vec![secret_file.clone()]);
+
+        let mut metadata = MetadataStore::load(&repo)?;
+        metadata.update_from_system(&secret_file, tracker.is_stored_encrypted(&secret_file))?;
+        metadata.save()?;
+
+        assert!(tracker.status(None)?.is_empty());
+        assert!(tracker.refresh_all()?.is_empty());
+        assert!(tracker.diff_file(&secret_file)?.is_empty());
+
+        // Without an identity the plaintext hash from the metadata is used
+        let without_identity = FileTracker::with_encryption(
+            &repo,
+            EncryptionConfig {
+                identity_file: Some(temp.path().join("missing-key.txt")),
+                ..encryption
+            },
+        );
+        assert!(without_identity.status(None)?.is_empty());
+
+        fs::write(&secret_file, "password=correct-horse")?;
+        assert_eq!(
+            tracker.status(None)?.get(&secret_file),
+            Some(&FileStatus::Modified)
+        );
+        assert!(tracker
+            .diff_file(&secret_file)?
+            .contains("+password=correct-horse"));
+        assert_eq!(
+            without_identity.status(None)?.get(&secret_file),
+            Some(&FileStatus::Modified)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn refresh_skips_unencrypted_files_with_secrets() -> Result<()> {
+        let temp = tempdir()?;
+        let repo_dir = temp.path().join("repo");
+        let source_dir = temp.path().join("app");
+        fs::create_dir_all(&source_dir)?;
+
+        let plain_file = source_dir.join("app.conf");
+        let secret_file = source_dir.join("db.conf");
+        let allowed_file = source_dir.join("example.conf");
+        fs::write(&plain_file, "listen = 8080")?;
+        fs::write(&secret_file, "password = hunter2")?;
+        fs::write(&allowed_file, "password = changeme")?;
+
+        let repo = Repository::init(&repo_dir, "test-host")?;
+        let mut categories = CategoryManager::load(&repo)?;
+        let mut cat = Category::new("app");
+        cat.paths = vec![source_dir.to_string_lossy().to_string()];
+        cat.allow_secrets = vec![allowed_file.to_string_lossy().to_string()];
+        categories.add(cat)?;
+        categories.save()?;
+
+        let tracker = FileTracker::with_encryption(&repo, EncryptionConfig::default());
+        let mut refreshed = tracker.refresh_all()?;
+        refreshed.updated.sort();
+
+        assert_eq!(refreshed.updated, vec![plain_file, allowed_file]);
+        assert_eq!(refreshed.blocked.len(), 1);
+        assert_eq!(refreshed.blocked[0].0, secret_file);
+        assert!(!repo_dir
+            .join("app")
+            .join(
+                secret_file
+                    .strip_prefix("/")
+                    .expect("tempdir path is absolute")
+            )
+            .exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn sensitive_paths_are_encrypted_or_rejected() -> Result<()> {
+        let temp = tempdir()?;
+        let repo_dir = temp.path().join("repo");
+        let source_dir = temp.path().join("vpn");
+        fs::create_dir_all(&source_dir)?;
+
+        let key_file = source_dir.join("wg0.key");
+        fs::write(&key_file, "private key material")?;
+
+        let repo = Repository::init(&repo_dir, "test-host")?;
+        let mut categories = CategoryManager::load(&repo)?;
+        categories.create("vpn", None, vec![source_dir.to_string_lossy().to_string()])?;
+        categories.save()?;
+
+        // Extend the built-in policy in the repo config
+        let config_path = repo_dir.join(".confect").join("config.toml");
+        let mut config = fs::read_to_string(&config_path)?;
+        config.push_str(&format!(
+            "\n[policy]\nencrypt = [\"{}/*.key\"]\n",
+            source_dir.display()
+        ));
+        fs::write(&config_path, config)?;
+
+        let tracker = FileTracker::with_encryption(&repo, EncryptionConfig::default());
+        assert!(tracker.add(&source_dir, "vpn", false).is_err());
+
+        let repo_file = repo_dir.join("vpn").join(
+            key_file
+                .strip_prefix("/")
+                .expect("tempdir path is absolute"),
+        );
+        assert!(!repo_file.exists());
+
+        let identity = age::x25519::Identity::generate();
+        let tracker = FileTracker::with_encryption(
+            &repo,
+            EncryptionConfig {
+                enabled: true,
+                public_key: Some(identity.to_public().to_string()),
+                ..Default::default()
+            },
+        );
+        tracker.add(&source_dir, "vpn", false)?;
+        assert!(AgeEncryption::is_encrypted(&repo_file));
+
+        Ok(())
+    }
+
+    #[test]
+    fn stored_files_are_encrypted_once_they_become_sensitive() -> Result<()> {
+        let temp = tempdir()?;
+        let repo_dir = temp.path().join("repo");
+        let source_dir = temp.path().join("vpn");
+        fs::create_dir_all(&source_dir)?;
+
+        let conf_file = source_dir.join("wg0.conf");
+        fs::write(&conf_file, "ListenPort = 51820")?;
+
+        let repo = Repository::init(&repo_dir, "test-host")?;
+        let mut categories = CategoryManager::load(&repo)?;
+        categories.create("vpn", None, vec![source_dir.to_string_lossy().to_string()])?;
+        categories.save()?;
+
+        let identity = age::x25519::Identity::generate();
+        let identity_file = temp.path().join("age-key.txt");
+        AgeEncryption::save_identity(&identity_file, &identity, None)?;
+        let encryption = EncryptionConfig {
+            enabled: true,
+            public_key: Some(identity.to_public().to_string()),
+            identity_file: Some(identity_file),
+            ..Default::default()
+        };
+        let tracker = FileTracker::with_encryption(&repo, encryption.clone());
+        tracker.add(&conf_file, "vpn", false)?;
+
+        let repo_file = repo_dir.join("vpn").join(
+            conf_file
+                .strip_prefix("/")
+                .expect("tempdir path is absolute"),
+        );
+        assert!(!AgeEncryption::is_encrypted(&repo_file));
+
+        // The content is unchanged, but the policy now covers the file
+        let config_path = repo_dir.join(".confect").join("config.toml");
+        let mut config = fs::read_to_string(&config_path)?;
+        config.push_str(&format!(
+            "\n[policy]\nencrypt = [\"{}/*.conf\"]\n",
+            source_dir.display()
+        ));
+        fs::write(&config_path, config)?;
+
+        let tracker = FileTracker::with_encryption(&repo, encryption);
+        assert_eq!(tracker.refresh_all()?.updated, vec![conf_file.clone()]);
+        assert!(AgeEncryption::is_encrypted(&repo_file));
+        assert!(tracker.refresh_all()?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn passphrase_categories_need_no_identity() -> Result<()> {
+        let temp = tempdir()?;
+        let repo_dir = temp.path().join("repo");
+        let source_dir = temp.path().join("bootstrap");
+        fs::create_dir_all(&source_dir)?;
+
+        let secret_file = source_dir.join("token");
+        fs::write(&secret_file, "bootstrap-token")?;
+
+        let repo = Repository::init(&repo_dir, "test-host")?;
+        let mut categories = CategoryManager::load(&repo)?;
+        let mut cat = Category::new("bootstrap");
+        cat.paths = vec![source_dir.to_string_lossy().to_string()];
+        cat.passphrase = true;
+        categories.add(cat)?;
+        categories.save()?;
+
+        // No public key or identity: a fresh machine only knows the passphrase
+        let encryption = EncryptionConfig {
+            enabled: true,
+            identity_file: Some(temp.path().join("missing-key.txt")),
+            ..Default::default()
+        };
+        let tracker = FileTracker::with_encryption(&repo, encryption.clone());
+        let _ = tracker
+            .passphrase
+            .set(Some(SecretString::new("correct horse".to_string())));
+        tracker.add(&secret_file, "bootstrap", true)?;
+
+        let repo_file = repo_dir.join("bootstrap").join(
+            secret_file
+                .strip_prefix("/")
+                .expect("tempdir path is absolute"),
+        );
+        assert!(AgeEncryption::is_encrypted(&repo_file));
+
+        fs::remove_file(&secret_file)?;
+        let tracker = FileTracker::with_encryption(&repo, encryption);
+        let _ = tracker
+            .passphrase
+            .set(Some(SecretString::new("correct horse".to_string())));
+        tracker.restore_file(&secret_file)?;
+        assert_eq!(fs::read_to_string(&secret_file)?, "bootstrap-token");
+
+        Ok(())
+    }
+
+    #[test]
+    fn category_recipients_replace_team_recipients() -> Result<()> {
+        let temp = tempdir()?;
+        let repo_dir = temp.path().join("repo");
+        let db_dir = temp.path().join("db");
+        let ssh_dir = temp.path().join("ssh");
+        fs::create_dir_all(&db_dir)?;
+        fs::create_dir_all(&ssh_dir)?;
+
+        let db_file = db_dir.join("db.conf");
+        let ssh_file = ssh_dir.join("ssh.conf");
+        fs::write(&db_file, "db secret")?;
+        fs::write(&ssh_file, "ssh secret")?;
+
+        let mut identity_files = Vec::new();
+        let mut public_keys = Vec::new();
+        for name in ["local", "admin", "dba"] {
+            let identity = age::x25519::Identity::generate();
+            let path = temp.path().join(format!("{}.txt", name));
+            AgeEncryption::save_identity(&path, &identity, None)?;
+            identity_files.push(path);
+            public_keys.push(identity.to_public().to_string());
+        }
+
+        let repo = Repository::init(&repo_dir, "test-host")?;
+        fs::write(
+            AgeEncryption::team_recipients_path(&repo_dir),
+            format!("# admins\n{}\n", public_keys[1]),
+        )?;
+
+        let mut categories = CategoryManager::load(&repo)?;
+        categories.create("db", None, vec![db_dir.to_string_lossy().to_string()])?;
+        categories.get_mut("db")?.recipients = vec![public_keys[2].clone()];
+        categories.create("ssh", None, vec![ssh_dir.to_string_lossy().to_string()])?;
+        categories.save()?;
+
+        let tracker_with = |identity_file: &Path| {
+            FileTracker::with_encryption(
+                &repo,
+                EncryptionConfig {
+                    enabled: true,
+                    public_key: Some(public_keys[0].clone()),
+                    identity_file: Some(identity_file.to_path_buf()),
+                    ..Default::default()
+                },
+            )
+        };
+
+        let local = tracker_with(&identity_files[0]);
+        local.add(&db_file, "db", true)?;
+        local.add(&ssh_file, "ssh", true)?;
+
+        let admin = tracker_with(&identity_files[1]);
+        let dba = tracker_with(&identity_files[2]);
+
+        assert!(admin.restore_file(&db_file).is_err());
+        dba.restore_file(&db_file)?;
+        local.restore_file(&db_file)?;
+        assert_eq!(fs::read_to_string(&db_file)?, "db secret");
+
+        admin.restore_file(&ssh_file)?;
+        assert!(dba.restore_file(&ssh_file).is_err());
+        assert_eq!(fs::read_to_string(&ssh_file)?, "ssh secret");
+
+        Ok(())
+    }
+
+    #[test]
+    fn rekey_reencrypts_to_new_team_recipients() -> Result<()> {
+        let temp = tempdir()?;
+        let repo_dir = temp.path().join("repo");
+        let source_dir = temp.path().join("source");
+        fs::create_dir_all(&source_dir)?;
+
+        let secret_file = source_dir.join("secret.conf");
+        fs::write(&secret_file, "token=abc")?;
+
+        let local = age::x25519::Identity::generate();
+        let local_file = temp.path().join("local.txt");
+        AgeEncryption::save_identity(&local_file, &local, None)?;
+
+        let newcomer = age::x25519::Identity::generate();
+        let newcomer_file = temp.path().join("newcomer.txt");
+        AgeEncryption::save_identity(&newcomer_file, &newcomer, None)?;
+
+        let repo = Repository::init(&repo_dir, "test-host")?;
+        let mut categories = CategoryManager::load(&repo)?;
+        categories.create(
+            "secrets",
+            None,
+            vec![source_dir.to_string_lossy().to_string()],
+        )?;
+        categories.save()?;
+
+        let config_for = |identity_file: &Path| EncryptionConfig {
+            enabled: true,
+            public_key: Some(local.to_public().to_string()),
+            identity_file: Some(identity_file.to_path_buf()),
+            ..Default::default()
+        };
+        let tracker = FileTracker::with_encryption(&repo, config_for(&local_file));
+        tracker.add(&secret_file, "secrets", true)?;
+
+        let other = FileTracker::with_encryption(&repo, config_for(&newcomer_file));
+        assert!(other.restore_file(&secret_file).is_err());
+
+        fs::write(
+            AgeEncryption::team_recipients_path(&repo_dir),
+            format!("{}\n", newcomer.to_public()),
+        )?;
+        let result = tracker.rekey(None)?;
+        assert_eq!(result.rekeyed, vec![secret_file.clone()]);
+        assert!(result.failed.is_empty());
+
+        fs::remove_file(&secret_file)?;
+        other.restore_file(&secret_file)?;
+        assert_eq!(fs::read_to_string(&secret_file)?, "token=abc");
+
+        Ok(())
+    }
+
+    #[test]
+    fn rekey_reports_indexes_it_cannot_decrypt() -> Result<()> {
+        let temp = tempdir()?;
+        let repo_dir = temp.path().join("repo");
+        let secrets_dir = temp.path().join("secrets");
+        let ssh_dir = temp.path().join("ssh");
+        fs::create_dir_all(&secrets_dir)?;
+        fs::create_dir_all(&ssh_dir)?;
+        let secret_file = secrets_dir.join("secret.conf");
+        let key_file = ssh_dir.join("ssh_host_ed25519_key");
+        fs::write(&secret_file, "token=abc")?;
+        fs::write(&key_file, "private key material")?;
+
+        let identity = age::x25519::Identity::generate();
+        let identity_file = temp.path().join("age-key.txt");
+        AgeEncryption::save_identity(&identity_file, &identity, None)?;
+
+        let repo = Repository::init(&repo_dir, "test-host")?;
+        let mut categories = CategoryManager::load(&repo)?;
+        categories.create(
+            "secrets",
+            None,
+            vec![secrets_dir.to_string_lossy().to_string()],
+        )?;
+        let mut cat = Category::new("ssh");
+        cat.paths = vec![ssh_dir.to_string_lossy().to_string()];
+        cat.hide_paths = true;
+        categories.add(cat)?;
+        categories.save()?;
+
+        let tracker = FileTracker::with_encryption(
+            &repo,
+            EncryptionConfig {
+                enabled: true,
+                public_key: Some(identity.to_public().to_string()),
+                identity_file: Some(identity_file),
+                ..Default::default()
+            },
+        );
+        tracker.add(&secret_file, "secrets", true)?;
+        tracker.add(&key_file, "ssh", true)?;
+
+        // An index only someone else can read
+        let stranger = age::x25519::Identity::generate();
+        let index_path = PathIndex::path(&repo.state_dir(), "ssh");
+        fs::write(
+            &index_path,
+            AgeEncryption::new(vec![stranger.to_public().to_string()])?.encrypt(b"[files]\n")?,
+        )?;
+
+        let result = tracker.rekey(None)?;
+        assert_eq!(result.rekeyed, vec![secret_file]);
+        assert_eq!(result.failed.len(), 1);
+        assert_eq!(result.failed[0].0, index_path);
+
+        Ok(())
+    }
+
+    #[test]
+    fn placeholder_values_are_kept_in_the_vault() -> Result<()> {
+        let temp = tempdir()?;
+        let repo_dir = temp.path().join("repo");
+        let source_dir = temp.path().join("app");
+        fs::create_dir_all(&source_dir)?;
+
+        let env_file = source_dir.join(".env");
+        fs::write(&env_file, "HOST=db.internal\nDB_PASSWORD=hunter2\n")?;
+
+        let identity = age::x25519::Identity::generate();
+        let identity_file = temp.path().join("age-key.txt");
+        AgeEncryption::save_identity(&identity_file, &identity, None)?;
+
+        let repo = Repository::init(&repo_dir, "test-host")?;
+        let mut categories = CategoryManager::load(&repo)?;
+        let mut cat = Category::new("app");
+        cat.paths = vec![source_dir.to_string_lossy().to_string()];
+        cat.placeholders = vec![PlaceholderRule {
+            path: env_file.to_string_lossy().to_string(),
+            pattern: "^DB_PASSWORD=(.*)$".to_string(),
+            name: "db-password".to_string(),
+        }];
+        categories.add(cat)?;
+        categories.save()?;
+
+        let encryption = EncryptionConfig {
+            enabled: true,
+            public_key: Some(identity.to_public().to_string()),
+            identity_file: Some(identity_file),
+            ..Default::default()
+        };
+        let tracker = FileTracker::with_encryption(&repo, encryption.clone());
+        let refreshed = tracker.refresh_all()?;
+        assert_eq!(refreshed.updated, vec![env_file.clone()]);
+        assert!(refreshed.blocked.is_empty());
+
+        let repo_file = repo_dir.join("app").join(
+            env_file
+                .strip_prefix("/")
+                .expect("tempdir path is absolute"),
+        );
+        assert_eq!(
+            fs::read_to_string(&repo_file)?,
+            "HOST=db.internal\nDB_PASSWORD={{ secret \"db-password\" }}\n"
+        );
+        assert!(AgeEncryption::is_encrypted(&SecretVault::path(
+            &repo.state_dir()
+        )));
+
+        // A fresh tracker reads the value back from the vault
+        let tracker = FileTracker::with_encryption(&repo, encryption);
+        assert!(tracker.status(None)?.is_empty());
+
+        fs::remove_file(&env_file)?;
+        tracker.restore_file(&env_file)?;
+        assert_eq!(
+            fs::read_to_string(&env_file)?,
+            "HOST=db.internal\nDB_PASSWORD=hunter2\n"
+        );
+
+        fs::write(&env_file, "HOST=db.internal\nDB_PASSWORD=changed\n")?;
+        assert_eq!(
+            tracker.status(None)?.get(&env_file),
+            Some(&FileStatus::Modified)
+        );
+        let diff = tracker.diff_file(&env_file)?;
+        assert_eq!(diff, "Secret values differ: db-password");
+
+        Ok(())
+    }
+
+    #[test]
+    fn placeholder_names_are_scoped_to_their_file() -> Result<()> {
+        let temp = tempdir()?;
+        let repo_dir = temp.path().join("repo");
+        let source_dir = temp.path().join("app");
+        fs::create_dir_all(&source_dir)?;
+
+        let web_env = source_dir.join("web.env");
+        let worker_env = source_dir.join("worker.env");
+        fs::write(&web_env, "DB_PASSWORD=hunter2\n")?;
+        fs::write(&worker_env, "DB_PASSWORD=correct-horse\n")?;
+
+        let identity = age::x25519::Identity::generate();
+        let identity_file = temp.path().join("age-key.txt");
+        AgeEncryption::save_identity(&identity_file, &identity, None)?;
+
+        let repo = Repository::init(&repo_dir, "test-host")?;
+        let mut categories = CategoryManager::load(&repo)?;
+        let mut cat = Category::new("app");
+        cat.paths = vec![source_dir.to_string_lossy().to_string()];
+        cat.placeholders = [&web_env, &worker_env]
+            .iter()
+            .map(|path| PlaceholderRule {
+                path: path.to_string_lossy().to_string(),
+                pattern: "^DB_PASSWORD=(.*)$".to_string(),
+                name: "db-password".to_string(),
+            })
+            .collect();
+        categories.add(cat)?;
+        categories.save()?;
+
+        let encryption = EncryptionConfig {
+            enabled: true,
+            public_key: Some(identity.to_public().to_string()),
+            identity_file: Some(identity_file),
+            ..Default::default()
+        };
+        let tracker = FileTracker::with_encryption(&repo, encryption.clone());
+        assert_eq!(tracker.refresh_all()?.updated.len(), 2);
+
+        // Neither value overwrote the other
+        let tracker = FileTracker::with_encryption(&repo, encryption);
+        assert!(tracker.status(None)?.is_empty());
+        assert!(tracker.refresh_all()?.is_empty());
+
+        fs::remove_file(&web_env)?;
+        fs::remove_file(&worker_env)?;
+        tracker.restore_file(&web_env)?;
+        tracker.restore_file(&worker_env)?;
+        assert_eq!(fs::read_to_string(&web_env)?, "DB_PASSWORD=hunter2\n");
+        assert_eq!(
+            fs::read_to_string(&worker_env)?,
+            "DB_PASSWORD=correct-horse\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn hidden_paths_are_resolved_through_the_encrypted_index() -> Result<()> {
+        let temp = tempdir()?;
+        let repo_dir = temp.path().join("repo");
+        let source_dir = temp.path().join("ssh");
+        fs::create_dir_all(&source_dir)?;
+
+        let key_file = source_dir.join("ssh_host_ed25519_key");
+        let config_file = source_dir.join("sshd_config");
+        fs::write(&key_file, "private key material")?;
+        fs::write(&config_file, "PermitRootLogin no")?;
+
+        let identity = age::x25519::Identity::generate();
+        let identity_file = temp.path().join("age-key.txt");
+        AgeEncryption::save_identity(&identity_file, &identity, None)?;
+
+        let repo = Repository::init(&repo_dir, "test-host")?;
+        let mut categories = CategoryManager::load(&repo)?;
+        let mut cat = Category::new("ssh");
+        cat.paths = vec![source_dir.to_string_lossy().to_string()];
+        cat.encrypt = vec![key_file.to_string_lossy().to_string()];
+        cat.hide_paths = true;
+        categories.add(cat)?;
+        categories.save()?;
+
+        let encryption = EncryptionConfig {
+            enabled: true,
+            public_key: Some(identity.to_public().to_string()),
+            identity_file: Some(identity_file),
+            ..Default::default()
+        };
+        let tracker = FileTracker::with_encryption(&repo, encryption.clone());
+        tracker.refresh_all()?;
+
+        let stored: Vec<_> = WalkDir::new(repo_dir.join("ssh"))
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file())
+            .map(|entry| entry.into_path())
+            .collect();
+        assert_eq!(stored.len(), 2);
+        assert!(stored
+            .iter()
+            .all(|path| path.file_name() != key_file.file_name()));
+        assert!(stored.iter().any(|path| path.ends_with(
+            config_file
+                .strip_prefix("/")
+                .expect("tempdir path is absolute")
+        )));
+
+        let index_path = PathIndex::path(&repo.state_dir(), "ssh");
+        assert!(AgeEncryption::is_encrypted(&index_path));
+        assert!(!String::from_utf8_lossy(&fs::read(&index_path)?).contains("ssh_host"));
+
+        // A fresh tracker resolves hidden files through the index
+        let tracker = FileTracker::with_encryption(&repo, encryption.clone());
+        assert!(tracker.status(None)?.is_empty());
+        let mut files = tracker.list_files_in_category("ssh")?;
+        files.sort();
+        assert_eq!(files, vec![key_file.clone(), config_file.clone()]);
+
+        fs::remove_file(&key_file)?;
+        tracker.restore_file(&key_file)?;
+        assert_eq!(fs::read_to_string(&key_file)?, "private key material");
+
+        // Turning the setting off moves files back to their real paths
+        let mut categories = CategoryManager::load(&repo)?;
+        categories.get_mut("ssh")?.hide_paths = false;
+        categories.save()?;
+
+        let tracker = FileTracker::with_encryption(&repo, encryption);
+        assert_eq!(tracker.refresh_all()?.updated, vec![key_file.clone()]);
+        assert!(repo_dir
+            .join("ssh")
+            .join(
+                key_file
+                    .strip_prefix("/")
+                    .expect("tempdir path is absolute")
+            )
+            .exists());
+        assert!(!index_path.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn hidden_files_keep_their_metadata_in_the_encrypted_index() -> Result<()> {
+        let temp = tempdir()?;
+        let repo_dir = temp.path().join("repo");
+        let source_dir = temp.path().join("ssh");
+        fs::create_dir_all(&source_dir)?;
+
+        let key_file = source_dir.join("ssh_host_ed25519_key");
+        let config_file = source_dir.join("sshd_config");
+        fs::write(&key_file, "private key material")?;
+        fs::set_permissions(&key_file, fs::Permissions::from_mode(0o600))?;
+        fs::write(&config_file, "PermitRootLogin no")?;
+
+        let identity = age::x25519::Identity::generate();
+        let identity_file = temp.path().join("age-key.txt");
+        AgeEncryption::save_identity(&identity_file, &identity, None)?;
+
+        let repo = Repository::init(&repo_dir, "test-host")?;
+        let mut categories = CategoryManager::load(&repo)?;
+        let mut cat = Category::new("ssh");
+        cat.paths = vec![source_dir.to_string_lossy().to_string()];
+        cat.encrypt = vec![key_file.to_string_lossy().to_string()];
+        cat.hide_paths = true;
+        categories.add(cat)?;
+        categories.save()?;
+
+        let encryption = EncryptionConfig {
+            enabled: true,
+            public_key: Some(identity.to_public().to_string()),
+            identity_file: Some(identity_file),
+            ..Default::default()
+        };
+        let tracker = FileTracker::with_encryption(&repo, encryption.clone());
+        tracker.update_metadata(&tracker.refresh_all()?)?;
+
+        // The plaintext metadata only names the file stored at its real path
+        let plaintext = fs::read_to_string(repo.state_dir().join("metadata.toml"))?;
+        assert!(!plaintext.contains("ssh_host"));
+        assert!(plaintext.contains("sshd_config"));
+
+        let tracker = FileTracker::with_encryption(&repo, encryption);
+        let meta = tracker
+            .stored_metadata(&key_file)?
+            .expect("metadata is kept");
+        assert_eq!(meta.mode & 0o777, 0o600);
+        assert!(meta.content_hash.is_some());
+
+        fs::set_permissions(&key_file, fs::Permissions::from_mode(0o644))?;
+        meta.apply_to(&key_file)?;
+        assert_eq!(fs::metadata(&key_file)?.permissions().mode() & 0o777, 0o600);
+
+        Ok(())
+    }
+
+    #[test]
+    fn host_branch_overlays_shared_files_from_main() -> Result<()> {
+        let temp = tempdir()?;
+        let repo_dir = temp.path().join("repo");
+        let source_dir = temp.path().join("shell");
+        fs::create_dir_all(&source_dir)?;
+
+        let bashrc = source_dir.join("bashrc");
+        let vimrc = source_dir.join("vimrc");
+        fs::write(&vimrc, "set number")?;
+
+        let repo = Repository::init(&repo_dir, "test-host")?;
+        let mut categories = CategoryManager::load(&repo)?;
+        categories.create(
+            "shell",
+            None,
+            vec![source_dir.to_string_lossy().to_string()],
+        )?;
+        categories.save()?;
+        let cat = categories.get("shell")?;
+
+        // Shared copies on main, with the host overriding vimrc
+        let shared = |path: &Path| repo_dir.join(cat.repo_path_for(path));
+        fs::create_dir_all(shared(&bashrc).parent().expect("has parent"))?;
+        fs::write(shared(&bashrc), "alias ll='ls -l'")?;
+        fs::write(shared(&vimrc), "set nocompatible")?;
+        repo.commit_all("Shared configs")?;
+        let git = git2::Repository::open(&repo_dir)?;
+        let shared_commit = git.head()?.target().expect("head is direct");
+        git.reference("refs/heads/main", shared_commit, true, "test")?;
+        fs::remove_file(shared(&bashrc))?;
+        fs::write(shared(&vimrc), "set number")?;
+        repo.commit_all("Host configs")?;
+
+        let tracker = FileTracker::with_encryption(&repo, EncryptionConfig::default());
+        let mut files = tracker.list_files_in_category("shell")?;
+        files.sort();
+        assert_eq!(files, vec![bashrc.clone(), vimrc.clone()]);
+        let status = tracker.status(None)?;
+        assert_eq!(status.get(&bashrc), Some(&FileStatus::Deleted));
+        assert_eq!(status.len(), 1);
+
+        tracker.restore_file(&bashrc)?;
+        tracker.restore_file(&vimrc)?;
+        assert_eq!(fs::read_to_string(&bashrc)?, "alias ll='ls -l'");
+        assert_eq!(fs::read_to_string(&vimrc)?, "set number");
+        assert!(tracker.status(None)?.is_empty());
+
+        // Unchanged shared files get no host copy, changed ones do
+        assert!(tracker.refresh_all()?.is_empty());
+        fs::write(&bashrc, "alias ll='ls -la'")?;
+        assert_eq!(
+            tracker.status(None)?.get(&bashrc),
+            Some(&FileStatus::Modified)
+        );
+        assert_eq!(tracker.refresh_all()?.updated, vec![bashrc.clone()]);
+        assert!(shared(&bashrc).exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn group_layers_sit_between_the_host_and_main() -> Result<()> {
+        let temp = tempdir()?;
+        let repo_dir = temp.path().join("repo");
+        let source_dir = temp.path().join("nginx");
+        fs::create_dir_all(&source_dir)?;
+
+        let nginx_conf = source_dir.join("nginx.conf");
+        let mime_types = source_dir.join("mime.types");
+        let site_conf = source_dir.join("site.conf");
+        fs::write(&site_conf, "host")?;
+
+        let repo = Repository::init(&repo_dir, "web-01")?;
+        let mut categories = CategoryManager::load(&repo)?;
+        categories.create(
+            "nginx",
+            None,
+            vec![source_dir.to_string_lossy().to_string()],
+        )?;
+        categories.save()?;
+        let cat = categories.get("nginx")?;
+        let mut config = RepoConfig::load(&repo_dir)?;
+        config
+            .hosts
+            .list
+            .get_mut("web-01")
+            .expect("host exists")
+            .groups = vec!["web".to_string(), "prod".to_string()];
+        config.save(&repo_dir)?;
+
+        let tracker = FileTracker::with_encryption(&repo, EncryptionConfig::default());
+        tracker.add(&site_conf, "nginx", false)?;
+        repo.commit_all("Add site")?;
+
+        // Each layer overrides the ones after it
+        let git = git2::Repository::open(&repo_dir)?;
+        let main = git.revparse_single(BASE_BRANCH)?.peel_to_commit()?;
+        git.branch("group/web", &main, false)?;
+        git.branch("group/prod", &main, false)?;
+        let stored = |path: &Path, content: &str| {
+            (
+                cat.layer_path_for(path),
+                BranchFile::Blob {
+                    content: content.as_bytes().to_vec(),
+                    executable: false,
+                },
+            )
+        };
+        repo.commit_to_branch(
+            BASE_BRANCH,
+            &[stored(&nginx_conf, "main"), stored(&mime_types, "main")],
+            "Shared",
+        )?;
+        repo.commit_to_branch(
+            "group/prod",
+            &[stored(&nginx_conf, "prod"), stored(&mime_types, "prod")],
+            "Prod",
+        )?;
+        repo.commit_to_branch(
+            "group/web",
+            &[stored(&nginx_conf, "web"), stored(&site_conf, "web")],
+            "Web",
+        )?;
+
+        let tracker = FileTracker::with_encryption(&repo, EncryptionConfig::default());
+        let mut files = tracker.list_files_in_category("nginx")?;
+        files.sort();
+        assert_eq!(
+            files,
+            vec![mime_types.clone(), nginx_conf.clone(), site_conf.clone()]
+        );
+
+        tracker.restore_file(&nginx_conf)?;
+        tracker.restore_file(&mime_types)?;
+        tracker.restore_file(&site_conf)?;
+        assert_eq!(fs::read_to_string(&nginx_conf)?, "web");
+        assert_eq!(fs::read_to_string(&mime_types)?, "prod");
+        assert_eq!(fs::read_to_string(&site_conf)?, "host");
+        assert!(tracker.status(None)?.is_empty());
+
+        let layers = |path: &Path| -> Result<Vec<String>> {
+            Ok(tracker
+                .which(path)?
+                .into_iter()
+                .map(|copy| copy.layer)
+                .collect())
+        };
+        assert_eq!(
+            layers(&nginx_conf)?,
+            vec!["group/web", "group/prod", "main"]
+        );
+        assert_eq!(layers(&site_conf)?, vec!["host/web-01", "group/web"]);
+        assert_eq!(
+            tracker.which(&site_conf)?[0].path,
+            cat.layer_path_for(&site_conf)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn shared_files_move_to_main_with_their_metadata() -> Result<()> {
+        let temp = tempdir()?;
+        let repo_dir = temp.path().join("repo");
+        let source_dir = temp.path().join("shell");
+        fs::create_dir_all(&source_dir)?;
+
+        let bashrc = source_dir.join("bashrc");
+        fs::write(&bashrc, "alias ll='ls -l'")?;
+        fs::set_permissions(&bashrc, fs::Permissions::from_mode(0o600))?;
+
+        let repo = Repository::init(&repo_dir, "test-host")?;
+        let mut categories = CategoryManager::load(&repo)?;
+        categories.create(
+            "shell",
+            None,
+            vec![source_dir.to_string_lossy().to_string()],
+        )?;
+        categories.save()?;
+        let cat = categories.get("shell")?;
+
+        let tracker = FileTracker::with_encryption(&repo, EncryptionConfig::default());
+        tracker.add(&bashrc, "shell", false)?;
+        let mut metadata = MetadataStore::load(&repo)?;
+        metadata.update_from_system(&bashrc, false)?;
+        metadata.save()?;
+        repo.commit_all("Add bashrc")?;
+
+        let tracker = FileTracker::with_encryption(&repo, EncryptionConfig::default());
+        let relative = cat.repo_path_for(&bashrc);
+        assert_eq!(
+            tracker.share(&source_dir)?,
+            vec![(bashrc.clone(), relative.clone())]
+        );
+
+        assert_eq!(
+            repo.read_branch_file(BASE_BRANCH, &relative)?,
+            Some(BranchFile::Blob {
+                content: b"alias ll='ls -l'".to_vec(),
+                executable: false,
+            })
+        );
+        let shared_metadata = MetadataStore::load_branch(&repo, BASE_BRANCH)?;
+        assert_eq!(
+            shared_metadata.get(&bashrc).map(|meta| meta.mode),
+            metadata.get(&bashrc).map(|meta| meta.mode)
+        );
+
+        // The host now follows main, its own entry no longer overriding main's
+        assert!(!repo_dir.join(&relative).exists());
+        assert!(MetadataStore::load(&repo)?.get(&bashrc).is_none());
+        repo.commit_paths(
+            &[relative.as_path(), Path::new(".confect/metadata.toml")],
+            "Use shared bashrc",
+        )?;
+        assert!(!repo.has_changes()?);
+        assert!(tracker.status(None)?.is_empty());
+        assert!(matches!(
+            tracker.share(&bashrc),
+            Err(ConfectError::PathNotTracked(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn taking_the_system_file_replaces_a_conflicted_shared_copy() -> Result<()> {
+        let temp = tempdir()?;
+        let repo_dir = temp.path().join("repo");
+        let source_dir = temp.path().join("shell");
+        fs::create_dir_all(&source_dir)?;
+
+        let bashrc = source_dir.join("bashrc");
+        fs::write(&bashrc, "alias ll='ls -l'")?;
+
+        let repo = Repository::init_with_strategy(&repo_dir, "test-host", HostStrategy::Directory)?;
+        let mut categories = CategoryManager::load(&repo)?;
+        categories.create(
+            "shell",
+            None,
+            vec![source_dir.to_string_lossy().to_string()],
+        )?;
+        categories.save()?;
+        let cat = categories.get("shell")?;
+
+        let tracker = FileTracker::with_encryption(&repo, EncryptionConfig::default());
+        tracker.add(&bashrc, "shell", false)?;
+        tracker.share(&bashrc)?;
+
+        // A conflict in common is resolved in the common copy
+        let shared = Path::new(COMMON_DIR).join(cat.layer_path_for(&bashrc));
+        fs::write(
+            repo_dir.join(&shared),
+            "<<<<<<< ours\nalias ll='ls -l'\n=======\nalias ll='ls -al'\n>>>>>>> theirs\n",
+        )?;
+        fs::write(&bashrc, "alias ll='ls -la'")?;
+        tracker.store_system_copy(&bashrc, &shared)?;
+
+        assert_eq!(
+            fs::read_to_string(repo_dir.join(&shared))?,
+            "alias ll='ls -la'"
+        );
+        assert!(!repo_dir.join(cat.repo_path_for(&bashrc)).exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn directory_hosts_store_files_apart_and_share_through_common() -> Result<()> {
+        let temp = tempdir()?;
+        let repo_dir = temp.path().join("repo");
+        let source_dir = temp.path().join("shell");
+        fs::create_dir_all(&source_dir)?;
+
+        let bashrc = source_dir.join("bashrc");
+        fs::write(&bashrc, "alias ll='ls -l'")?;
+
+        let repo = Repository::init_with_strategy(&repo_dir, "test-host", HostStrategy::Directory)?;
+        let mut categories = CategoryManager::load(&repo)?;
+        categories.create(
+            "shell",
+            None,
+            vec![source_dir.to_string_lossy().to_string()],
+        )?;
+        categories.save()?;
+        let cat = categories.get("shell")?;
+
+        let tracker = FileTracker::with_encryption(&repo, EncryptionConfig::default());
+        tracker.add(&bashrc, "shell", false)?;
+        let mut metadata = MetadataStore::load(&repo)?;
+        metadata.update_from_system(&bashrc, false)?;
+        metadata.save()?;
+        repo.commit_all("Add bashrc")?;
+
+        let relative = Path::new("hosts/test-host").join(cat.layer_path_for(&bashrc));
+        assert_eq!(cat.repo_path_for(&bashrc), relative);
+        assert!(repo_dir.join(&relative).exists());
+        assert!(repo_dir
+            .join("hosts/test-host/.confect/metadata.toml")
+            .exists());
+
+        // Sharing moves the file and its metadata into common
+        let tracker = FileTracker::with_encryption(&repo, EncryptionConfig::default());
+        assert_eq!(
+            tracker.share(&bashrc)?,
+            vec![(bashrc.clone(), relative.clone())]
+        );
+        let common_file = repo_dir.join(COMMON_DIR).join(cat.layer_path_for(&bashrc));
+        assert_eq!(fs::read_to_string(&common_file)?, "alias ll='ls -l'");
+        assert!(!repo_dir.join(&relative).exists());
+        let common_metadata = MetadataStore::load_base(&repo)?.expect("common layer");
+        assert_eq!(
+            common_metadata.get(&bashrc).map(|meta| meta.mode),
+            metadata.get(&bashrc).map(|meta| meta.mode)
+        );
+        assert!(MetadataStore::load(&repo)?.get(&bashrc).is_none());
+        repo.commit_paths(
+            &[
+                relative.as_path(),
+                Path::new(COMMON_DIR),
+                Path::new("hosts/test-host/.confect/metadata.toml"),
+            ],
+            "Share",
+        )?;
+        assert!(!repo.has_changes()?);
+
+        // The host follows common until it changes the file
+        assert_eq!(
+            tracker.list_files_in_category("shell")?,
+            vec![bashrc.clone()]
+        );
+        assert!(tracker.status(None)?.is_empty());
+        fs::remove_file(&bashrc)?;
+        tracker.restore_file(&bashrc)?;
+        assert_eq!(fs::read_to_string(&bashrc)?, "alias ll='ls -l'");
+
+        fs::write(&bashrc, "alias ll='ls -la'")?;
+        assert_eq!(tracker.refresh_all()?.updated, vec![bashrc.clone()]);
+        assert_eq!(
+            fs::read_to_string(repo_dir.join(&relative))?,
+            "alias ll='ls -la'"
+        );
+        assert_eq!(fs::read_to_string(&common_file)?, "alias ll='ls -l'");
+
+        Ok(())
+    }
+
+    #[test]
+    fn host_branches_are_diffed_against_each_other_and_the_system() -> Result<()> {
+        let temp = tempdir()?;
+        let repo_dir = temp.path().join("repo");
+        let source_dir = temp.path().join("nginx");
+        fs::create_dir_all(&source_dir)?;
+
+        let nginx_conf = source_dir.join("nginx.conf");
+        let extra_conf = source_dir.join("extra.conf");
+        fs::write(&nginx_conf, "worker_processes 4;\n")?;
+
+        let mut repo = Repository::init(&repo_dir, "web-01")?;
+        let mut categories = CategoryManager::load(&repo)?;
+        categories.create(
+            "nginx",
+            None,
+            vec![source_dir.to_string_lossy().to_string()],
+        )?;
+        categories.save()?;
+        let cat = categories.get("nginx")?.clone();
+
+        let mut config = RepoConfig::load(&repo_dir)?;
+        config.hosts.list.insert(
+            "web-02".to_string(),
+            HostEntry {
+                branch: "host/web-02".to_string(),
+                groups: Vec::new(),
+            },
+        );
+        config.save(&repo_dir)?;
+        FileTracker::with_encryption(&repo, EncryptionConfig::default()).add(
+            &source_dir,
+            "nginx",
+            false,
+        )?;
+        repo.commit_all("Add nginx")?;
+
+        // web-02 runs more workers and has an extra file
+        let git = git2::Repository::open(&repo_dir)?;
+        git.branch("host/web-02", &git.head()?.peel_to_commit()?, false)?;
+        repo.switch_host("web-02")?;
+        fs::write(
+            repo_dir.join(cat.repo_path_for(&nginx_conf)),
+            "worker_processes 8;\n",
+        )?;
+        fs::write(repo_dir.join(cat.repo_path_for(&extra_conf)), "gzip on;\n")?;
+        repo.commit_all("Tune web-02")?;
+        repo.switch_host("web-01")?;
+
+        let tracker = FileTracker::with_encryption(&repo, EncryptionConfig::default());
+        let diffs = tracker.diff_between("web-01", "web-02", None, None)?;
+        assert_eq!(diffs.len(), 2);
+        assert_eq!(diffs[0], (extra_conf.clone(), "Only in web-02".to_string()));
+        assert_eq!(diffs[1].0, nginx_conf);
+        assert!(diffs[1].1.contains("-worker_processes 4;"));
+        assert!(diffs[1].1.contains("+worker_processes 8;"));
+
+        let diffs = tracker.diff_between("web-01", "web-02", Some("nginx"), Some(&nginx_conf))?;
+        assert_eq!(diffs.len(), 1);
+
+        let diffs = tracker.diff_host("web-02", None, Some(&nginx_conf))?;
+        assert_eq!(diffs.len(), 1);
+        assert!(diffs[0].1.contains("+worker_processes 4;"));
+
+        fs::write(&nginx_conf, "worker_processes 8;\n")?;
+        assert!(tracker
+            .diff_host("web-02", None, Some(&nginx_conf))?
+            .is_empty());
+        assert_eq!(tracker.diff_host("web-01", None, None)?.len(), 1);
+        assert!(matches!(
+            tracker.diff_host("web-03", None, None),
+            Err(ConfectError::HostNotFound(_))
+        ));
+
+        Ok(())
+    }
 
     #[test]
     fn refresh_all_adds_new_files_and_removes_deleted_files_in_tracked_directory() -> Result<()> {
@@ -513,7 +3722,7 @@ mod tests {
         )?;
         categories.save()?;
 
-        let tracker = FileTracker::new(&repo);
+        let tracker = FileTracker::with_encryption(&repo, EncryptionConfig::default());
         tracker.add(&source_dir, "configs", false)?;
 
         let nested_dir = source_dir.join("nested");
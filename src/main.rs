@@ -4,6 +4,7 @@ use console::style;
 
 use confect::cli::commands;
 use confect::cli::{Cli, Commands};
+use confect::core::HostBase;
 
 fn main() {
     if let Err(err) = run() {
@@ -21,8 +22,17 @@ fn run() -> Result<()> {
             system,
             remote,
             host,
+            strategy,
+            from_host,
+            from,
+            dry_run_restore,
         } => {
-            commands::run_init(path, system, remote, host)?;
+            let base = match (from_host, from) {
+                (Some(name), _) => Some(HostBase::Host(name)),
+                (None, Some(branch)) => Some(HostBase::Branch(branch)),
+                (None, None) => None,
+            };
+            commands::run_init(path, system, remote, host, strategy, base, dry_run_restore)?;
         }
 
         Commands::Add {
@@ -56,14 +66,31 @@ fn run() -> Result<()> {
             dry_run,
             force,
             backup,
+            at,
         } => {
-            commands::run_restore(category, file, dry_run, force, backup)?;
+            commands::run_restore(category, file, dry_run, force, backup, at)?;
         }
 
         Commands::Category(cmd) => {
             commands::run_category(cmd)?;
         }
 
+        Commands::Key(cmd) => {
+            commands::run_key(cmd)?;
+        }
+
+        Commands::Host(cmd) => {
+            commands::run_host(cmd)?;
+        }
+
+        Commands::Snapshot(cmd) => {
+            commands::run_snapshot(cmd)?;
+        }
+
+        Commands::Rekey { category, message } => {
+            commands::run_rekey(category, message)?;
+        }
+
         Commands::Info => {
             commands::run_info()?;
         }
@@ -72,12 +99,42 @@ fn run() -> Result<()> {
             setup_timer(&schedule, remove)?;
         }
 
-        Commands::Pull { restore } => {
-            pull_changes(restore)?;
+        Commands::Pull {
+            restore,
+            shared,
+            rebase,
+        } => {
+            pull_changes(restore, shared, rebase)?;
+        }
+
+        Commands::Resolve => {
+            commands::run_resolve()?;
+        }
+
+        Commands::Share { path, no_push } => {
+            commands::run_share(path, no_push)?;
+        }
+
+        Commands::Diff {
+            category,
+            file,
+            host,
+            between,
+        } => {
+            commands::run_diff(category, file, host, between)?;
+        }
+
+        Commands::Log {
+            path,
+            category,
+            patch,
+            max_count,
+        } => {
+            commands::run_log(path, category, patch, max_count)?;
         }
 
-        Commands::Diff { category, file } => {
-            commands::run_diff(category, file)?;
+        Commands::Which { path } => {
+            commands::run_which(path)?;
         }
 
         Commands::SelfUpdate { check } => {
@@ -160,21 +217,89 @@ WantedBy=timers.target
     Ok(())
 }
 
-fn pull_changes(restore: bool) -> Result<()> {
-    use confect::core::Repository;
+fn pull_changes(restore: bool, shared: bool, rebase: bool) -> Result<()> {
+    use confect::core::{Config, MergeOutcome, PullStrategy, Repository, BASE_BRANCH};
 
     let repo = Repository::open_default()?;
+    let strategy = if rebase {
+        PullStrategy::Rebase
+    } else {
+        Config::load_global()?.global.pull
+    };
+    let steps = 1 + usize::from(shared) + usize::from(restore);
+    let mut step = 0;
+    let mut next_step = || {
+        step += 1;
+        style(format!("[{}/{}]", step, steps)).bold().dim()
+    };
+
+    println!("{} Pulling from remote...", next_step());
+
+    match repo.pull("origin", strategy)? {
+        MergeOutcome::UpToDate => println!("{} Already up to date", style("✓").green()),
+        MergeOutcome::FastForward => println!("{} Pulled latest changes", style("✓").green()),
+        MergeOutcome::Merged => {
+            println!("{} Merged remote changes", style("✓").green())
+        }
+        MergeOutcome::Rebased => println!(
+            "{} Rebased local changes onto the remote",
+            style("✓").green()
+        ),
+        MergeOutcome::Conflicts(paths) => {
+            return report_conflicts(&repo, "Pulling", &paths);
+        }
+    }
 
-    println!("{} Pulling from remote...", style("[1/2]").bold().dim());
-
-    repo.pull("origin")?;
-
-    println!("{} Pulled latest changes", style("✓").green());
+    if shared {
+        println!("{} Merging {}...", next_step(), BASE_BRANCH);
+
+        match repo.merge_branch(BASE_BRANCH)? {
+            MergeOutcome::UpToDate => {
+                println!(
+                    "{} Already up to date with {}",
+                    style("✓").green(),
+                    BASE_BRANCH
+                );
+            }
+            MergeOutcome::FastForward | MergeOutcome::Merged | MergeOutcome::Rebased => {
+                println!("{} Merged {}", style("✓").green(), BASE_BRANCH);
+            }
+            MergeOutcome::Conflicts(paths) => {
+                return report_conflicts(&repo, &format!("Merging {}", BASE_BRANCH), &paths);
+            }
+        }
+    }
 
     if restore {
-        println!("{} Restoring files...", style("[2/2]").bold().dim());
-        commands::run_restore(None, None, false, true, true)?;
+        println!("{} Restoring files...", next_step());
+        commands::run_restore(None, None, false, true, true, None)?;
     }
 
     Ok(())
 }
+
+/// List the files a merge left conflicted by system path and fail the pull
+fn report_conflicts(
+    repo: &confect::core::Repository,
+    action: &str,
+    paths: &[std::path::PathBuf],
+) -> Result<()> {
+    use confect::error::ConfectError;
+    use confect::fs::FileTracker;
+
+    eprintln!(
+        "{} {} conflicts in {} file(s):",
+        style("✗").red(),
+        action,
+        paths.len()
+    );
+    let tracker = FileTracker::new(repo)?;
+    for path in paths {
+        match tracker.system_path_of(path).ok().flatten() {
+            Some(system_path) => eprintln!("  {} ({})", system_path.display(), path.display()),
+            None => eprintln!("  {}", path.display()),
+        }
+    }
+    eprintln!("  Run 'confect resolve' to choose the version to keep");
+    Err(ConfectError::UnresolvedConflicts(paths.len()).into())
+}